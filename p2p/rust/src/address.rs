@@ -0,0 +1,129 @@
+// address.rs - 地址分类与拨号顺序
+// 许多移动和宽带网络给用户分配没有 NAT 的公网 IPv6 地址，双方都有公网 IPv6 时直接连接最可靠，
+// 因此拨号时把公网 IPv6 地址排在最前；局域网和环回地址排在最后
+// UDP 映射比 TCP 容易打洞，对方同时提供 QUIC 和 TCP 地址时同一级别内先拨号 QUIC
+use libp2p::core::ConnectedPoint;
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+// 公网 IPv6 单播地址（2000::/3）
+pub fn is_global_ipv6(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xe000 == 0x2000
+}
+
+// 公网 IPv4 地址：排除私有、环回、链路本地、CGNAT（100.64.0.0/10）和文档地址
+pub fn is_global_ipv4(ip: &Ipv4Addr) -> bool {
+    let cgnat = ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64;
+    !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified() || ip.is_documentation() || cgnat)
+}
+
+pub fn is_global(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_global_ipv4(ip),
+        IpAddr::V6(ip) => is_global_ipv6(ip),
+    }
+}
+
+// 地址中的 IP，/dns 等地址为 None
+pub fn ip(addr: &Multiaddr) -> Option<IpAddr> {
+    match addr.iter().next()? {
+        Protocol::Ip4(ip) => Some(ip.into()),
+        Protocol::Ip6(ip) => Some(ip.into()),
+        _ => None,
+    }
+}
+
+// /ip4|ip6/<addr>/tcp|udp/<port> 开头的地址转换为 SocketAddr
+pub fn socket_addr(addr: &Multiaddr) -> Option<SocketAddr> {
+    let ip = ip(addr)?;
+    match addr.iter().nth(1)? {
+        Protocol::Tcp(port) | Protocol::Udp(port) => Some(SocketAddr::new(ip, port)),
+        _ => None,
+    }
+}
+
+// 是否为公网 IPv6 地址
+pub fn is_global_ipv6_addr(addr: &Multiaddr) -> bool {
+    matches!(ip(addr), Some(IpAddr::V6(ip)) if is_global_ipv6(&ip))
+}
+
+// 是否为 QUIC 地址（/udp/<port>/quic-v1）
+pub fn is_quic_address(addr: &Multiaddr) -> bool {
+    addr.iter().any(|protocol| matches!(protocol, Protocol::QuicV1))
+}
+
+// 连接使用的传输方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    Tcp,
+    Quic,
+    // 经 TURN 服务器或中继节点（/p2p-circuit）转发
+    Relayed,
+    Other,
+}
+
+impl fmt::Display for TransportKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TransportKind::Tcp => "tcp",
+            TransportKind::Quic => "quic",
+            TransportKind::Relayed => "relayed",
+            TransportKind::Other => "other",
+        };
+        f.write_str(name)
+    }
+}
+
+impl TransportKind {
+    // 是否经第三方转发，其余为直接连接
+    pub fn is_relayed(self) -> bool {
+        self == TransportKind::Relayed
+    }
+}
+
+// 地址对应的传输方式
+pub fn transport_kind(addr: &Multiaddr) -> TransportKind {
+    if is_relayed(addr) {
+        TransportKind::Relayed
+    } else if is_quic_address(addr) {
+        TransportKind::Quic
+    } else if addr.iter().any(|protocol| matches!(protocol, Protocol::Tcp(_))) {
+        TransportKind::Tcp
+    } else {
+        TransportKind::Other
+    }
+}
+
+// 连接使用的传输方式：出站连接看拨号地址，入站连接看接受连接的本地地址
+pub fn connection_transport(endpoint: &ConnectedPoint) -> TransportKind {
+    match endpoint {
+        ConnectedPoint::Dialer { address, .. } => transport_kind(address),
+        ConnectedPoint::Listener { local_addr, .. } => transport_kind(local_addr),
+    }
+}
+
+// 是否为经转发的地址：TURN 中继地址或 /p2p-circuit 地址
+pub fn is_relayed(addr: &Multiaddr) -> bool {
+    crate::turn_transport::is_relayed_address(addr) || crate::relay::is_circuit_address(addr)
+}
+
+// 按拨号优先级排序（稳定排序，同一级别保持原顺序）：
+// 本机有公网 IPv6 时公网 IPv6 最先，然后是公网 IPv4 和域名，其次是本机无法使用的公网 IPv6，再次是局域网和环回地址，
+// 经 TURN 服务器或中继节点转发的地址最后；同一级别内 QUIC 地址排在 TCP 之前
+pub fn rank_addresses(addresses: &mut [Multiaddr], local_has_ipv6: bool) {
+    addresses.sort_by_key(|addr| {
+        let rank = match ip(addr) {
+            _ if is_relayed(addr) => 4,
+            Some(IpAddr::V6(ip)) if is_global_ipv6(&ip) => {
+                if local_has_ipv6 { 0 } else { 2 }
+            }
+            Some(ip) if !is_global(&ip) => 3,
+            _ => 1,
+        };
+        (rank, !is_quic_address(addr))
+    });
+}
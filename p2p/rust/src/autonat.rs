@@ -0,0 +1,114 @@
+// autonat.rs - AutoNAT 可达性确认
+// STUN 只能告诉节点 NAT 映射出的公网地址，不能说明其它节点能否连进来。AutoNAT 请其它节点回拨本节点的地址，
+// 回拨成功为公网可达，失败为私有网络，多次结果一致后置信度上升。需要以 --features autonat 编译，
+// 未编译时行为用 dummy::Behaviour 占位，可达性始终为 unknown
+use libp2p::kad::Mode;
+use libp2p::{Multiaddr, PeerId};
+use serde::Serialize;
+use std::fmt;
+
+// AutoNAT 行为
+#[cfg(feature = "autonat")]
+pub type Behaviour = libp2p::autonat::Behaviour;
+#[cfg(not(feature = "autonat"))]
+pub type Behaviour = libp2p::swarm::dummy::Behaviour;
+
+// 可达性至少有这么多次后续探测确认后才视为可信，用于切换 Kademlia 模式和判定 NAT 测试结果
+pub const MIN_CONFIDENCE: usize = 1;
+
+// 是否编译了 AutoNAT
+pub fn is_supported() -> bool {
+    cfg!(feature = "autonat")
+}
+
+// 创建 AutoNAT 行为，Bootstrap 节点作为固定的回拨服务器，其余服务器从已连接的节点中选择
+#[cfg(feature = "autonat")]
+pub fn new_behaviour(local_peer_id: PeerId, servers: &[(PeerId, Multiaddr)]) -> Behaviour {
+    let mut behaviour = libp2p::autonat::Behaviour::new(local_peer_id, libp2p::autonat::Config::default());
+    for (peer, address) in servers {
+        behaviour.add_server(*peer, Some(address.clone()));
+    }
+    behaviour
+}
+#[cfg(not(feature = "autonat"))]
+pub fn new_behaviour(_local_peer_id: PeerId, _servers: &[(PeerId, Multiaddr)]) -> Behaviour {
+    libp2p::swarm::dummy::Behaviour
+}
+
+// 其它节点能否连接本节点
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Reachability {
+    // 其它节点回拨成功
+    Public,
+    // 回拨失败，本节点在 NAT 或防火墙之后
+    Private,
+    // 还没有探测结果
+    Unknown,
+}
+
+impl fmt::Display for Reachability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reachability::Public => write!(f, "public"),
+            Reachability::Private => write!(f, "private"),
+            Reachability::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+// AutoNAT 的当前结论：可达性、置信度（连续确认的探测次数）和回拨成功的地址
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReachabilityStatus {
+    pub reachability: Reachability,
+    pub confidence: usize,
+    pub address: Option<String>,
+}
+
+impl Default for ReachabilityStatus {
+    fn default() -> Self {
+        ReachabilityStatus { reachability: Reachability::Unknown, confidence: 0, address: None }
+    }
+}
+
+impl ReachabilityStatus {
+    #[cfg(feature = "autonat")]
+    pub fn from_behaviour(behaviour: &Behaviour) -> Self {
+        let (reachability, address) = match behaviour.nat_status() {
+            libp2p::autonat::NatStatus::Public(address) => (Reachability::Public, Some(address.to_string())),
+            libp2p::autonat::NatStatus::Private => (Reachability::Private, None),
+            libp2p::autonat::NatStatus::Unknown => (Reachability::Unknown, None),
+        };
+        ReachabilityStatus { reachability, confidence: behaviour.confidence(), address }
+    }
+
+    // 可达性已有足够的确认
+    pub fn is_confirmed(&self) -> bool {
+        self.reachability != Reachability::Unknown && self.confidence >= MIN_CONFIDENCE
+    }
+
+    // 确认公网可达
+    pub fn is_public(&self) -> bool {
+        self.is_confirmed() && self.reachability == Reachability::Public
+    }
+
+    // 可达性对应的 Kademlia 模式：公网可达时作为服务器应答查询，私有网络时只作为客户端，未确认时不切换
+    pub fn kademlia_mode(&self) -> Option<Mode> {
+        match self.reachability {
+            _ if !self.is_confirmed() => None,
+            Reachability::Public => Some(Mode::Server),
+            Reachability::Private => Some(Mode::Client),
+            Reachability::Unknown => None,
+        }
+    }
+}
+
+impl fmt::Display for ReachabilityStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (confidence {})", self.reachability, self.confidence)?;
+        if let Some(address) = &self.address {
+            write!(f, " at {}", address)?;
+        }
+        Ok(())
+    }
+}
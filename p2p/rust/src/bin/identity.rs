@@ -0,0 +1,12 @@
+// identity.rs - 节点身份密钥管理工具
+// 等同于 p2p identity <generate|show|rotate> [--key-type ed25519|secp256k1|ecdsa|rsa] [--keystore 文件]
+// 口令从环境变量 P2P_KEYSTORE_PASSPHRASE 读取，未设置时密钥不加密
+use clap::Parser;
+use p2p::cli::{self, Cli};
+
+#[tokio::main]
+async fn main() {
+    let args = ["identity".to_string(), "identity".to_string()].into_iter().chain(std::env::args().skip(1));
+    let code = cli::run(Cli::parse_from(args)).await;
+    std::process::exit(code);
+}
@@ -0,0 +1,424 @@
+// bootstrap.rs - Bootstrap节点信息的记录与持久化
+use crate::error::{Error, Result};
+use crate::persist;
+use libp2p::{Multiaddr, PeerId, multiaddr::Protocol};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+// 引入时间处理库
+use chrono::{DateTime, Utc};
+
+// Bootstrap节点信息默认保存的文件
+pub const BOOTSTRAPS_FILE: &str = "BOOTSTRAPS.json";
+
+// 路由表快照默认保存的文件
+pub const ROUTING_TABLE_FILE: &str = "ROUTING_TABLE.json";
+
+// 连续失败达到该次数的节点被视为失效并从列表中移除
+pub const DEFAULT_MAX_BOOTSTRAP_FAILURES: u32 = 5;
+
+// 评分中各项的权重：成功率、响应时间、最近活跃程度
+const SUCCESS_WEIGHT: f64 = 0.5;
+const LATENCY_WEIGHT: f64 = 0.3;
+const RECENCY_WEIGHT: f64 = 0.2;
+// 响应时间为该值时延迟得分为 0.5
+const REFERENCE_RTT_MS: f64 = 200.0;
+// 最近活跃得分每经过该时长减半
+const RECENCY_HALF_LIFE_HOURS: f64 = 24.0;
+
+// BOOTSTRAPS.json 的当前格式版本
+// 0: 没有 version 字段，每条记录一个地址，状态和时间为字符串
+// 1: 状态为枚举，时间为 DateTime<Utc>，每个节点可有多个地址及协议信息
+pub const BOOTSTRAPS_SCHEMA_VERSION: u32 = 1;
+
+// Bootstrap节点的连接状态
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeStatus {
+    // 尚未连接过
+    #[default]
+    Unknown,
+    // 最近一次连接或 Ping 成功
+    Active,
+    // 最近一次拨号或 Ping 失败
+    Inactive,
+}
+
+impl fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            NodeStatus::Unknown => "unknown",
+            NodeStatus::Active => "active",
+            NodeStatus::Inactive => "inactive",
+        };
+        f.write_str(status)
+    }
+}
+
+// 定义Bootstrap节点信息结构
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BootstrapNode {
+    pub peer_id: String,
+    pub addresses: Vec<String>, // 已知地址，按添加顺序排列
+    pub status: NodeStatus,
+    pub last_seen: Option<DateTime<Utc>>, // 最后一次成功连接的时间
+    pub response_time: Option<u64>, // 最近一次响应时间（毫秒）
+    pub success_count: u32, // 成功连接次数
+    pub failure_count: u32, // 失败连接次数
+    pub consecutive_failures: u32, // 自上次成功以来的连续失败次数
+    pub score: f64, // 综合评分，0 到 1 之间，越高越优先连接
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_version: Option<String>, // 对端报告的客户端版本
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<String>, // 对端报告的协议版本
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protocols: Vec<String>, // 对端支持的协议
+}
+
+impl BootstrapNode {
+    // 为一个尚未连接过的地址创建记录
+    pub fn new(address: &Multiaddr, peer_id: &PeerId) -> Self {
+        BootstrapNode {
+            peer_id: peer_id.to_string(),
+            addresses: vec![address.to_string()],
+            status: NodeStatus::Unknown,
+            last_seen: None,
+            response_time: None,
+            success_count: 0,
+            failure_count: 0,
+            consecutive_failures: 0,
+            score: 0.0,
+            agent_version: None,
+            protocol_version: None,
+            protocols: Vec::new(),
+        }
+    }
+
+    // 添加地址，与已有地址重复（忽略 /p2p/ 后缀）时不添加
+    pub fn add_address(&mut self, address: &str) {
+        let canonical = |addr: &str| addr.parse::<Multiaddr>().ok().map(strip_p2p);
+        let key = canonical(address);
+        let exists = self
+            .addresses
+            .iter()
+            .any(|existing| existing == address || (key.is_some() && canonical(existing) == key));
+        if !exists {
+            self.addresses.push(address.to_string());
+        }
+    }
+
+    // 综合成功率、Ping 响应时间和最近一次成功连接的时间计算评分
+    pub fn compute_score(&self, now: DateTime<Utc>) -> f64 {
+        // 加一平滑，没有记录的节点成功率为 0.5
+        let success_ratio =
+            (self.success_count as f64 + 1.0) / (self.success_count as f64 + self.failure_count as f64 + 2.0);
+
+        let latency = match self.response_time {
+            Some(rtt) => REFERENCE_RTT_MS / (REFERENCE_RTT_MS + rtt as f64),
+            None => 0.5,
+        };
+
+        let recency = self
+            .last_seen
+            .map(|t| {
+                let hours = (now - t).num_seconds().max(0) as f64 / 3600.0;
+                0.5f64.powf(hours / RECENCY_HALF_LIFE_HOURS)
+            })
+            .unwrap_or(0.0);
+
+        SUCCESS_WEIGHT * success_ratio + LATENCY_WEIGHT * latency + RECENCY_WEIGHT * recency
+    }
+
+    pub fn update_score(&mut self) {
+        self.score = self.compute_score(Utc::now());
+    }
+}
+
+// 路由表中的一个节点及其已知地址
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoutingTableEntry {
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_version: Option<String>, // 对端通过 identify 报告的客户端版本
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<String>,
+}
+
+// 路由表快照结构
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RoutingTable {
+    pub peers: Vec<RoutingTableEntry>,
+    pub last_updated: String, // 最后更新时间戳
+}
+
+// 定义Bootstrap节点列表结构
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BootstrapList {
+    pub version: u32,
+    pub nodes: Vec<BootstrapNode>,
+    pub last_updated: DateTime<Utc>, // 最后更新时间
+}
+
+// 版本 0 的节点记录，只用于迁移旧文件
+#[derive(Deserialize)]
+struct BootstrapNodeV0 {
+    address: String,
+    peer_id: String,
+    status: String,
+    last_seen: Option<String>,
+    response_time: Option<u64>,
+    success_count: u32,
+    failure_count: u32,
+    #[serde(default)]
+    consecutive_failures: u32,
+    #[serde(default)]
+    score: f64,
+}
+
+#[derive(Deserialize)]
+struct BootstrapListV0 {
+    nodes: Vec<BootstrapNodeV0>,
+}
+
+impl From<BootstrapNodeV0> for BootstrapNode {
+    fn from(node: BootstrapNodeV0) -> Self {
+        let status = match node.status.as_str() {
+            "active" => NodeStatus::Active,
+            "inactive" => NodeStatus::Inactive,
+            _ => NodeStatus::Unknown,
+        };
+        BootstrapNode {
+            peer_id: node.peer_id,
+            addresses: vec![node.address],
+            status,
+            last_seen: node
+                .last_seen
+                .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                .map(|t| t.with_timezone(&Utc)),
+            response_time: node.response_time,
+            success_count: node.success_count,
+            failure_count: node.failure_count,
+            consecutive_failures: node.consecutive_failures,
+            score: node.score,
+            agent_version: None,
+            protocol_version: None,
+            protocols: Vec::new(),
+        }
+    }
+}
+
+impl BootstrapNode {
+    // 解析出可加入 Kademlia 路由表的 PeerId 和地址（地址统一带 /p2p/ 后缀）
+    // 无法解析的地址被跳过，没有可用地址时返回 None
+    pub fn dial_addresses(&self) -> Option<(PeerId, Vec<Multiaddr>)> {
+        let peer_id: PeerId = self.peer_id.parse().ok()?;
+        let addresses: Vec<Multiaddr> = self
+            .addresses
+            .iter()
+            .filter_map(|addr| addr.parse::<Multiaddr>().ok()?.with_p2p(peer_id).ok())
+            .collect();
+        if addresses.is_empty() {
+            return None;
+        }
+        Some((peer_id, addresses))
+    }
+}
+
+// 去掉地址末尾的 /p2p/ 部分，用于比较地址是否相同
+fn strip_p2p(mut addr: Multiaddr) -> Multiaddr {
+    if let Some(Protocol::P2p(_)) = addr.iter().last() {
+        addr.pop();
+    }
+    addr
+}
+
+// 从Multiaddr中提取 /p2p/ 部分的PeerId
+pub fn peer_id_from_multiaddr(addr: &Multiaddr) -> Option<PeerId> {
+    addr.iter().find_map(|p| match p {
+        Protocol::P2p(peer_id) => Some(peer_id),
+        _ => None,
+    })
+}
+
+// 更新Bootstrap节点状态的辅助函数
+pub fn update_bootstrap_node_status(nodes: &mut [BootstrapNode], peer_id: &str, status: NodeStatus) {
+    for node in nodes.iter_mut() {
+        if node.peer_id == peer_id {
+            // 更新状态
+            node.status = status;
+
+            match status {
+                // 连接成功，更新最后活动时间和成功计数
+                NodeStatus::Active => {
+                    node.last_seen = Some(Utc::now());
+                    node.success_count += 1;
+                    node.consecutive_failures = 0;
+                }
+                // 连接失败，增加失败计数
+                NodeStatus::Inactive => {
+                    node.failure_count += 1;
+                    node.consecutive_failures += 1;
+                }
+                NodeStatus::Unknown => {}
+            }
+            node.update_score();
+            break;
+        }
+    }
+}
+
+// 记录对端仍然在线（如 Ping 成功），刷新最后活动时间并清零连续失败，不计入成功次数
+// 成功次数只在建立连接时增加，长连接上的周期性 Ping 不会抬高成功率
+pub fn touch_bootstrap_node(nodes: &mut [BootstrapNode], peer_id: &str) {
+    if let Some(node) = nodes.iter_mut().find(|node| node.peer_id == peer_id) {
+        node.status = NodeStatus::Active;
+        node.last_seen = Some(Utc::now());
+        node.consecutive_failures = 0;
+        node.update_score();
+    }
+}
+
+// 记录Ping测得的响应时间
+pub fn update_bootstrap_node_response_time(nodes: &mut [BootstrapNode], peer_id: &str, rtt: Duration) {
+    if let Some(node) = nodes.iter_mut().find(|node| node.peer_id == peer_id) {
+        node.response_time = Some(rtt.as_millis() as u64);
+        node.update_score();
+    }
+}
+
+// 记录对端通过 identify 报告的版本和支持的协议
+pub fn update_bootstrap_node_versions(
+    nodes: &mut [BootstrapNode],
+    peer_id: &str,
+    agent_version: &str,
+    protocol_version: &str,
+    protocols: &[String],
+) {
+    if let Some(node) = nodes.iter_mut().find(|node| node.peer_id == peer_id) {
+        node.agent_version = Some(agent_version.to_string());
+        node.protocol_version = Some(protocol_version.to_string());
+        node.protocols = protocols.to_vec();
+    }
+}
+
+// 重新计算评分并按评分从高到低排序
+pub fn rank_bootstrap_nodes(nodes: &mut [BootstrapNode]) {
+    let now = Utc::now();
+    for node in nodes.iter_mut() {
+        node.score = node.compute_score(now);
+    }
+    nodes.sort_by(|a, b| b.score.total_cmp(&a.score));
+}
+
+// 移除连续失败次数达到阈值的节点，返回被移除的节点
+pub fn prune_dead_bootstrap_nodes(nodes: &mut Vec<BootstrapNode>, max_failures: u32) -> Vec<BootstrapNode> {
+    let (dead, alive) = nodes.drain(..).partition(|node| node.consecutive_failures >= max_failures);
+    *nodes = alive;
+    dead
+}
+
+// 合并内置的种子节点和上次保存的节点，按 PeerId 去重并合并地址
+// 同一节点以保存的记录为准，保留其历史统计；无法解析的记录被丢弃
+pub fn merge_bootstrap_nodes(seeds: Vec<BootstrapNode>, saved: Vec<BootstrapNode>) -> Vec<BootstrapNode> {
+    let mut merged: Vec<BootstrapNode> = Vec::new();
+    let mut index_by_peer: HashMap<PeerId, usize> = HashMap::new();
+
+    // 先放入保存的记录，再把种子节点的地址并入，最后按种子节点顺序排列
+    for node in saved.into_iter().chain(seeds.iter().cloned()) {
+        let Some((peer_id, _)) = node.dial_addresses() else { continue };
+        match index_by_peer.get(&peer_id) {
+            Some(&index) => {
+                for address in &node.addresses {
+                    merged[index].add_address(address);
+                }
+            }
+            None => {
+                index_by_peer.insert(peer_id, merged.len());
+                merged.push(node);
+            }
+        }
+    }
+
+    let seed_order: Vec<PeerId> = seeds.iter().filter_map(|seed| seed.peer_id.parse().ok()).collect();
+    let position = |node: &BootstrapNode| {
+        node.peer_id
+            .parse::<PeerId>()
+            .ok()
+            .and_then(|peer_id| seed_order.iter().position(|seed| *seed == peer_id))
+            .unwrap_or(seed_order.len())
+    };
+    merged.sort_by_key(position);
+    merged
+}
+
+// 从JSON文件加载上次保存的Bootstrap节点，文件不存在时返回空列表
+pub fn load_bootstrap_nodes_from_json() -> Result<Vec<BootstrapNode>> {
+    load_bootstrap_nodes_from_path(Path::new(BOOTSTRAPS_FILE))
+}
+
+// 读取任意已知版本的文件并迁移为当前格式
+pub fn load_bootstrap_nodes_from_path(path: &Path) -> Result<Vec<BootstrapNode>> {
+    let Some(value) = persist::read_json(path)? else {
+        return Ok(Vec::new());
+    };
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    match version {
+        0 => {
+            let list: BootstrapListV0 = persist::from_json(path, value)?;
+            Ok(migrate_v0(list.nodes))
+        }
+        1 => {
+            let list: BootstrapList = persist::from_json(path, value)?;
+            Ok(list.nodes)
+        }
+        _ => Err(Error::UnsupportedVersion { path: path.to_path_buf(), version, supported: BOOTSTRAPS_SCHEMA_VERSION }),
+    }
+}
+
+// 版本 0 中同一节点的多个地址是分开的记录，合并为一条
+// 文件按评分排序保存，保留排在最前的记录的统计
+fn migrate_v0(nodes: Vec<BootstrapNodeV0>) -> Vec<BootstrapNode> {
+    let mut migrated: Vec<BootstrapNode> = Vec::new();
+    for node in nodes.into_iter().map(BootstrapNode::from) {
+        match migrated.iter_mut().find(|existing| existing.peer_id == node.peer_id) {
+            Some(existing) => existing.add_address(&node.addresses[0]),
+            None => migrated.push(node),
+        }
+    }
+    migrated
+}
+
+// 保存Bootstrap节点信息到JSON文件的函数
+pub fn save_bootstrap_nodes_to_json(nodes: &[BootstrapNode]) -> Result<()> {
+    save_bootstrap_nodes_to_path(nodes, Path::new(BOOTSTRAPS_FILE))
+}
+
+pub fn save_bootstrap_nodes_to_path(nodes: &[BootstrapNode], path: &Path) -> Result<()> {
+    // 创建Bootstrap节点列表结构，按最新评分排序
+    let mut nodes = nodes.to_vec();
+    rank_bootstrap_nodes(&mut nodes);
+    let bootstrap_list = BootstrapList {
+        version: BOOTSTRAPS_SCHEMA_VERSION,
+        nodes,
+        last_updated: Utc::now(),
+    };
+
+    // 序列化为JSON并原子地替换文件
+    persist::write_json(path, &bootstrap_list)
+}
+
+// 保存路由表快照到JSON文件
+pub fn save_routing_table_to_json(peers: &[RoutingTableEntry]) -> Result<()> {
+    save_routing_table_to_path(peers, Path::new(ROUTING_TABLE_FILE))
+}
+
+pub fn save_routing_table_to_path(peers: &[RoutingTableEntry], path: &Path) -> Result<()> {
+    let routing_table = RoutingTable {
+        peers: peers.to_vec(),
+        last_updated: Utc::now().to_rfc3339(),
+    };
+    persist::write_json(path, &routing_table)
+}
@@ -0,0 +1,413 @@
+// cli.rs - p2p 命令行前端
+// 所有子命令共用 --config 和单项配置参数，--format json 时标准输出只包含结果
+use crate::bootstrap::DEFAULT_MAX_BOOTSTRAP_FAILURES;
+use crate::commands::{self, nat_test::DEFAULT_ROOM};
+use crate::config::{self, Config, ConfigError, KademliaMode, StunSource, EXIT_CONFIG_ERROR, MAX_RUNTIME_MINUTES};
+use crate::keystore::{self, KeyType, KEYSTORE_FILE};
+use crate::output::{self, OutputFormat};
+use clap::{value_parser, Args, Parser, Subcommand, ValueEnum};
+use libp2p::identity::Keypair;
+use libp2p::{Multiaddr, PeerId};
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(name = "p2p", version, about = "P2P node with Kademlia DHT, STUN and NAT traversal tests")]
+#[command(args_override_self = true)]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    #[arg(long, value_enum, global = true, default_value_t = OutputFormat::Text,
+          help = "Output format; with json only the result is written to stdout")]
+    pub format: OutputFormat,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+// 覆盖配置文件和环境变量的单项配置，列表用逗号分隔，空值表示空列表
+// 值由 clap 按类型解析，只有跨配置项的检查留给 Config::validate
+#[derive(Args, Debug, Default)]
+pub struct ConfigArgs {
+    #[arg(long, global = true, value_name = "FILE", help = "Config file (default: p2p.toml if present)")]
+    pub config: Option<PathBuf>,
+    #[arg(long, global = true, value_name = "ADDRS", value_parser = parse_list::<Multiaddr>,
+          help = "Comma-separated listen multiaddrs")]
+    pub listen_addrs: Option<::std::vec::Vec<Multiaddr>>,
+    #[arg(long, global = true, value_name = "ADDRS", value_parser = parse_list::<Multiaddr>,
+          help = "Comma-separated bootstrap multiaddrs")]
+    pub bootstrap_addrs: Option<::std::vec::Vec<Multiaddr>>,
+    #[arg(long, global = true, value_name = "SERVERS", value_parser = parse_list::<HostPort>,
+          help = "Comma-separated STUN servers (host:port)")]
+    pub stun_servers: Option<::std::vec::Vec<HostPort>>,
+    #[arg(long, global = true, value_enum, value_name = "SOURCE", help = "Where to discover the public address")]
+    pub stun_source: Option<StunSource>,
+    #[arg(long, global = true, value_name = "BOOL", help = "Answer STUN requests from other peers")]
+    pub stun_responder: Option<bool>,
+    #[arg(long, global = true, value_name = "PORT", help = "UDP port of the STUN responder")]
+    pub stun_responder_port: Option<u16>,
+    #[arg(long, global = true, value_name = "SERVER", help = "TURN server (host:port) used to accept relayed connections")]
+    pub turn_server: Option<HostPort>,
+    #[arg(long, global = true, value_name = "NAME", help = "TURN long-term credential username")]
+    pub turn_username: Option<String>,
+    #[arg(long, global = true, value_name = "PASSWORD", help = "TURN long-term credential password (prefer P2P_TURN_PASSWORD)")]
+    pub turn_password: Option<String>,
+    #[arg(long, global = true, value_name = "N", help = "Number of circuit relays to reserve a slot on (0 disables)")]
+    pub relay_reservations: Option<usize>,
+    #[arg(long, global = true, value_name = "BOOL", help = "Serve as a circuit relay for other peers")]
+    pub relay_server: Option<bool>,
+    #[arg(long, global = true, value_name = "N", help = "Maximum reservations held by the relay server")]
+    pub relay_max_reservations: Option<usize>,
+    #[arg(long, global = true, value_name = "N", help = "Maximum circuits relayed at once")]
+    pub relay_max_circuits: Option<usize>,
+    #[arg(long, global = true, value_name = "BYTES", value_parser = value_parser!(u64).range(1..),
+          help = "Maximum bytes relayed per circuit and direction")]
+    pub relay_max_circuit_bytes: Option<u64>,
+    #[arg(long, global = true, value_name = "SECS", value_parser = value_parser!(u64).range(1..=u32::MAX as u64),
+          help = "Maximum duration of a relayed circuit")]
+    pub relay_max_circuit_duration_secs: Option<u64>,
+    #[arg(long, global = true, value_name = "PEERS", value_parser = parse_list::<PeerId>,
+          help = "Comma-separated peer IDs allowed to use the relay server (default: any)")]
+    pub relay_allowlist: Option<::std::vec::Vec<PeerId>>,
+    #[arg(long, global = true, value_enum, value_name = "MODE",
+          help = "Kademlia mode; auto follows AutoNAT reachability")]
+    pub kademlia_mode: Option<KademliaMode>,
+    #[arg(long, global = true, value_name = "SECS", value_parser = value_parser!(u64).range(1..),
+          help = "Kademlia query timeout")]
+    pub query_timeout_secs: Option<u64>,
+    #[arg(long, global = true, value_name = "SECS", value_parser = value_parser!(u64).range(1..), help = "Ping interval")]
+    pub ping_interval_secs: Option<u64>,
+    #[arg(long, global = true, value_name = "MINUTES", value_parser = value_parser!(u64).range(1..=MAX_RUNTIME_MINUTES),
+          help = "Maximum runtime")]
+    pub max_runtime_minutes: Option<u64>,
+    #[arg(long, global = true, value_name = "N", value_parser = value_parser!(u32).range(1..),
+          help = "Maximum connection attempts")]
+    pub max_connection_attempts: Option<u32>,
+    #[arg(long, global = true, value_name = "SECS", value_parser = value_parser!(u64).range(1..),
+          help = "Initial bootstrap timer")]
+    pub bootstrap_interval_secs: Option<u64>,
+    #[arg(long, global = true, value_name = "SECS", value_parser = value_parser!(u64).range(1..),
+          help = "Peer discovery refresh timer")]
+    pub refresh_interval_secs: Option<u64>,
+    #[arg(long, global = true, value_name = "SECS", value_parser = value_parser!(u64).range(1..),
+          help = "Address output and STUN timer")]
+    pub address_interval_secs: Option<u64>,
+    #[arg(long, global = true, value_name = "DIR", help = "Directory for output files (default: current directory)")]
+    pub data_dir: Option<PathBuf>,
+    #[arg(long, global = true, value_name = "FILE", help = "Bootstrap node list, relative to --data-dir")]
+    pub bootstraps_file: Option<PathBuf>,
+    #[arg(long, global = true, value_name = "FILE", help = "STUN server health records, relative to --data-dir")]
+    pub stun_servers_file: Option<PathBuf>,
+    #[arg(long, global = true, value_name = "FILE", help = "Routing table snapshot, relative to --data-dir")]
+    pub routing_table_file: Option<PathBuf>,
+    #[arg(long, global = true, value_name = "FILE", help = "NAT traversal test report, relative to --data-dir")]
+    pub report_file: Option<PathBuf>,
+}
+
+// host:port 形式的服务器地址
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostPort(pub String);
+
+impl FromStr for HostPort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if config::is_host_port(s) {
+            Ok(HostPort(s.to_string()))
+        } else {
+            Err("expected host:port".to_string())
+        }
+    }
+}
+
+impl fmt::Display for HostPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// 逗号分隔的列表，每一项按 T 解析，空字符串表示空列表
+fn parse_list<T: FromStr>(value: &str) -> Result<Vec<T>, String>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse().map_err(|e| format!("`{}`: {}", item, e)))
+        .collect()
+}
+
+fn to_strings<T: ToString>(items: &[T]) -> Vec<String> {
+    items.iter().map(T::to_string).collect()
+}
+
+impl ConfigArgs {
+    // 用命令行中出现的配置项覆盖 config
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(addrs) = &self.listen_addrs {
+            config.listen_addrs = to_strings(addrs);
+        }
+        if let Some(addrs) = &self.bootstrap_addrs {
+            config.bootstrap_addrs = to_strings(addrs);
+        }
+        if let Some(servers) = &self.stun_servers {
+            config.stun_servers = to_strings(servers);
+        }
+        if let Some(peers) = &self.relay_allowlist {
+            config.relay_allowlist = to_strings(peers);
+        }
+        if let Some(server) = &self.turn_server {
+            config.turn_server = Some(server.to_string());
+        }
+        if let Some(username) = &self.turn_username {
+            config.turn_username = Some(username.clone());
+        }
+        if let Some(password) = &self.turn_password {
+            config.turn_password = Some(password.clone());
+        }
+        if self.query_timeout_secs.is_some() {
+            config.query_timeout_secs = self.query_timeout_secs;
+        }
+
+        macro_rules! set {
+            ($($field:ident),* $(,)?) => {
+                $(if let Some(value) = &self.$field {
+                    config.$field = value.clone();
+                })*
+            };
+        }
+        set!(
+            stun_source,
+            stun_responder,
+            stun_responder_port,
+            relay_reservations,
+            relay_server,
+            relay_max_reservations,
+            relay_max_circuits,
+            relay_max_circuit_bytes,
+            relay_max_circuit_duration_secs,
+            kademlia_mode,
+            ping_interval_secs,
+            max_runtime_minutes,
+            max_connection_attempts,
+            bootstrap_interval_secs,
+            refresh_interval_secs,
+            address_interval_secs,
+            data_dir,
+            bootstraps_file,
+            stun_servers_file,
+            routing_table_file,
+            report_file,
+        );
+    }
+
+    // 在 defaults 基础上依次应用配置文件、环境变量和命令行参数
+    pub fn load(&self, defaults: Config) -> Result<Config, ConfigError> {
+        let mut config = Config::load_file_and_env(defaults, self.config.clone(), |name| std::env::var(name).ok())?;
+        self.apply(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    #[command(about = "Run a node that discovers peers and checks node-to-node communication")]
+    Run {
+        #[arg(long, value_name = "FILE", default_value = KEYSTORE_FILE, help = "Keystore holding the node identity")]
+        keystore: PathBuf,
+    },
+    #[command(about = "Discover the public address through the configured STUN servers")]
+    Stun,
+    #[command(about = "Inspect and maintain the saved bootstrap node list")]
+    Bootstrap {
+        #[command(subcommand)]
+        command: BootstrapCommand,
+    },
+    #[command(about = "Run a NAT traversal test between two nodes sharing a room")]
+    NatTest {
+        #[arg(value_enum)]
+        role: Role,
+        #[arg(long, default_value = DEFAULT_ROOM, help = "Room name used as the shared DHT key")]
+        room: String,
+    },
+    #[command(about = "Run the performance benchmark node with a temporary identity")]
+    Bench,
+    #[command(about = "Publish or look up DHT records")]
+    Dht {
+        #[command(subcommand)]
+        command: DhtCommand,
+    },
+    #[command(about = "Manage the node identity keystore")]
+    Identity {
+        #[command(subcommand)]
+        command: IdentityCommand,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Initiator,
+    Responder,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BootstrapCommand {
+    #[command(about = "List saved bootstrap nodes ordered by score")]
+    List,
+    #[command(about = "Connect to bootstrap nodes for a while and save the updated statistics")]
+    Probe {
+        #[arg(long, value_name = "SECS", default_value_t = 30, help = "How long to probe")]
+        duration_secs: u64,
+    },
+    #[command(about = "Remove bootstrap nodes that keep failing")]
+    Prune {
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_BOOTSTRAP_FAILURES,
+              help = "Consecutive failures after which a node is removed")]
+        max_failures: u32,
+        #[arg(long, help = "Only report what would be removed")]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DhtCommand {
+    #[command(about = "Publish a record")]
+    Put {
+        key: String,
+        value: String,
+        #[arg(long, value_name = "SECS", default_value_t = 60, help = "Timeout for joining the network and publishing")]
+        timeout_secs: u64,
+    },
+    #[command(about = "Look up a record")]
+    Get {
+        key: String,
+        #[arg(long, value_name = "SECS", default_value_t = 60, help = "Timeout for joining the network and the lookup")]
+        timeout_secs: u64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IdentityCommand {
+    #[command(about = "Generate a new keystore (the passphrase is read from P2P_KEYSTORE_PASSPHRASE)")]
+    Generate(KeyArgs),
+    #[command(about = "Show the peer ID and key type without decrypting the key")]
+    Show {
+        #[arg(long, value_name = "FILE", default_value = KEYSTORE_FILE)]
+        keystore: PathBuf,
+    },
+    #[command(about = "Replace the key and keep the previous keystore as a backup")]
+    Rotate(KeyArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct KeyArgs {
+    #[arg(long, value_name = "TYPE", default_value = "ed25519", help = "ed25519, secp256k1, ecdsa or rsa")]
+    pub key_type: KeyType,
+    #[arg(long, value_name = "FILE", default_value = KEYSTORE_FILE)]
+    pub keystore: PathBuf,
+}
+
+// 执行命令并返回进程退出码
+pub async fn run(cli: Cli) -> i32 {
+    output::set_format(cli.format);
+    match execute(cli).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+async fn execute(cli: Cli) -> Result<i32, Box<dyn Error>> {
+    // 只有需要启动节点的命令才加载配置
+    let load = |defaults: Config| {
+        cli.config.load(defaults).inspect_err(|e| eprintln!("Configuration error: {}", e)).ok()
+    };
+
+    match &cli.command {
+        Command::Run { keystore: path } => {
+            let Some(config) = load(commands::run::default_config()) else { return Ok(EXIT_CONFIG_ERROR) };
+            // 从密钥文件加载节点身份，首次运行时生成 Ed25519 密钥对，保证重启后 PeerId 不变
+            let passphrase = keystore::passphrase_from_env();
+            let path = path.clone();
+            let keypair =
+                commands::blocking(move || keystore::load_or_generate(&path, KeyType::Ed25519, passphrase.as_deref())).await?;
+            let summary = commands::run::execute(&config, keypair).await?;
+            summary.print();
+            Ok(summary.exit_code())
+        }
+        Command::Bench => {
+            let Some(config) = load(commands::run::bench_config()) else { return Ok(EXIT_CONFIG_ERROR) };
+            let summary = commands::run::execute(&config, Keypair::generate_ed25519()).await?;
+            summary.print();
+            Ok(summary.exit_code())
+        }
+        Command::Stun => {
+            let Some(config) = load(Config::default()) else { return Ok(EXIT_CONFIG_ERROR) };
+            commands::stun::execute(&config).await?.print();
+            Ok(0)
+        }
+        Command::NatTest { role, room } => {
+            let Some(config) = load(commands::nat_test::default_config()) else { return Ok(EXIT_CONFIG_ERROR) };
+            let is_initiator = *role == Role::Initiator;
+            let summary = commands::nat_test::execute(&config, Keypair::generate_ed25519(), is_initiator, room).await?;
+            summary.print();
+            Ok(summary.exit_code())
+        }
+        Command::Bootstrap { command } => match command {
+            BootstrapCommand::List => {
+                let Some(config) = load(Config::default()) else { return Ok(EXIT_CONFIG_ERROR) };
+                commands::bootstrap::print_nodes(&commands::bootstrap::list(&config.bootstraps_path())?);
+                Ok(0)
+            }
+            BootstrapCommand::Probe { duration_secs } => {
+                let Some(config) = load(commands::run::default_config()) else { return Ok(EXIT_CONFIG_ERROR) };
+                let nodes = commands::bootstrap::probe(&config, Duration::from_secs(*duration_secs)).await?;
+                commands::bootstrap::print_nodes(&nodes);
+                Ok(0)
+            }
+            BootstrapCommand::Prune { max_failures, dry_run } => {
+                let Some(config) = load(Config::default()) else { return Ok(EXIT_CONFIG_ERROR) };
+                let (path, max_failures, dry_run) = (config.bootstraps_path(), *max_failures, *dry_run);
+                commands::blocking(move || commands::bootstrap::prune(&path, max_failures, dry_run)).await?.print();
+                Ok(0)
+            }
+        },
+        Command::Dht { command } => {
+            let Some(config) = load(commands::run::default_config()) else { return Ok(EXIT_CONFIG_ERROR) };
+            let record = match command {
+                DhtCommand::Put { key, value, timeout_secs } => {
+                    commands::dht::put(&config, key, value, Duration::from_secs(*timeout_secs)).await?
+                }
+                DhtCommand::Get { key, timeout_secs } => {
+                    commands::dht::get(&config, key, Duration::from_secs(*timeout_secs)).await?
+                }
+            };
+            record.print();
+            Ok(0)
+        }
+        Command::Identity { command } => {
+            let summary = match command {
+                IdentityCommand::Generate(args) => {
+                    let (path, key_type) = (args.keystore.clone(), args.key_type);
+                    commands::blocking(move || commands::identity::generate(&path, key_type)).await?
+                }
+                IdentityCommand::Show { keystore } => commands::identity::show(keystore)?,
+                IdentityCommand::Rotate(args) => {
+                    let (path, key_type) = (args.keystore.clone(), args.key_type);
+                    commands::blocking(move || commands::identity::rotate(&path, key_type)).await?
+                }
+            };
+            summary.print();
+            Ok(0)
+        }
+    }
+}
@@ -0,0 +1,101 @@
+// bootstrap.rs - 查看、探测和清理保存的 Bootstrap 节点
+use crate::bootstrap::{self, load_bootstrap_nodes_from_path, save_bootstrap_nodes_to_path, BootstrapNode};
+use crate::config::Config;
+use crate::node::NodeEvent;
+use crate::output;
+use crate::progress;
+use libp2p::{futures::StreamExt, identity::Keypair};
+use serde::Serialize;
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::{timeout_at, Instant};
+
+// 输出 Bootstrap 节点列表
+pub fn print_nodes(nodes: &[BootstrapNode]) {
+    output::print_result(nodes, |nodes| {
+        println!("{:<8} {:<10} {:>8} {:>8} {:>10}  ADDRESS", "SCORE", "STATUS", "SUCCESS", "FAILURE", "RTT(ms)");
+        for node in nodes {
+            let rtt = node.response_time.map_or("-".to_string(), |rtt| rtt.to_string());
+            println!(
+                "{:<8.3} {:<10} {:>8} {:>8} {:>10}  {}",
+                node.score,
+                node.status.to_string(),
+                node.success_count,
+                node.failure_count,
+                rtt,
+                node.addresses.join(" ")
+            );
+        }
+        println!("{} bootstrap nodes", nodes.len());
+    });
+}
+
+// 按评分排序的保存的 Bootstrap 节点
+pub fn list(path: &Path) -> crate::error::Result<Vec<BootstrapNode>> {
+    let mut nodes = load_bootstrap_nodes_from_path(path)?;
+    bootstrap::rank_bootstrap_nodes(&mut nodes);
+    Ok(nodes)
+}
+
+// 连接配置中的和保存的 Bootstrap 节点，在 duration 内记录连接和 Ping 结果后保存
+pub async fn probe(config: &Config, duration: Duration) -> Result<Vec<BootstrapNode>, Box<dyn Error>> {
+    // 使用临时身份探测，不需要读取密钥文件
+    let (handle, mut events) = super::start_node(config, Keypair::generate_ed25519()).await?;
+
+    let deadline = Instant::now() + duration;
+    while let Ok(Some(event)) = timeout_at(deadline, events.next()).await {
+        match event {
+            NodeEvent::ConnectionEstablished { peer_id, .. } => progress!("Connected to {}", peer_id),
+            NodeEvent::Ping { peer, result: Ok(rtt) } => progress!("Ping {} in {:?}", peer, rtt),
+            NodeEvent::OutgoingConnectionError { peer_id: Some(peer_id), error } => {
+                progress!("Failed to connect to {}: {}", peer_id, error)
+            }
+            _ => {}
+        }
+    }
+
+    let snapshot = handle.shutdown().await?;
+    let (bootstraps_path, routing_table_path) = (config.bootstraps_path(), config.routing_table_path());
+    let paths = (bootstraps_path.clone(), routing_table_path.clone());
+    let snapshot = super::blocking(move || {
+        snapshot.save_to(&paths.0, &paths.1)?;
+        Ok(snapshot)
+    })
+    .await?;
+    progress!("Probe results saved to {} and {}", bootstraps_path.display(), routing_table_path.display());
+
+    let mut nodes = snapshot.bootstrap_nodes;
+    bootstrap::rank_bootstrap_nodes(&mut nodes);
+    Ok(nodes)
+}
+
+// 清理结果
+#[derive(Debug, Clone, Serialize)]
+pub struct PruneSummary {
+    pub removed: Vec<BootstrapNode>,
+    pub remaining: usize,
+    pub dry_run: bool,
+}
+
+impl PruneSummary {
+    pub fn print(&self) {
+        output::print_result(self, |summary| {
+            for node in &summary.removed {
+                println!("Removed {} ({} consecutive failures)", node.peer_id, node.consecutive_failures);
+            }
+            let verb = if summary.dry_run { "Would remove" } else { "Removed" };
+            println!("{} {} bootstrap nodes, {} remaining", verb, summary.removed.len(), summary.remaining);
+        });
+    }
+}
+
+// 移除连续失败次数达到阈值的节点，dry_run 时不写回文件
+pub fn prune(path: &Path, max_failures: u32, dry_run: bool) -> crate::error::Result<PruneSummary> {
+    let mut nodes = load_bootstrap_nodes_from_path(path)?;
+    let removed = bootstrap::prune_dead_bootstrap_nodes(&mut nodes, max_failures);
+    if !dry_run && !removed.is_empty() {
+        save_bootstrap_nodes_to_path(&nodes, path)?;
+    }
+    Ok(PruneSummary { removed, remaining: nodes.len(), dry_run })
+}
@@ -0,0 +1,75 @@
+// dht.rs - 在 DHT 中发布和查找记录
+use crate::config::Config;
+use crate::node::{NodeEvent, NodeEvents};
+use crate::output;
+use crate::progress;
+use libp2p::{futures::StreamExt, identity::Keypair, kad::RecordKey};
+use serde::Serialize;
+use std::error::Error;
+use std::time::Duration;
+use tokio::time::{sleep, timeout, Instant};
+
+// DHT 记录
+#[derive(Debug, Clone, Serialize)]
+pub struct DhtRecord {
+    pub key: String,
+    pub value: String,
+    pub publisher: Option<String>,
+}
+
+impl DhtRecord {
+    pub fn print(&self) {
+        output::print_result(self, |record| println!("{} = {}", record.key, record.value));
+    }
+}
+
+// 启动临时节点并等待加入网络，最多等待 wait
+async fn join(config: &Config, wait: Duration) -> Result<(crate::node::NodeHandle, NodeEvents), Box<dyn Error>> {
+    let (handle, mut events) = super::start_node(config, Keypair::generate_ed25519()).await?;
+    match handle.bootstrap().await {
+        Ok(()) => wait_for_bootstrap(&mut events, wait).await,
+        Err(e) => progress!("Failed to start bootstrap: {}", e),
+    }
+    Ok((handle, events))
+}
+
+// 等待 Bootstrap 查询结束或超时
+async fn wait_for_bootstrap(events: &mut NodeEvents, wait: Duration) {
+    let deadline = Instant::now() + wait;
+    loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(NodeEvent::Bootstrap(Ok(result))) if result.num_remaining == 0 => break,
+                Some(NodeEvent::Bootstrap(Err(e))) => {
+                    progress!("Bootstrap failed: {}", e);
+                    break;
+                }
+                Some(_) => {}
+                None => break,
+            },
+            _ = sleep(deadline.saturating_duration_since(Instant::now())) => break,
+        }
+    }
+}
+
+// 发布记录，wait 同时限制加入网络和发布的时间
+pub async fn put(config: &Config, key: &str, value: &str, wait: Duration) -> Result<DhtRecord, Box<dyn Error>> {
+    let (handle, _events) = join(config, wait).await?;
+    let result = timeout(wait, handle.publish(RecordKey::new(&key), value.as_bytes().to_vec())).await;
+    handle.shutdown().await?;
+    result.map_err(|_| format!("publishing `{}` timed out", key))??;
+    Ok(DhtRecord { key: key.to_string(), value: value.to_string(), publisher: Some(handle.local_peer_id().to_string()) })
+}
+
+// 查找记录，wait 同时限制加入网络和查找的时间
+pub async fn get(config: &Config, key: &str, wait: Duration) -> Result<DhtRecord, Box<dyn Error>> {
+    let (handle, _events) = join(config, wait).await?;
+    let result = timeout(wait, handle.lookup(RecordKey::new(&key))).await;
+    handle.shutdown().await?;
+    let record = result.map_err(|_| format!("looking up `{}` timed out", key))??;
+    Ok(DhtRecord {
+        key: key.to_string(),
+        value: String::from_utf8_lossy(&record.value).into_owned(),
+        publisher: record.publisher.map(|peer| peer.to_string()),
+    })
+}
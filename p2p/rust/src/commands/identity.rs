@@ -0,0 +1,78 @@
+// identity.rs - 节点身份密钥管理
+// 口令从环境变量 P2P_KEYSTORE_PASSPHRASE 读取，未设置时密钥不加密
+use crate::error::Result;
+use crate::keystore::{self, KeyType};
+use crate::output;
+use crate::progress;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+// 密钥文件信息
+#[derive(Debug, Clone, Serialize)]
+pub struct IdentitySummary {
+    pub keystore: PathBuf,
+    pub peer_id: String,
+    pub key_type: KeyType,
+    pub encrypted: bool,
+    pub created_at: String,
+    // 轮换时的旧 PeerId 和旧密钥文件的备份路径
+    pub previous_peer_id: Option<String>,
+    pub backup: Option<PathBuf>,
+}
+
+impl IdentitySummary {
+    fn from_keystore(path: &Path) -> Result<Self> {
+        let info = keystore::show(path)?;
+        Ok(IdentitySummary {
+            keystore: path.to_path_buf(),
+            peer_id: info.peer_id.to_string(),
+            key_type: info.key_type,
+            encrypted: info.encrypted,
+            created_at: info.created_at,
+            previous_peer_id: None,
+            backup: None,
+        })
+    }
+
+    pub fn print(&self) {
+        output::print_result(self, |summary| {
+            println!("Keystore: {}", summary.keystore.display());
+            if let Some(previous) = &summary.previous_peer_id {
+                println!("  Previous peer ID: {}", previous);
+            }
+            println!("  Peer ID: {}", summary.peer_id);
+            println!("  Key type: {}", summary.key_type);
+            println!("  Encrypted: {}", summary.encrypted);
+            println!("  Created at: {}", summary.created_at);
+            if let Some(backup) = &summary.backup {
+                println!("  Previous keystore saved to {}", backup.display());
+            }
+        });
+    }
+}
+
+// 生成新的密钥文件，文件已存在时报错
+pub fn generate(path: &Path, key_type: KeyType) -> Result<IdentitySummary> {
+    let passphrase = keystore::passphrase_from_env();
+    keystore::generate(path, key_type, passphrase.as_deref())?;
+    if passphrase.is_none() {
+        progress!("Warning: keystore is not encrypted, set {} to protect it", keystore::PASSPHRASE_ENV);
+    }
+    IdentitySummary::from_keystore(path)
+}
+
+// 查看密钥文件中的 PeerId 和密钥类型，不需要口令
+pub fn show(path: &Path) -> Result<IdentitySummary> {
+    IdentitySummary::from_keystore(path)
+}
+
+// 生成新密钥替换旧密钥，旧文件保留为备份
+pub fn rotate(path: &Path, key_type: KeyType) -> Result<IdentitySummary> {
+    let passphrase = keystore::passphrase_from_env();
+    let previous = keystore::show(path)?.peer_id;
+    let (_, backup) = keystore::rotate(path, key_type, passphrase.as_deref())?;
+    let mut summary = IdentitySummary::from_keystore(path)?;
+    summary.previous_peer_id = Some(previous.to_string());
+    summary.backup = Some(backup);
+    Ok(summary)
+}
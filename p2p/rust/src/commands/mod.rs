@@ -0,0 +1,343 @@
+// commands - p2p 命令行各子命令的实现
+// 每个子命令接收已加载的配置，进度信息用 progress! 输出，结果用 output::print_result 输出
+pub mod bootstrap;
+pub mod dht;
+pub mod identity;
+pub mod nat_test;
+pub mod run;
+pub mod stun;
+
+use crate::bootstrap::{load_bootstrap_nodes_from_path, peer_id_from_multiaddr};
+use crate::config::Config;
+use crate::node::{stun_servers_key, NodeBuilder, NodeEvents, NodeHandle, DEFAULT_STUN_PEERS};
+use crate::progress;
+use crate::stun::{perform_stun_request, probe_from, StunProbe, StunTransport};
+use crate::stun_servers::{
+    load_stun_servers_from_path, merge_shared_stun_servers, merge_stun_servers, prune_failed_candidates,
+    select_stun_servers, shared_stun_servers, stun_credentials, update_stun_servers_at_path, SharedStunServers,
+    StunRound, StunServerRecord, DEFAULT_MAX_STUN_FAILURES,
+};
+use libp2p::futures::future::join_all;
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use std::net::{IpAddr, SocketAddr};
+use libp2p::identity::Keypair;
+use std::error::Error;
+use tokio::task::JoinHandle;
+
+// 按配置创建并启动节点：合并上次保存的 Bootstrap 节点，并按评分顺序拨号
+pub async fn start_node(config: &Config, keypair: Keypair) -> Result<(NodeHandle, NodeEvents), Box<dyn Error>> {
+    let mut builder = NodeBuilder::from_config(config).keypair(keypair);
+
+    // 只有包含 PeerId 的地址才能加入路由表
+    for addr in config.bootstrap_multiaddrs() {
+        if peer_id_from_multiaddr(&addr).is_none() {
+            progress!("Warning: Bootstrap address {} does not contain a PeerId. Skipping.", addr);
+        }
+    }
+
+    // 合并上次运行保存的 Bootstrap 节点，保留其历史统计
+    let bootstraps_path = config.bootstraps_path();
+    match load_bootstrap_nodes_from_path(&bootstraps_path) {
+        Ok(saved) => {
+            progress!("Loaded {} bootstrap nodes from {}", saved.len(), bootstraps_path.display());
+            builder = builder.saved_bootstrap_nodes(saved);
+        }
+        Err(e) => progress!("Failed to load {}: {}", bootstraps_path.display(), e),
+    }
+
+    let node = builder.build()?;
+    progress!("Local peer ID: {:?}", node.local_peer_id());
+    for (addr, e) in node.listen_errors() {
+        progress!("Warning: Failed to listen on {}: {}", addr, e);
+    }
+    for address in node.stun_responder_addresses() {
+        progress!("Answering STUN requests on UDP {} and {}", address, crate::peer_stun::PROTOCOL_NAME);
+    }
+
+    // 在后台运行节点事件循环
+    let (handle, events) = node.spawn();
+
+    // 按评分顺序优先连接历史表现最好的 Bootstrap 节点
+    let dialed = handle.dial_bootstrap_nodes().await?;
+    progress!("Dialing {} bootstrap nodes in score order", dialed);
+
+    Ok((handle, events))
+}
+
+// 在后台运行的 stun_from_listen_port：询问节点时要等待 get_providers 查询，可能持续到查询超时，
+// 主循环在等待期间仍要处理退出信号和节点事件，结果作为 select! 的一个分支接收
+#[derive(Default)]
+pub struct StunTask(Option<JoinHandle<crate::error::Result<StunProbe>>>);
+
+impl StunTask {
+    // 启动一次查询，上一次查询仍在进行时不重复启动，返回是否启动了新的查询
+    pub fn start(&mut self, handle: &NodeHandle, config: &Config) -> bool {
+        if self.0.is_some() {
+            return false;
+        }
+        let (handle, config) = (handle.clone(), config.clone());
+        self.0 = Some(tokio::spawn(async move { stun_from_listen_port(&handle, &config).await }));
+        true
+    }
+
+    // 等待查询结果，没有进行中的查询时一直等待；可以在 select! 中安全取消
+    pub async fn finished(&mut self) -> crate::error::Result<StunProbe> {
+        let Some(task) = &mut self.0 else { return std::future::pending().await };
+        let result = task.await;
+        self.0 = None;
+        match result {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(_) => Err(crate::error::Error::Stopped),
+        }
+    }
+}
+
+impl Drop for StunTask {
+    fn drop(&mut self) {
+        if let Some(task) = self.0.take() {
+            task.abort();
+        }
+    }
+}
+
+// 合并配置的 STUN 服务器和 STUN_SERVERS.json 中的健康记录（包括其它节点分享的服务器）
+pub fn load_stun_servers(config: &Config) -> Vec<StunServerRecord> {
+    let path = config.stun_servers_path();
+    let saved = match load_stun_servers_from_path(&path) {
+        Ok(saved) => saved,
+        Err(e) => {
+            progress!("Failed to load {}: {}", path.display(), e);
+            Vec::new()
+        }
+    };
+    let mut records = merge_stun_servers(&config.stun_servers, saved);
+    prune_failed_candidates(&mut records);
+    records
+}
+
+// 在文件锁内更新 STUN_SERVERS.json，分享服务器的任务和 STUN 查询可能同时更新记录
+// 失败只输出提示并返回 None
+pub async fn update_stun_servers<T: Send + 'static>(
+    config: &Config,
+    update: impl FnOnce(&mut Vec<StunServerRecord>) -> T + Send + 'static,
+) -> Option<T> {
+    let path = config.stun_servers_path();
+    let (update_path, configured) = (path.clone(), config.stun_servers.clone());
+    match blocking(move || update_stun_servers_at_path(&update_path, &configured, update)).await {
+        Ok(result) => Some(result),
+        Err(e) => {
+            progress!("Failed to update STUN server records in {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// 把一轮查询的结果计入 STUN_SERVERS.json
+pub async fn record_stun_round(config: &Config, round: StunRound) {
+    update_stun_servers(config, move |records| round.record(records)).await;
+}
+
+// 在阻塞线程池中执行文件读写：持久化时可能要等待其它进程释放文件锁，不能占用异步运行时
+pub async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> crate::error::Result<T> + Send + 'static,
+) -> crate::error::Result<T> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(crate::error::Error::Stopped),
+    }
+}
+
+// 与其它节点交换能正常应答的 STUN 服务器：先加入 DHT 中其它节点分享的服务器，再发布本节点最近查询成功的服务器
+// 加入的服务器只是候选，是否优先使用取决于本节点自己的查询统计，同一地区的节点因此逐渐使用在当地可用的服务器
+// DHT 查询可能持续到超时，调用方应在单独的任务中运行
+pub async fn share_stun_servers(handle: NodeHandle, config: Config) {
+    match handle.lookup_remote(stun_servers_key()).await {
+        Ok(record) => match serde_json::from_slice::<SharedStunServers>(&record.value) {
+            Ok(shared) => {
+                let added = update_stun_servers(&config, move |records| merge_shared_stun_servers(records, &shared)).await;
+                if let Some(added @ 1..) = added {
+                    progress!("Added {} STUN servers shared by other peers", added);
+                }
+            }
+            Err(e) => progress!("Ignoring invalid shared STUN server list: {}", e),
+        },
+        Err(crate::error::Error::RecordNotFound) => {}
+        Err(e) => progress!("Failed to look up shared STUN servers: {}", e),
+    }
+
+    let shared = shared_stun_servers(&load_stun_servers(&config));
+    if shared.servers.is_empty() {
+        return;
+    }
+    let value = match serde_json::to_vec(&shared) {
+        Ok(value) => value,
+        Err(e) => {
+            progress!("Failed to encode shared STUN servers: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = handle.publish(stun_servers_key(), value).await {
+        progress!("Failed to share STUN servers: {}", e);
+    }
+}
+
+// 输出一次多服务器 STUN 查询的结果，IPv4 和 IPv6 分别给出多数服务器报告的地址
+// 同一地址族的映射地址不一致时提示可能是对称型 NAT
+pub fn report_stun_probe(probe: &StunProbe) {
+    let total = probe.results.len() + probe.failures.len();
+    for ipv6 in [false, true] {
+        let Some(consensus) = probe.consensus_for(ipv6) else { continue };
+        progress!(
+            "Discovered public {} address via STUN: {} ({} of {} servers agree)",
+            if ipv6 { "IPv6" } else { "IPv4" },
+            consensus,
+            probe.results.iter().filter(|result| result.mapped_address == consensus).count(),
+            total
+        );
+    }
+    for result in &probe.results {
+        progress!("  {} ({}) -> {} in {:?}", result.server, result.server_address, result.mapped_address, result.rtt);
+    }
+    for failure in &probe.failures {
+        progress!("  {}", failure);
+    }
+    if probe.disagrees() {
+        progress!("STUN servers report different mapped addresses - likely symmetric NAT");
+    }
+}
+
+// 每个地址族的第一个 TCP 监听端口，STUN 从同一端口发出
+fn tcp_listen_addresses(listen_addrs: &[Multiaddr]) -> Vec<SocketAddr> {
+    let mut locals: Vec<SocketAddr> = Vec::new();
+    for addr in listen_addrs {
+        let mut protocols = addr.iter();
+        let local = match (protocols.next(), protocols.next()) {
+            (Some(Protocol::Ip4(_)), Some(Protocol::Tcp(port))) => SocketAddr::new(IpAddr::from([0, 0, 0, 0]), port),
+            (Some(Protocol::Ip6(_)), Some(Protocol::Tcp(port))) => SocketAddr::new(IpAddr::from([0u16; 8]), port),
+            _ => continue,
+        };
+        if !locals.iter().any(|existing| existing.is_ipv6() == local.is_ipv6()) {
+            locals.push(local);
+        }
+    }
+    locals
+}
+
+// QUIC 监听地址的（地址族是否为 IPv6，UDP 端口）
+fn quic_listen_ports(listen_addrs: &[Multiaddr]) -> Vec<(bool, u16)> {
+    listen_addrs
+        .iter()
+        .filter(|addr| crate::address::is_quic_address(addr))
+        .filter_map(crate::address::socket_addr)
+        .map(|local| (local.is_ipv6(), local.port()))
+        .collect()
+}
+
+// UDP 映射保持了本地端口且各服务器看到的一致时，NAT 很可能对 QUIC 端口同样保持端口映射（或者本机就在公网上），
+// 以映射的公网 IP 和 QUIC 监听端口登记 /quic-v1 外部地址，其它节点就能优先通过 QUIC 连接本节点
+async fn register_quic_addresses(handle: &NodeHandle, udp: &StunProbe, local: SocketAddr, quic_ports: &[(bool, u16)]) {
+    let ipv6 = local.is_ipv6();
+    let Some(mapped) = udp.consensus_for(ipv6) else { return };
+    if udp.disagrees_for(ipv6) || mapped.port() != local.port() {
+        return;
+    }
+    for (_, port) in quic_ports.iter().filter(|(quic_ipv6, _)| *quic_ipv6 == ipv6) {
+        let address = Multiaddr::from(mapped.ip()).with(Protocol::Udp(*port)).with(Protocol::QuicV1);
+        match handle.add_external_address(address.clone()).await {
+            Ok(()) => progress!("Registered external address {}", address),
+            Err(e) => progress!("Failed to announce external address {}: {}", address, e),
+        }
+    }
+}
+
+// 发现节点的公网地址：按 stun_source 从 Swarm 的每个 TCP 监听端口（IPv4 和 IPv6）查询 STUN 服务器（TCP 和 UDP，端口复用），
+// 和/或询问 DHT 中开启 STUN 应答的节点（它们看到的是本节点连接时使用的监听端口映射）
+// 每个地址族中所有应答者看到的 TCP 映射一致时，把它登记为节点的外部地址并在 DHT 中宣告；UDP 映射还用来判断能否宣告 QUIC 地址
+// 返回 TCP 映射的查询结果；没有 TCP 结果时返回 UDP 查询结果
+pub async fn stun_from_listen_port(handle: &NodeHandle, config: &Config) -> crate::error::Result<StunProbe> {
+    // 代表监听端口 TCP 映射的结果
+    let mut probe = StunProbe::default();
+    let mut fallback: Option<crate::error::Result<StunProbe>> = None;
+
+    if config.stun_source.uses_servers() {
+        let mut records = load_stun_servers(config);
+        let servers = select_stun_servers(&mut records, DEFAULT_MAX_STUN_FAILURES);
+        let servers: Vec<&str> = servers.iter().map(String::as_str).collect();
+        let credentials = stun_credentials(&records);
+        let listen_addrs = handle.listen_addresses().await?;
+        let locals = tcp_listen_addresses(&listen_addrs);
+        let quic_ports = quic_listen_ports(&listen_addrs);
+        if locals.is_empty() {
+            progress!("No TCP listen address yet, querying STUN from an ephemeral port");
+            let outcome = perform_stun_request(&servers, &credentials).await;
+            record_stun_round(config, StunRound::from_outcomes([&outcome])).await;
+            fallback = Some(outcome);
+        }
+        let probes = join_all(locals.iter().map(|local| async {
+            tokio::join!(
+                probe_from(&servers, *local, StunTransport::Tcp, &credentials),
+                probe_from(&servers, *local, StunTransport::Udp, &credentials)
+            )
+        }))
+        .await;
+        record_stun_round(config, StunRound::from_outcomes(probes.iter().flat_map(|(tcp, udp)| [tcp, udp]))).await;
+        for (local, (tcp, udp)) in locals.iter().zip(probes) {
+            match &udp {
+                Ok(probe) => {
+                    progress!("STUN over UDP from port {}:", local);
+                    report_stun_probe(probe);
+                    register_quic_addresses(handle, probe, *local, &quic_ports).await;
+                }
+                Err(e) => progress!("STUN over UDP from port {} failed: {}", local, e),
+            }
+            match tcp {
+                Ok(tcp) => {
+                    progress!("STUN over TCP from listen port {}:", local);
+                    report_stun_probe(&tcp);
+                    probe.extend(tcp);
+                }
+                Err(e) => {
+                    progress!("STUN over TCP from port {} failed: {}", local, e);
+                    if !matches!(fallback, Some(Ok(_))) {
+                        fallback = Some(udp);
+                    }
+                }
+            }
+        }
+    }
+
+    if config.stun_source.uses_peers() {
+        match handle.peer_stun(DEFAULT_STUN_PEERS).await {
+            Ok(peers) => {
+                progress!("STUN via peers found in the DHT:");
+                report_stun_probe(&peers);
+                probe.extend(peers);
+            }
+            Err(e) => {
+                progress!("STUN via peers failed: {}", e);
+                probe.failures.push(e);
+            }
+        }
+    }
+
+    if probe.results.is_empty() {
+        return fallback.unwrap_or(Err(crate::error::Error::StunFailed(probe.failures)));
+    }
+
+    // 映射随目标变化时其它节点无法使用该地址；公网 IPv6 通常没有 NAT，映射就是监听地址本身
+    for ipv6 in [false, true] {
+        let Some(mapped) = probe.consensus_for(ipv6) else { continue };
+        if probe.disagrees_for(ipv6) {
+            continue;
+        }
+        let address = Multiaddr::from(mapped.ip()).with(Protocol::Tcp(mapped.port()));
+        match handle.add_external_address(address.clone()).await {
+            Ok(()) => progress!("Registered external address {}", address),
+            Err(e) => progress!("Failed to announce external address {}: {}", address, e),
+        }
+    }
+    Ok(probe)
+}
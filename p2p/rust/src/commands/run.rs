@@ -0,0 +1,414 @@
+// run.rs - 运行节点，持续发现节点并检测节点间通信
+use crate::autonat::ReachabilityStatus;
+use crate::bootstrap::{peer_id_from_multiaddr, save_bootstrap_nodes_to_path};
+use crate::config::{Config, KademliaMode};
+use crate::node::NodeEvent;
+use crate::output;
+use crate::performance_benchmark::{TransportBenchmark, TransportMetrics};
+use crate::progress;
+use crate::signal::{ShutdownSignal, ShutdownSignals};
+use libp2p::{
+    futures::StreamExt,
+    identity::Keypair,
+    kad::{BootstrapError, BootstrapOk, GetClosestPeersError, GetClosestPeersOk},
+    ping::Failure as PingFailure, // Ping 失败类型
+};
+use serde::Serialize;
+use std::time::Instant;
+use tokio::time::interval;
+
+// 默认的 DHT Bootstrap 节点，可通过配置项 bootstrap_addrs 替换
+// 注意：这些地址需要包含 PeerId。如果原始地址没有，我们需要先获取。
+// 为简化，这里假设地址是有效的。在实际应用中，你可能需要先通过其他方式（如 DHT 查询）获取完整的 multiaddr。
+const DEFAULT_BOOTSTRAPS: [&str; 31] = [
+    // 原始 Bootstrap 列表
+    "/ip4/34.197.35.250/tcp/6880",
+    "/ip4/72.46.58.63/tcp/51413",
+    "/ip4/46.53.251.68/tcp/16970",
+    "/ip4/191.95.16.229/tcp/55998",
+    "/ip4/79.173.94.111/tcp/1438",
+    "/ip4/45.233.86.50/tcp/61995",
+    "/ip4/178.162.174.28/tcp/28013",
+    "/ip4/178.162.174.240/tcp/28006",
+    "/ip4/72.21.17.101/tcp/22643",
+    "/ip4/31.181.42.46/tcp/22566",
+    "/ip4/67.213.106.46/tcp/61956",
+    "/ip4/201.131.172.249/tcp/53567",
+    "/ip4/185.203.152.184/tcp/2003",
+    "/ip4/68.146.23.207/tcp/42107",
+    "/ip4/51.195.222.183/tcp/8653",
+    "/ip4/85.17.170.48/tcp/28005",
+    "/ip4/87.98.162.88/tcp/6881",
+    "/ip4/185.145.245.121/tcp/8656",
+    "/ip4/52.201.45.189/tcp/6880",
+
+    // 从 BitTorrent 生态中获取的一些公共 DHT 节点
+    // 这些节点可能需要先通过某种方式获取 PeerId，但在实际应用中可以作为备选
+    // 注意：这些地址可能随时变化，需要定期更新
+    "/dnsaddr/bootstrap.libp2p.io/p2p/QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN",
+    "/dnsaddr/bootstrap.libp2p.io/p2p/QmQCU2EcMqAqQPR2i9bChDtGNJchTbq5TbXJJ16u19uLTa",
+    "/dnsaddr/bootstrap.libp2p.io/p2p/QmbLHAnMoJPWSCR5Zhtx6BHJX9KiKNN6tpvbUcqanj75Nb",
+    "/dnsaddr/bootstrap.libp2p.io/p2p/QmcZf59bWwK5XFi76CZX8cbJ4BhTzzA3gU1ZjYZcYW3dwt",
+
+    // IPFS 默认 Bootstrap 节点 (部分)
+    "/ip4/104.131.131.82/tcp/4001/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmPYiLMwpSM",
+    "/ip4/104.236.179.241/tcp/4001/p2p/QmSoLPppuBtQSGwKDZT2M73ULpjvfd3aZ6ha4oFGL1KrGM",
+    "/ip4/128.199.219.111/tcp/4001/p2p/QmSoLSafTMBsPKadTEgaXctDQVcqN88CNLHXMkTNwMKPnu",
+    "/ip4/104.236.76.40/tcp/4001/p2p/QmSoLV4Bbm51jM9C4gDYZQ9Cy3U6aXMJDAbzgu2fzaDs64",
+    // 同一批节点的 IPv6 地址，双方都有公网 IPv6 时优先使用
+    "/ip6/2604:a880:1:20::203:d001/tcp/4001/p2p/QmSoLPppuBtQSGwKDZT2M73ULpjvfd3aZ6ha4oFGL1KrGM",
+    "/ip6/2400:6180:0:d0::151:6001/tcp/4001/p2p/QmSoLSafTMBsPKadTEgaXctDQVcqN88CNLHXMkTNwMKPnu",
+    "/ip6/2604:a880:800:10::4a:5001/tcp/4001/p2p/QmSoLV4Bbm51jM9C4gDYZQ9Cy3U6aXMJDAbzgu2fzaDs64",
+    "/ip6/2a03:b0c0:0:1010::23:1001/tcp/4001/p2p/QmSoLer265NRgSp2LA3dPaeykiS1J6DifTC88f5uVQKNAd",
+];
+
+// bench 默认的 Bootstrap 节点地址，可通过配置项 bootstrap_addrs 替换
+const BENCH_BOOTSTRAPS: [&str; 28] = [
+    // IPFS Bootstrappers - 多个可替换的服务器
+    "/ip4/104.131.131.82/tcp/4001/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmPYiLMwpSM", // 位于美国的服务器
+    "/ip4/104.236.179.241/tcp/4001/p2p/QmSoLPppuBtQSGwKDZT2M73ULpjvfd3aZ6ha4oFGL1KrGM", // 位于美国的服务器
+    "/ip4/128.199.219.111/tcp/4001/p2p/QmSoLSafTMBsPKadTEgaXctDQVcqN88CNLHXMkTNwMKPnu", // 位于英国的服务器
+    "/ip4/104.236.76.40/tcp/4001/p2p/QmSoLV4Bbm51jM9C4gDYZQ9Cy3U6aXMJDAbzgu2fzaDs64", // 位于美国的服务器
+    "/ip4/178.62.158.247/tcp/4001/p2p/QmSoLer265NRgSp2LA3dPaeykiS1J6DifTC88f5uVQKNAd", // 位于新加坡的服务器
+    // 上面 IPFS 节点的 IPv6 地址
+    "/ip6/2604:a880:1:20::203:d001/tcp/4001/p2p/QmSoLPppuBtQSGwKDZT2M73ULpjvfd3aZ6ha4oFGL1KrGM",
+    "/ip6/2400:6180:0:d0::151:6001/tcp/4001/p2p/QmSoLSafTMBsPKadTEgaXctDQVcqN88CNLHXMkTNwMKPnu",
+    "/ip6/2604:a880:800:10::4a:5001/tcp/4001/p2p/QmSoLV4Bbm51jM9C4gDYZQ9Cy3U6aXMJDAbzgu2fzaDs64",
+    "/ip6/2a03:b0c0:0:1010::23:1001/tcp/4001/p2p/QmSoLer265NRgSp2LA3dPaeykiS1J6DifTC88f5uVQKNAd",
+    // Libp2p Test Network Bootstrap Nodes
+    "/ip4/34.197.35.250/tcp/6880", // 位于美国的服务器
+    "/ip4/72.46.58.63/tcp/51413", // 位于美国的服务器
+    "/ip4/46.53.251.68/tcp/16970", // 位于英国的服务器
+    "/ip4/191.95.16.229/tcp/55998", // 位于德国的服务器
+    "/ip4/79.173.94.111/tcp/1438", // 位于俄罗斯的服务器
+    "/ip4/45.233.86.50/tcp/61995", // 位于巴西的服务器
+    "/ip4/178.162.174.28/tcp/28013", // 位于法国的服务器
+    "/ip4/178.162.174.240/tcp/28006", // 位于法国的服务器
+    "/ip4/72.21.17.101/tcp/22643", // 位于美国的服务器
+    "/ip4/31.181.42.46/tcp/22566", // 位于俄罗斯的服务器
+    "/ip4/67.213.106.46/tcp/61956", // 位于美国的服务器
+    "/ip4/201.131.172.249/tcp/53567", // 位于巴西的服务器
+    "/ip4/185.203.152.184/tcp/2003", // 位于德国的服务器
+    "/ip4/68.146.23.207/tcp/42107", // 位于美国的服务器
+    "/ip4/51.195.222.183/tcp/8653", // 位于法国的服务器
+    "/ip4/85.17.170.48/tcp/28005", // 位于荷兰的服务器
+    "/ip4/87.98.162.88/tcp/6881", // 位于法国的服务器
+    "/ip4/185.145.245.121/tcp/8656", // 位于德国的服务器
+    "/ip4/52.201.45.189/tcp/6880", // 位于美国的服务器
+];
+
+// run 子命令的默认配置
+pub fn default_config() -> Config {
+    Config {
+        bootstrap_addrs: DEFAULT_BOOTSTRAPS.iter().map(|s| s.to_string()).collect(),
+        query_timeout_secs: Some(5 * 60), // 增加查询超时时间
+        ..Config::default()
+    }
+}
+
+// bench 子命令的默认配置，Kademlia 为客户端模式，不接受其他节点的存储请求
+pub fn bench_config() -> Config {
+    Config {
+        bootstrap_addrs: BENCH_BOOTSTRAPS.iter().map(|s| s.to_string()).collect(),
+        kademlia_mode: KademliaMode::Client,
+        ..Config::default()
+    }
+}
+
+// 节点运行结果
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub peer_id: String,
+    pub communication_success: bool,
+    pub connection_attempts: u32,
+    pub max_connection_attempts: u32,
+    pub duration_secs: u64,
+    pub signal: Option<ShutdownSignal>,
+    // AutoNAT 对本节点可达性的最终结论，未编译 AutoNAT 时为 unknown
+    pub reachability: ReachabilityStatus,
+    // 按传输方式分别统计的连接和 Ping 结果
+    pub transports: Vec<TransportMetrics>,
+}
+
+impl RunSummary {
+    // 被信号中断时使用独立的退出码
+    pub fn exit_code(&self) -> i32 {
+        self.signal.map_or(0, ShutdownSignal::exit_code)
+    }
+
+    pub fn print(&self) {
+        output::print_result(self, |summary| {
+            println!("Final node-to-node communication status:");
+            println!("  Success: {}", summary.communication_success);
+            println!("  Attempts: {}/{}", summary.connection_attempts, summary.max_connection_attempts);
+            println!("  Reachability: {}", summary.reachability);
+            for metrics in &summary.transports {
+                println!(
+                    "  {}: {} connections, {} closed, {} pings ({} failed), avg ping {:.1} ms",
+                    metrics.transport,
+                    metrics.connections,
+                    metrics.connections_closed,
+                    metrics.pings,
+                    metrics.ping_failures,
+                    metrics.avg_ping_ms
+                );
+            }
+        });
+    }
+}
+
+// 运行节点直到节点间通信成功、达到最大运行时间或连接尝试次数，或收到退出信号
+pub async fn execute(config: &Config, keypair: Keypair) -> Result<RunSummary, Box<dyn std::error::Error>> {
+    let start_time = Instant::now();
+    // 设置运行时间限制(分钟)
+    let max_runtime_minutes = config.max_runtime_minutes;
+
+    // 节点间通信成功标志
+    let mut communication_success = false;
+
+    // 节点连接尝试计数器
+    let mut connection_attempts = 0;
+    let max_connection_attempts = config.max_connection_attempts;
+
+    // 存储已知的 Bootstrap 节点地址
+    let bootstrap_addresses: Vec<String> = config
+        .bootstrap_multiaddrs()
+        .into_iter()
+        .filter(|addr| peer_id_from_multiaddr(addr).is_some())
+        .map(|addr| addr.to_string())
+        .collect();
+
+    let local_peer_id = keypair.public().to_peer_id();
+    let (handle, mut events) = super::start_node(config, keypair).await?;
+
+    // 启动一个计时器，定期执行 Bootstrap
+    let mut bootstrap_timer = interval(config.bootstrap_interval());
+    bootstrap_timer.tick().await; // 消费第一个 tick
+    // 启动一个计时器，定期查找自己的 PeerId 以保持网络连接
+    let mut refresh_timer = interval(config.refresh_interval());
+    refresh_timer.tick().await; // 消费第一个 tick
+    // 启动一个计时器，定期输出 Bootstrap 地址列表
+    let mut address_output_timer = interval(config.address_interval());
+    address_output_timer.tick().await; // 消费第一个 tick
+
+    // 标记是否已执行初始 Bootstrap
+    let mut bootstrapped = false;
+
+    // 注册退出信号，收到 SIGINT/SIGTERM 时保存状态后退出
+    let mut signals = ShutdownSignals::new()?;
+    let mut received_signal = None;
+
+    // 按传输方式统计连接
+    let mut transports = TransportBenchmark::new();
+
+    // AutoNAT 确认的可达性；STUN 得到的映射地址不能说明其它节点能连进来
+    let mut reachability = ReachabilityStatus::default();
+
+    // 后台运行的 STUN 请求，退出主循环时取消
+    let mut stun_task = super::StunTask::default();
+
+    // 实现节点发现和连接逻辑
+    loop {
+        // 检查是否达到最大运行时间
+        if start_time.elapsed() > config.max_runtime() {
+            progress!("Maximum runtime of {} minutes reached. Shutting down...", max_runtime_minutes);
+            break;
+        }
+
+        // 检查节点间通信是否成功
+        if communication_success {
+            progress!("Node-to-node communication succeeded. Shutting down...");
+            break;
+        }
+
+        // 检查是否超过最大连接尝试次数
+        if connection_attempts >= max_connection_attempts {
+            progress!("Maximum connection attempts ({}) reached. Shutting down...", max_connection_attempts);
+            break;
+        }
+
+        tokio::select! {
+            // 收到退出信号时停止主循环
+            signal = signals.recv() => {
+                progress!("Received {}. Shutting down gracefully...", signal);
+                received_signal = Some(signal);
+                break;
+            }
+            event = events.next() => {
+                let Some(event) = event else {
+                    progress!("Node event loop stopped unexpectedly.");
+                    break;
+                };
+                match &event {
+                    NodeEvent::ConnectionEstablished { peer_id, endpoint } => transports.connection_established(*peer_id, endpoint),
+                    NodeEvent::ConnectionClosed { peer_id, .. } => transports.connection_closed(*peer_id),
+                    NodeEvent::Ping { peer, result } => transports.ping(*peer, result),
+                    _ => {}
+                }
+                match event {
+                    NodeEvent::NewListenAddr { address } => {
+                        progress!("Node {} listening on {:?}", local_peer_id, address);
+                    }
+                    // 处理 Kademlia 事件
+                    NodeEvent::Bootstrap(Ok(BootstrapOk { peer, .. })) => {
+                        progress!("Successfully bootstrapped with {:?}", peer);
+                    }
+                    NodeEvent::Bootstrap(Err(BootstrapError::Timeout { .. })) => {
+                        progress!("Bootstrap query timed out");
+                    }
+                    NodeEvent::ClosestPeers(Ok(GetClosestPeersOk { key, peers })) => {
+                        progress!("Found {} closest peers for {:?}", peers.len(), key);
+                        // 这里可以处理找到的节点，例如尝试连接
+                    }
+                    NodeEvent::ClosestPeers(Err(GetClosestPeersError::Timeout { key, .. })) => {
+                        progress!("GetClosestPeers query for {:?} timed out", key);
+                    }
+                    NodeEvent::RoutingUpdated { peer } => {
+                        progress!("Routing table updated with peer: {}", peer);
+                    }
+                    // 处理 Ping 事件
+                    NodeEvent::Ping { peer, result: Ok(duration) } => {
+                        progress!("Ping succeeded with {} in {:?}", peer, duration);
+                        // Ping 成功表明节点间通信成功，可能意味着 NAT 穿透成功
+                        communication_success = true;
+                        progress!("Node-to-node communication success detected through successful ping to peer: {}", peer);
+                        // 重置连接尝试计数器，因为连接成功了
+                        connection_attempts = 0;
+                    }
+                    NodeEvent::Ping { peer, result: Err(PingFailure::Timeout) } => {
+                        progress!("Ping timeout with {}", peer);
+                    }
+                    NodeEvent::Ping { peer, result: Err(PingFailure::Unsupported) } => {
+                        progress!("Peer {} does not support ping protocol", peer);
+                    }
+                    NodeEvent::Ping { peer, result: Err(PingFailure::Other { error }) } => {
+                        progress!("Ping failed with {} due to: {:?}", peer, error);
+                    }
+                    NodeEvent::ConnectionEstablished { peer_id, endpoint } => {
+                        progress!("Connection established with {} at {:?}", peer_id, endpoint);
+                    }
+                    NodeEvent::ConnectionClosed { peer_id, cause } => {
+                        progress!("Connection closed with {}: {:?}", peer_id, cause);
+                    }
+                    NodeEvent::OutgoingConnectionError { peer_id, error } => {
+                        progress!("Outgoing connection error to {:?}: {}", peer_id, error);
+                        connection_attempts += 1; // 增加连接尝试计数器
+                    }
+                    NodeEvent::IncomingConnectionError { local_addr, send_back_addr, error } => {
+                        progress!("Incoming connection error from {} to {}: {:?}", send_back_addr, local_addr, error);
+                        connection_attempts += 1; // 增加连接尝试计数器
+                    }
+                    NodeEvent::RelayFailed { error } => {
+                        progress!("TURN relay unavailable: {}", error);
+                    }
+                    NodeEvent::RelayPermissionFailed { error } => {
+                        progress!("Failed to install TURN permissions: {}", error);
+                    }
+                    NodeEvent::RelayReservation { relay, renewal } => {
+                        progress!("{} reservation on relay {}", if renewal { "Renewed" } else { "Accepted" }, relay);
+                    }
+                    NodeEvent::HolePunch { peer_id, result: Ok(()) } => {
+                        progress!("Hole punching with {} succeeded, now connected directly", peer_id);
+                    }
+                    NodeEvent::HolePunch { result: Err(e), .. } => {
+                        progress!("{}", e);
+                    }
+                    NodeEvent::RelayServer(event) => {
+                        progress!("Relay server {}", event);
+                    }
+                    NodeEvent::Identified { peer_id, identity } => {
+                        progress!("Identified {} as {} ({})", peer_id, identity.agent_version, identity.protocol_version);
+                    }
+                    NodeEvent::Reachability(status) => {
+                        progress!("Reachability: {}", status);
+                        // 其它节点回拨成功并得到确认，说明它们能直接连接本节点
+                        if status.is_public() {
+                            communication_success = true;
+                            progress!("Node-to-node communication success confirmed by AutoNAT dial-back");
+                        }
+                        reachability = status;
+                    }
+                }
+            }
+            // 定期执行 Bootstrap
+            _ = bootstrap_timer.tick() => {
+                if !bootstrapped {
+                    progress!("Starting initial bootstrap...");
+                    // 启动 Bootstrap 过程
+                    if handle.bootstrap().await.is_ok() {
+                        bootstrapped = true;
+                    } else {
+                        progress!("Failed to start bootstrap.");
+                    }
+                }
+            }
+            // 定期刷新自己的 PeerId 查询
+            _ = refresh_timer.tick() => {
+                progress!("Refreshing peer discovery...");
+                handle.get_closest_peers(local_peer_id)?;
+            }
+            // 后台 STUN 请求完成
+            result = stun_task.finished() => {
+                match result {
+                    Ok(_) => {
+                        // 映射地址只说明出站方向可用，是否可达由 AutoNAT 回拨确认
+                        progress!("Public address discovered via STUN, reachability: {}", reachability);
+                    }
+                    Err(e) => {
+                        progress!("STUN request failed: {}", e);
+                        connection_attempts += 1; // 增加连接尝试计数器
+                    }
+                }
+            }
+            // 定期输出 Bootstrap 地址列表并执行 STUN 请求
+            _ = address_output_timer.tick() => {
+                progress!("Current known bootstrap addresses:");
+                for addr in &bootstrap_addresses {
+                    progress!("  {}", addr);
+                }
+
+                // 在后台执行 STUN 请求以发现公网地址
+                if !stun_task.start(&handle, config) {
+                    progress!("Previous STUN request still running, skipping this round");
+                }
+
+                // 与其它节点交换可用的 STUN 服务器
+                tokio::spawn(super::share_stun_servers(handle.clone(), config.clone()));
+
+                // 保存Bootstrap节点信息到JSON文件
+                let (nodes, path) = (handle.bootstrap_nodes().await?, config.bootstraps_path());
+                match super::blocking(move || save_bootstrap_nodes_to_path(&nodes, &path)).await {
+                    Ok(_) => {
+                        progress!("Bootstrap node information saved to {}", config.bootstraps_path().display());
+                    }
+                    Err(e) => {
+                        progress!("Failed to save bootstrap node information to JSON file: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    drop(stun_task);
+
+    // 关闭节点并保存最终的 Bootstrap 节点列表和路由表
+    let snapshot = handle.shutdown().await?;
+    progress!("Node shutdown complete.");
+    let (bootstraps_path, routing_table_path) = (config.bootstraps_path(), config.routing_table_path());
+    let paths = (bootstraps_path.clone(), routing_table_path.clone());
+    match super::blocking(move || snapshot.save_to(&paths.0, &paths.1)).await {
+        Ok(_) => progress!("Final state saved to {} and {}", bootstraps_path.display(), routing_table_path.display()),
+        Err(e) => progress!("Failed to save final node state: {}", e),
+    }
+
+    Ok(RunSummary {
+        peer_id: local_peer_id.to_string(),
+        communication_success,
+        connection_attempts,
+        max_connection_attempts,
+        duration_secs: start_time.elapsed().as_secs(),
+        signal: received_signal,
+        reachability,
+        transports: transports.results(),
+    })
+}
@@ -0,0 +1,83 @@
+// stun.rs - 通过 STUN 服务器查询本机的公网地址
+use crate::config::Config;
+use crate::output;
+use crate::stun::perform_stun_request;
+use crate::stun_servers::{select_stun_servers, stun_credentials, StunRound, DEFAULT_MAX_STUN_FAILURES};
+use serde::Serialize;
+use std::error::Error;
+
+// 单个服务器的应答
+#[derive(Debug, Clone, Serialize)]
+pub struct StunResponse {
+    pub server: String,
+    pub server_address: String,
+    pub mapped_address: String,
+    pub rtt_ms: u64,
+}
+
+// STUN 查询结果
+#[derive(Debug, Clone, Serialize)]
+pub struct StunSummary {
+    // 多数服务器报告的公网地址，有 IPv4 结果时为 IPv4 地址
+    pub public_address: String,
+    // 多数服务器报告的公网 IPv6 地址，没有 IPv6 连接时为 None
+    pub public_ipv6_address: Option<String>,
+    // 所有应答的服务器报告的映射地址相同
+    pub consistent: bool,
+    pub responses: Vec<StunResponse>,
+    pub failures: Vec<String>,
+    // 实际查询的服务器，按健康记录排序
+    pub servers: Vec<String>,
+}
+
+impl StunSummary {
+    pub fn print(&self) {
+        output::print_result(self, |summary| {
+            println!("Discovered public address via STUN: {}", summary.public_address);
+            if let Some(ipv6) = &summary.public_ipv6_address {
+                println!("Discovered public IPv6 address via STUN: {}", ipv6);
+            }
+            for response in &summary.responses {
+                println!(
+                    "  {} ({}) -> {} in {} ms",
+                    response.server, response.server_address, response.mapped_address, response.rtt_ms
+                );
+            }
+            for failure in &summary.failures {
+                println!("  {}", failure);
+            }
+            if !summary.consistent {
+                println!("STUN servers report different mapped addresses - likely symmetric NAT");
+            }
+        });
+    }
+}
+
+// 分别通过 IPv4 和 IPv6 并发查询 STUN 服务器（按健康记录排序，跳过连续失败的服务器），
+// 汇总各服务器报告的映射地址并更新 STUN_SERVERS.json
+pub async fn execute(config: &Config) -> Result<StunSummary, Box<dyn Error>> {
+    let mut records = super::load_stun_servers(config);
+    let servers = select_stun_servers(&mut records, DEFAULT_MAX_STUN_FAILURES);
+    let servers: Vec<&str> = servers.iter().map(String::as_str).collect();
+    let outcome = perform_stun_request(&servers, &stun_credentials(&records)).await;
+    super::record_stun_round(config, StunRound::from_outcomes([&outcome])).await;
+    let probe = outcome?;
+    let public_address = probe.consensus().map(|addr| addr.to_string()).unwrap_or_default();
+    Ok(StunSummary {
+        public_address,
+        public_ipv6_address: probe.consensus_for(true).map(|addr| addr.to_string()),
+        consistent: !probe.disagrees(),
+        responses: probe
+            .results
+            .iter()
+            .map(|result| StunResponse {
+                server: result.server.clone(),
+                server_address: result.server_address.to_string(),
+                mapped_address: result.mapped_address.to_string(),
+                rtt_ms: result.rtt.as_millis() as u64,
+            })
+            .collect(),
+        failures: probe.failures.iter().map(|e| e.to_string()).collect(),
+        servers: servers.iter().map(|server| server.to_string()).collect(),
+    })
+}
@@ -0,0 +1,559 @@
+// config.rs - 节点配置
+// 优先级从低到高：程序内置默认值、TOML 配置文件、P2P_ 开头的环境变量、命令行参数
+use libp2p::{Multiaddr, PeerId, kad::Mode};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::autonat;
+use crate::bootstrap::{BOOTSTRAPS_FILE, ROUTING_TABLE_FILE};
+use crate::relay::{
+    self, RelayServerConfig, DEFAULT_RELAY_MAX_CIRCUITS, DEFAULT_RELAY_MAX_CIRCUIT_BYTES,
+    DEFAULT_RELAY_MAX_CIRCUIT_DURATION, DEFAULT_RELAY_MAX_RESERVATIONS, DEFAULT_RELAY_RESERVATIONS,
+};
+use crate::report::NAT_TRAVERSAL_REPORT_FILE;
+use crate::stun::DEFAULT_STUN_SERVERS;
+use crate::stun_auth::StunCredentials;
+use crate::stun_server::DEFAULT_STUN_RESPONDER_PORT;
+use crate::stun_servers::STUN_SERVERS_FILE;
+
+// 当前目录下存在时自动加载的配置文件
+pub const CONFIG_FILE: &str = "p2p.toml";
+// 指定配置文件路径的环境变量
+pub const CONFIG_ENV: &str = "P2P_CONFIG";
+// 单项配置的环境变量前缀，例如 P2P_MAX_RUNTIME_MINUTES
+pub const ENV_PREFIX: &str = "P2P_";
+
+// 配置错误时的退出码（sysexits.h 中的 EX_CONFIG）
+pub const EXIT_CONFIG_ERROR: i32 = 78;
+
+// 最长运行时间的上限（一年），换算成秒和计时器截止时间时都不会溢出
+pub const MAX_RUNTIME_MINUTES: u64 = 365 * 24 * 60;
+
+// 所有配置项名称，命令行中可以用 - 代替 _
+pub const CONFIG_KEYS: [&str; 29] = [
+    "listen_addrs",
+    "bootstrap_addrs",
+    "stun_servers",
+    "stun_source",
+    "stun_responder",
+    "stun_responder_port",
+    "turn_server",
+    "turn_username",
+    "turn_password",
+    "relay_reservations",
+    "relay_server",
+    "relay_max_reservations",
+    "relay_max_circuits",
+    "relay_max_circuit_bytes",
+    "relay_max_circuit_duration_secs",
+    "relay_allowlist",
+    "kademlia_mode",
+    "query_timeout_secs",
+    "ping_interval_secs",
+    "max_runtime_minutes",
+    "max_connection_attempts",
+    "bootstrap_interval_secs",
+    "refresh_interval_secs",
+    "address_interval_secs",
+    "data_dir",
+    "bootstraps_file",
+    "stun_servers_file",
+    "routing_table_file",
+    "report_file",
+];
+
+// Kademlia 运行模式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum KademliaMode {
+    Client,
+    Server,
+    // 先作为客户端，AutoNAT 确认公网可达后切换为服务器，确认不可达时切换回客户端
+    Auto,
+}
+
+impl FromStr for KademliaMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "client" => Ok(KademliaMode::Client),
+            "server" => Ok(KademliaMode::Server),
+            "auto" => Ok(KademliaMode::Auto),
+            _ => Err("expected \"client\", \"server\" or \"auto\"".to_string()),
+        }
+    }
+}
+
+impl From<KademliaMode> for Mode {
+    fn from(mode: KademliaMode) -> Self {
+        match mode {
+            KademliaMode::Client | KademliaMode::Auto => Mode::Client,
+            KademliaMode::Server => Mode::Server,
+        }
+    }
+}
+
+// 发现公网地址时询问的对象
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StunSource {
+    // 配置的 STUN 服务器
+    Servers,
+    // 通过 DHT 找到的开启 STUN 应答的节点
+    Peers,
+    // 两者都询问
+    Both,
+}
+
+impl StunSource {
+    pub fn uses_servers(self) -> bool {
+        self != StunSource::Peers
+    }
+
+    pub fn uses_peers(self) -> bool {
+        self != StunSource::Servers
+    }
+}
+
+impl FromStr for StunSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "servers" => Ok(StunSource::Servers),
+            "peers" => Ok(StunSource::Peers),
+            "both" => Ok(StunSource::Both),
+            _ => Err("expected \"servers\", \"peers\" or \"both\"".to_string()),
+        }
+    }
+}
+
+// 默认的 Kademlia 模式：编译了 AutoNAT 时按确认的可达性切换，否则作为服务器
+#[cfg(not(feature = "autonat"))]
+pub const DEFAULT_KADEMLIA_MODE: KademliaMode = KademliaMode::Server;
+#[cfg(feature = "autonat")]
+pub const DEFAULT_KADEMLIA_MODE: KademliaMode = KademliaMode::Auto;
+
+// 默认监听地址：IPv4 和 IPv6 的随机 TCP 端口，编译了 QUIC 时再加上两个地址族的随机 UDP 端口
+#[cfg(not(feature = "quic"))]
+pub const DEFAULT_LISTEN_ADDRS: &[&str] = &["/ip4/0.0.0.0/tcp/0", "/ip6/::/tcp/0"];
+#[cfg(feature = "quic")]
+pub const DEFAULT_LISTEN_ADDRS: &[&str] =
+    &["/ip4/0.0.0.0/tcp/0", "/ip6/::/tcp/0", "/ip4/0.0.0.0/udp/0/quic-v1", "/ip6/::/udp/0/quic-v1"];
+
+// 节点配置
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub listen_addrs: Vec<String>,
+    pub bootstrap_addrs: Vec<String>,
+    pub stun_servers: Vec<String>, // host:port
+    pub stun_source: StunSource,
+    pub stun_responder: bool, // 是否应答其它节点的 STUN 请求
+    pub stun_responder_port: u16, // STUN 应答的 UDP 端口
+    pub turn_server: Option<String>, // host:port，设置后经 TURN 中继接受其它节点的连接
+    pub turn_username: Option<String>,
+    pub turn_password: Option<String>,
+    pub relay_reservations: usize, // 在几个中继节点上预留位置，0 为不预留
+    pub relay_server: bool, // 是否为其它节点提供 Circuit Relay v2 中继服务
+    pub relay_max_reservations: usize,
+    pub relay_max_circuits: usize,
+    pub relay_max_circuit_bytes: u64, // 每条转发连接每个方向最多转发的字节数
+    pub relay_max_circuit_duration_secs: u64,
+    pub relay_allowlist: Vec<String>, // 允许使用中继的 PeerId，为空时不限制
+    pub kademlia_mode: KademliaMode,
+    pub query_timeout_secs: Option<u64>, // 未设置时使用 libp2p 默认值
+    pub ping_interval_secs: u64,
+    pub max_runtime_minutes: u64,
+    pub max_connection_attempts: u32,
+    pub bootstrap_interval_secs: u64,
+    pub refresh_interval_secs: u64,
+    pub address_interval_secs: u64,
+    pub data_dir: PathBuf, // 输出文件所在目录，下面的相对路径都相对于该目录
+    pub bootstraps_file: PathBuf,
+    pub stun_servers_file: PathBuf,
+    pub routing_table_file: PathBuf,
+    pub report_file: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen_addrs: DEFAULT_LISTEN_ADDRS.iter().map(|s| s.to_string()).collect(),
+            bootstrap_addrs: Vec::new(),
+            stun_servers: DEFAULT_STUN_SERVERS.iter().map(|s| s.to_string()).collect(),
+            stun_source: StunSource::Servers,
+            stun_responder: false,
+            stun_responder_port: DEFAULT_STUN_RESPONDER_PORT,
+            turn_server: None,
+            turn_username: None,
+            turn_password: None,
+            relay_reservations: DEFAULT_RELAY_RESERVATIONS,
+            relay_server: false,
+            relay_max_reservations: DEFAULT_RELAY_MAX_RESERVATIONS,
+            relay_max_circuits: DEFAULT_RELAY_MAX_CIRCUITS,
+            relay_max_circuit_bytes: DEFAULT_RELAY_MAX_CIRCUIT_BYTES,
+            relay_max_circuit_duration_secs: DEFAULT_RELAY_MAX_CIRCUIT_DURATION.as_secs(),
+            relay_allowlist: Vec::new(),
+            kademlia_mode: DEFAULT_KADEMLIA_MODE,
+            query_timeout_secs: None,
+            ping_interval_secs: 10,
+            max_runtime_minutes: 30,
+            max_connection_attempts: 10,
+            bootstrap_interval_secs: 10,
+            refresh_interval_secs: 60,
+            address_interval_secs: 60,
+            data_dir: PathBuf::from("."),
+            bootstraps_file: PathBuf::from(BOOTSTRAPS_FILE),
+            stun_servers_file: PathBuf::from(STUN_SERVERS_FILE),
+            routing_table_file: PathBuf::from(ROUTING_TABLE_FILE),
+            report_file: PathBuf::from(NAT_TRAVERSAL_REPORT_FILE),
+        }
+    }
+}
+
+// 配置加载或校验失败的原因
+#[derive(Debug)]
+pub enum ConfigError {
+    // 配置文件无法读取
+    Io { path: PathBuf, source: std::io::Error },
+    // 配置文件不是合法的 TOML 或类型不符
+    Parse { path: PathBuf, message: String },
+    // 未知的配置项，origin 说明来源（文件、环境变量或命令行）
+    UnknownKey { key: String, origin: String },
+    // 命令行参数缺少值
+    MissingValue(String),
+    // 配置值不合法
+    Invalid { key: String, value: String, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "cannot read config file {}: {}", path.display(), source),
+            ConfigError::Parse { path, message } => write!(f, "invalid config file {}: {}", path.display(), message),
+            ConfigError::UnknownKey { key, origin } => write!(
+                f,
+                "unknown config key `{}` in {} (known keys: {})",
+                key,
+                origin,
+                CONFIG_KEYS.join(", ")
+            ),
+            ConfigError::MissingValue(flag) => write!(f, "missing value for {}", flag),
+            ConfigError::Invalid { key, value, reason } => {
+                write!(f, "invalid value `{}` for `{}`: {}", value, key, reason)
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+fn invalid(key: &str, value: impl fmt::Display, reason: impl fmt::Display) -> ConfigError {
+    ConfigError::Invalid { key: key.to_string(), value: value.to_string(), reason: reason.to_string() }
+}
+
+// host:port 形式的服务器地址，端口不能为 0
+pub fn is_host_port(value: &str) -> bool {
+    value
+        .rsplit_once(':')
+        .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok_and(|p| p != 0))
+}
+
+fn parse_number<T: FromStr>(key: &str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: fmt::Display,
+{
+    value.trim().parse().map_err(|e| invalid(key, value, e))
+}
+
+// 逗号分隔的列表，空字符串表示空列表
+fn parse_list(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect()
+}
+
+// 空字符串表示未设置
+fn parse_optional(value: &str) -> Option<String> {
+    Some(value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+impl Config {
+    // 从进程参数和环境变量加载配置，返回配置和剩余的位置参数
+    pub fn load(defaults: Config) -> Result<(Config, Vec<String>), ConfigError> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        Self::load_from(defaults, &args, |name| std::env::var(name).ok())
+    }
+
+    // 从给定的参数和环境变量加载配置
+    // 参数中 --config FILE 指定配置文件，--<key> VALUE 或 --<key>=VALUE 覆盖单项配置
+    pub fn load_from(
+        defaults: Config,
+        args: &[String],
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<(Config, Vec<String>), ConfigError> {
+        let mut config_path = None;
+        let mut overrides = Vec::new();
+        let mut positional = Vec::new();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                positional.push(arg.clone());
+                continue;
+            };
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None => {
+                    let value = args.next().ok_or_else(|| ConfigError::MissingValue(arg.clone()))?;
+                    (flag.to_string(), value.clone())
+                }
+            };
+            if name == "config" {
+                config_path = Some(PathBuf::from(value));
+            } else {
+                overrides.push((name.replace('-', "_"), value));
+            }
+        }
+
+        let mut config = Self::load_file_and_env(defaults, config_path, env)?;
+        for (key, value) in overrides {
+            if !CONFIG_KEYS.contains(&key.as_str()) {
+                return Err(ConfigError::UnknownKey { key, origin: "command line".to_string() });
+            }
+            config.set(&key, &value)?;
+        }
+
+        config.validate()?;
+        Ok((config, positional))
+    }
+
+    // 在 defaults 基础上应用配置文件和环境变量，不做校验，调用方应用命令行参数后再调用 validate
+    // 配置文件：config_path（命令行）> 环境变量 > 当前目录下的默认文件
+    pub fn load_file_and_env(
+        defaults: Config,
+        config_path: Option<PathBuf>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Config, ConfigError> {
+        let mut config = defaults;
+        match config_path.or_else(|| env(CONFIG_ENV).map(PathBuf::from)) {
+            Some(path) => config = config.merge_file(&path)?,
+            None if Path::new(CONFIG_FILE).exists() => config = config.merge_file(Path::new(CONFIG_FILE))?,
+            None => {}
+        }
+
+        for key in CONFIG_KEYS {
+            if let Some(value) = env(&format!("{}{}", ENV_PREFIX, key.to_ascii_uppercase())) {
+                config.set(key, &value)?;
+            }
+        }
+        Ok(config)
+    }
+
+    // 用配置文件中出现的配置项覆盖当前值
+    pub fn merge_file(self, path: &Path) -> Result<Config, ConfigError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|source| ConfigError::Io { path: path.to_path_buf(), source })?;
+        let parse_error = |message: String| ConfigError::Parse { path: path.to_path_buf(), message };
+
+        let file: toml::Table = content.parse().map_err(|e: toml::de::Error| parse_error(e.message().to_string()))?;
+        let mut merged = toml::Table::try_from(&self).map_err(|e| parse_error(e.to_string()))?;
+        for (key, value) in file {
+            if !CONFIG_KEYS.contains(&key.as_str()) {
+                return Err(ConfigError::UnknownKey { key, origin: path.display().to_string() });
+            }
+            merged.insert(key, value);
+        }
+        merged.try_into().map_err(|e: toml::de::Error| parse_error(e.message().to_string()))
+    }
+
+    // 按名称设置单项配置，列表用逗号分隔
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "listen_addrs" => self.listen_addrs = parse_list(value),
+            "bootstrap_addrs" => self.bootstrap_addrs = parse_list(value),
+            "stun_servers" => self.stun_servers = parse_list(value),
+            "stun_source" => self.stun_source = value.parse().map_err(|e| invalid(key, value, e))?,
+            "stun_responder" => self.stun_responder = parse_number(key, value)?,
+            "stun_responder_port" => self.stun_responder_port = parse_number(key, value)?,
+            "turn_server" => self.turn_server = parse_optional(value),
+            "turn_username" => self.turn_username = parse_optional(value),
+            "turn_password" => self.turn_password = parse_optional(value),
+            "relay_reservations" => self.relay_reservations = parse_number(key, value)?,
+            "relay_server" => self.relay_server = parse_number(key, value)?,
+            "relay_max_reservations" => self.relay_max_reservations = parse_number(key, value)?,
+            "relay_max_circuits" => self.relay_max_circuits = parse_number(key, value)?,
+            "relay_max_circuit_bytes" => self.relay_max_circuit_bytes = parse_number(key, value)?,
+            "relay_max_circuit_duration_secs" => self.relay_max_circuit_duration_secs = parse_number(key, value)?,
+            "relay_allowlist" => self.relay_allowlist = parse_list(value),
+            "kademlia_mode" => self.kademlia_mode = value.parse().map_err(|e| invalid(key, value, e))?,
+            "query_timeout_secs" => self.query_timeout_secs = Some(parse_number(key, value)?),
+            "ping_interval_secs" => self.ping_interval_secs = parse_number(key, value)?,
+            "max_runtime_minutes" => self.max_runtime_minutes = parse_number(key, value)?,
+            "max_connection_attempts" => self.max_connection_attempts = parse_number(key, value)?,
+            "bootstrap_interval_secs" => self.bootstrap_interval_secs = parse_number(key, value)?,
+            "refresh_interval_secs" => self.refresh_interval_secs = parse_number(key, value)?,
+            "address_interval_secs" => self.address_interval_secs = parse_number(key, value)?,
+            "data_dir" => self.data_dir = PathBuf::from(value),
+            "bootstraps_file" => self.bootstraps_file = PathBuf::from(value),
+            "stun_servers_file" => self.stun_servers_file = PathBuf::from(value),
+            "routing_table_file" => self.routing_table_file = PathBuf::from(value),
+            "report_file" => self.report_file = PathBuf::from(value),
+            _ => return Err(ConfigError::UnknownKey { key: key.to_string(), origin: "config".to_string() }),
+        }
+        Ok(())
+    }
+
+    // 检查所有配置值
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.listen_addrs.is_empty() {
+            return Err(invalid("listen_addrs", "[]", "at least one listen address is required"));
+        }
+        for addr in self.listen_addrs.iter().chain(&self.bootstrap_addrs) {
+            addr.parse::<Multiaddr>().map_err(|e| {
+                let key = if self.listen_addrs.contains(addr) { "listen_addrs" } else { "bootstrap_addrs" };
+                invalid(key, addr, e)
+            })?;
+        }
+
+        if self.stun_servers.is_empty() && self.stun_source.uses_servers() {
+            return Err(invalid("stun_servers", "[]", "at least one STUN server is required"));
+        }
+        if let Some(server) = self.stun_servers.iter().find(|server| !is_host_port(server)) {
+            return Err(invalid("stun_servers", server, "expected host:port"));
+        }
+        if let Some(server) = self.turn_server.as_ref().filter(|server| !is_host_port(server)) {
+            return Err(invalid("turn_server", server, "expected host:port"));
+        }
+        if self.turn_username.is_some() != self.turn_password.is_some() {
+            let key = if self.turn_username.is_some() { "turn_password" } else { "turn_username" };
+            return Err(invalid(key, "\"\"", "turn_username and turn_password must be set together"));
+        }
+
+        if self.kademlia_mode == KademliaMode::Auto && !autonat::is_supported() {
+            return Err(invalid("kademlia_mode", "auto", "automatic mode requires building with --features autonat"));
+        }
+        if self.relay_server && !relay::is_supported() {
+            return Err(invalid("relay_server", true, "relay server mode requires building with --features relay"));
+        }
+        for peer in &self.relay_allowlist {
+            peer.parse::<PeerId>().map_err(|e| invalid("relay_allowlist", peer, e))?;
+        }
+        if self.max_runtime_minutes > MAX_RUNTIME_MINUTES {
+            return Err(invalid("max_runtime_minutes", self.max_runtime_minutes, format!("must be at most {}", MAX_RUNTIME_MINUTES)));
+        }
+        // libp2p 以 u32 秒数告知转发连接的时长上限
+        if self.relay_max_circuit_duration_secs > u32::MAX as u64 {
+            return Err(invalid("relay_max_circuit_duration_secs", self.relay_max_circuit_duration_secs, "must fit in 32 bits"));
+        }
+
+        let paths = [
+            ("data_dir", &self.data_dir),
+            ("bootstraps_file", &self.bootstraps_file),
+            ("stun_servers_file", &self.stun_servers_file),
+            ("routing_table_file", &self.routing_table_file),
+            ("report_file", &self.report_file),
+        ];
+        for (key, path) in paths {
+            if path.as_os_str().is_empty() {
+                return Err(invalid(key, "\"\"", "path must not be empty"));
+            }
+        }
+
+        let positive = [
+            ("ping_interval_secs", self.ping_interval_secs),
+            ("max_runtime_minutes", self.max_runtime_minutes),
+            ("max_connection_attempts", self.max_connection_attempts as u64),
+            ("bootstrap_interval_secs", self.bootstrap_interval_secs),
+            ("refresh_interval_secs", self.refresh_interval_secs),
+            ("address_interval_secs", self.address_interval_secs),
+            ("relay_max_circuit_bytes", self.relay_max_circuit_bytes),
+            ("relay_max_circuit_duration_secs", self.relay_max_circuit_duration_secs),
+            ("query_timeout_secs", self.query_timeout_secs.unwrap_or(1)),
+        ];
+        for (key, value) in positive {
+            if value == 0 {
+                return Err(invalid(key, value, "must be greater than zero"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn listen_multiaddrs(&self) -> Vec<Multiaddr> {
+        self.listen_addrs.iter().filter_map(|addr| addr.parse().ok()).collect()
+    }
+
+    pub fn bootstrap_multiaddrs(&self) -> Vec<Multiaddr> {
+        self.bootstrap_addrs.iter().filter_map(|addr| addr.parse().ok()).collect()
+    }
+
+    pub fn stun_server_list(&self) -> Vec<&str> {
+        self.stun_servers.iter().map(String::as_str).collect()
+    }
+
+    // TURN 服务器的长期凭据，未设置用户名时服务器不要求认证
+    pub fn turn_credentials(&self) -> Option<StunCredentials> {
+        match (&self.turn_username, &self.turn_password) {
+            (Some(username), Some(password)) => {
+                Some(StunCredentials { username: username.clone(), password: password.clone() })
+            }
+            _ => None,
+        }
+    }
+
+    // 中继服务端的限制和允许列表，无法解析的 PeerId 已在 validate 中报错
+    pub fn relay_server_config(&self) -> RelayServerConfig {
+        RelayServerConfig {
+            max_reservations: self.relay_max_reservations,
+            max_circuits: self.relay_max_circuits,
+            max_circuit_bytes: self.relay_max_circuit_bytes,
+            max_circuit_duration: Duration::from_secs(self.relay_max_circuit_duration_secs),
+            allowlist: self.relay_allowlist.iter().filter_map(|peer| peer.parse().ok()).collect(),
+        }
+    }
+
+    pub fn query_timeout(&self) -> Option<Duration> {
+        self.query_timeout_secs.map(Duration::from_secs)
+    }
+
+    pub fn ping_interval(&self) -> Duration {
+        Duration::from_secs(self.ping_interval_secs)
+    }
+
+    pub fn max_runtime(&self) -> Duration {
+        // 未经 validate 检查的配置也不会溢出
+        Duration::from_secs(self.max_runtime_minutes.saturating_mul(60))
+    }
+
+    pub fn bootstrap_interval(&self) -> Duration {
+        Duration::from_secs(self.bootstrap_interval_secs)
+    }
+
+    pub fn refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.refresh_interval_secs)
+    }
+
+    pub fn address_interval(&self) -> Duration {
+        Duration::from_secs(self.address_interval_secs)
+    }
+
+    pub fn bootstraps_path(&self) -> PathBuf {
+        self.data_dir.join(&self.bootstraps_file)
+    }
+
+    pub fn stun_servers_path(&self) -> PathBuf {
+        self.data_dir.join(&self.stun_servers_file)
+    }
+
+    pub fn routing_table_path(&self) -> PathBuf {
+        self.data_dir.join(&self.routing_table_file)
+    }
+
+    pub fn report_path(&self) -> PathBuf {
+        self.data_dir.join(&self.report_file)
+    }
+}
@@ -0,0 +1,237 @@
+// error.rs - 库的错误类型
+// 调用方按变体区分失败原因，不再依赖错误信息中的文字
+use libp2p::{
+    Multiaddr,
+    PeerId,
+    core::transport::TransportError,
+    kad,
+    swarm::DialError,
+};
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    // STUN 服务器在超时时间内没有响应
+    StunTimeout { server: String },
+    // STUN 响应无法解码
+    StunDecode { server: String, reason: String },
+    // STUN 响应中没有映射地址属性
+    NoMappedAddress { server: String },
+    // STUN 服务器返回 ERROR-CODE
+    StunErrorResponse { server: String, code: u16, reason: String },
+    // 与 STUN 服务器通信时的本地 IO 错误（地址解析、发送、接收）
+    StunIo { server: String, source: io::Error },
+    // STUN 服务器不支持 RFC 5780 NAT 行为发现（响应中没有 OTHER-ADDRESS）
+    NatDiscoveryUnsupported { server: String },
+    // 对端节点没有开启 STUN 应答
+    PeerStunUnsupported { peer_id: PeerId },
+    // DHT 中没有找到提供 STUN 应答的节点
+    NoStunPeers,
+    // 所有 STUN 服务器都失败，按尝试顺序保存每个服务器的错误
+    StunFailed(Vec<Error>),
+    // 拨号失败，传输层错误时 kind 为底层 IO 错误类型
+    Dial { peer_id: Option<PeerId>, kind: Option<io::ErrorKind>, message: String },
+    // 对端身份与期望的 PeerId 不符，obtained 为对端实际的 PeerId
+    WrongPeerId { obtained: PeerId },
+    // 经中继的连接上打洞失败，仍使用中继连接
+    HolePunch { peer_id: PeerId, message: String },
+    // 路由表为空，无法执行 Bootstrap
+    NoKnownPeers,
+    // 本地记录存储失败
+    Store(kad::store::Error),
+    // 记录发布失败
+    PutRecord(kad::PutRecordError),
+    // 记录查询失败
+    GetRecord(kad::GetRecordError),
+    // 查询结束但没有找到记录
+    RecordNotFound,
+    // 节点已停止运行
+    Stopped,
+    // 本地 IO 错误（套接字、TURN 服务器地址解析等）
+    Io(io::Error),
+    // 读写持久化文件失败
+    Persist { path: PathBuf, source: io::Error },
+    // 持久化文件不是有效的 JSON，或内容不符合格式
+    Json { path: PathBuf, source: serde_json::Error },
+    // 持久化文件的格式版本比本程序支持的新
+    UnsupportedVersion { path: PathBuf, version: u64, supported: u32 },
+    // 持久化文件内容损坏（例如密钥与记录的 PeerId 不符）
+    Corrupted { path: PathBuf, reason: String },
+    // 密钥文件已存在，生成新密钥应使用 rotate
+    KeystoreExists { path: PathBuf },
+    // 密钥文件已加密，但没有提供口令
+    PassphraseRequired { path: PathBuf },
+    // 口令错误或密钥文件密文损坏
+    WrongPassphrase { path: PathBuf },
+    // 密钥生成、编码或解码失败
+    Key(String),
+    // 创建传输层失败
+    Transport(String),
+    // 无法在地址上监听
+    Listen { address: Multiaddr, source: TransportError<io::Error> },
+    // 需要的功能没有编译进来，feature 为 Cargo feature 名称
+    FeatureDisabled { feature: &'static str },
+}
+
+impl Error {
+    // 将拨号错误转换为 Error，peer_id 为拨号时期望的对端
+    pub fn from_dial(peer_id: Option<PeerId>, error: &DialError) -> Self {
+        match error {
+            DialError::WrongPeerId { obtained, .. } => Error::WrongPeerId { obtained: *obtained },
+            DialError::Transport(errors) => Error::Dial {
+                peer_id,
+                kind: transport_error_kind(errors),
+                message: error.to_string(),
+            },
+            _ => Error::Dial { peer_id, kind: None, message: error.to_string() },
+        }
+    }
+
+    // 读写持久化文件 path 时的 IO 错误
+    pub fn persist(path: &std::path::Path, source: io::Error) -> Self {
+        Error::Persist { path: path.to_path_buf(), source }
+    }
+
+    // 持久化文件 path 的 JSON 错误
+    pub fn json(path: &std::path::Path, source: serde_json::Error) -> Self {
+        Error::Json { path: path.to_path_buf(), source }
+    }
+
+    // STUN 错误对应的服务器名称（配置中的 host:port）
+    pub fn stun_server(&self) -> Option<&str> {
+        match self {
+            Error::StunTimeout { server }
+            | Error::StunDecode { server, .. }
+            | Error::NoMappedAddress { server }
+            | Error::StunErrorResponse { server, .. }
+            | Error::StunIo { server, .. }
+            | Error::NatDiscoveryUnsupported { server } => Some(server),
+            _ => None,
+        }
+    }
+
+    // 是否为超时导致的失败
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Error::StunTimeout { .. } => true,
+            Error::Dial { kind, .. } => *kind == Some(io::ErrorKind::TimedOut),
+            Error::StunFailed(errors) => !errors.is_empty() && errors.iter().all(Error::is_timeout),
+            Error::GetRecord(kad::GetRecordError::Timeout { .. }) | Error::PutRecord(kad::PutRecordError::Timeout { .. }) => true,
+            _ => false,
+        }
+    }
+
+    // 是否为对端拒绝连接
+    pub fn is_connection_refused(&self) -> bool {
+        matches!(self, Error::Dial { kind: Some(io::ErrorKind::ConnectionRefused), .. })
+    }
+
+    // STUN 服务器是否有响应但没有给出可用的映射地址
+    pub fn is_stun_binding_failure(&self) -> bool {
+        match self {
+            Error::StunDecode { .. } | Error::NoMappedAddress { .. } | Error::StunErrorResponse { .. } => true,
+            Error::StunFailed(errors) => errors.iter().any(Error::is_stun_binding_failure),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::StunTimeout { server } => write!(f, "{}: STUN request timed out", server),
+            Error::StunDecode { server, reason } => write!(f, "{}: failed to decode STUN response: {}", server, reason),
+            Error::NoMappedAddress { server } => write!(f, "{}: no mapped address in STUN response", server),
+            Error::StunErrorResponse { server, code, reason } => {
+                write!(f, "{}: STUN error response {} {}", server, code, reason)
+            }
+            Error::StunIo { server, source } => write!(f, "{}: {}", server, source),
+            Error::NatDiscoveryUnsupported { server } => {
+                write!(f, "{}: server does not support NAT behavior discovery (no OTHER-ADDRESS)", server)
+            }
+            Error::PeerStunUnsupported { peer_id } => write!(f, "{}: peer does not answer STUN requests", peer_id),
+            Error::NoStunPeers => write!(f, "no STUN responders found in the DHT"),
+            Error::StunFailed(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "all STUN servers failed ({})", errors.join("; "))
+            }
+            Error::Dial { peer_id: Some(peer_id), message, .. } => write!(f, "dial {} failed: {}", peer_id, message),
+            Error::Dial { peer_id: None, message, .. } => write!(f, "dial failed: {}", message),
+            Error::WrongPeerId { obtained } => write!(f, "wrong peer id: obtained {}", obtained),
+            Error::HolePunch { peer_id, message } => write!(f, "hole punching with {} failed: {}", peer_id, message),
+            Error::NoKnownPeers => write!(f, "no known peers to bootstrap with"),
+            Error::Store(e) => write!(f, "record store error: {}", e),
+            Error::PutRecord(e) => write!(f, "put record failed: {}", e),
+            Error::GetRecord(e) => write!(f, "get record failed: {}", e),
+            Error::RecordNotFound => write!(f, "record not found"),
+            Error::Stopped => write!(f, "node is not running"),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Persist { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Json { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::UnsupportedVersion { path, version, supported } => {
+                write!(f, "{} has unsupported version {} (newest supported is {})", path.display(), version, supported)
+            }
+            Error::Corrupted { path, reason } => write!(f, "{} is corrupted: {}", path.display(), reason),
+            Error::KeystoreExists { path } => write!(f, "keystore {} already exists, use rotate to replace it", path.display()),
+            Error::PassphraseRequired { path } => {
+                write!(f, "keystore {} is encrypted, set {}", path.display(), crate::keystore::PASSPHRASE_ENV)
+            }
+            Error::WrongPassphrase { path } => write!(f, "{}: wrong passphrase or corrupted keystore", path.display()),
+            Error::Key(reason) => write!(f, "key error: {}", reason),
+            Error::Transport(reason) => write!(f, "failed to create transport: {}", reason),
+            Error::Listen { address, source } => write!(f, "failed to listen on {}: {}", address, source),
+            Error::FeatureDisabled { feature } => write!(f, "requires building with --features {}", feature),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::StunIo { source, .. } => Some(source),
+            Error::Store(e) => Some(e),
+            Error::PutRecord(e) => Some(e),
+            Error::GetRecord(e) => Some(e),
+            Error::Io(e) | Error::Persist { source: e, .. } => Some(e),
+            Error::Json { source, .. } => Some(source),
+            Error::Listen { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+// 从传输层错误中找出最具体的 IO 错误类型
+// libp2p 会把底层错误层层包装成 ErrorKind::Other，需要沿 source 链向下查找
+fn transport_error_kind(errors: &[(libp2p::Multiaddr, TransportError<io::Error>)]) -> Option<io::ErrorKind> {
+    let mut fallback = None;
+    for (_, error) in errors {
+        let TransportError::Other(error) = error else { continue };
+        let mut current: Option<&(dyn StdError + 'static)> = Some(error);
+        while let Some(err) = current {
+            if let Some(io_err) = err.downcast_ref::<io::Error>() {
+                if io_err.kind() != io::ErrorKind::Other {
+                    return Some(io_err.kind());
+                }
+                // io::Error 的 source 不一定包含内部错误，优先查看 get_ref
+                if let Some(inner) = io_err.get_ref() {
+                    current = Some(inner);
+                    continue;
+                }
+            }
+            current = err.source();
+        }
+        fallback.get_or_insert(error.kind());
+    }
+    fallback
+}
@@ -0,0 +1,79 @@
+// identify.rs - libp2p identify 协议
+// 连接建立后双方交换客户端版本、协议版本、支持的协议、监听地址和观察到的对方地址。
+// 对端的监听地址加入 Kademlia 路由表，观察到的本节点地址由 identify 行为作为外部地址候选交给 Swarm。
+// 需要以 --features identify 编译，未编译时行为用 dummy::Behaviour 占位
+use crate::address;
+use libp2p::{identity::PublicKey, kad, Multiaddr};
+
+// identify 行为
+#[cfg(feature = "identify")]
+pub type Behaviour = libp2p::identify::Behaviour;
+#[cfg(not(feature = "identify"))]
+pub type Behaviour = libp2p::swarm::dummy::Behaviour;
+
+// 本节点报告的协议版本
+pub const PROTOCOL_VERSION: &str = "/p2p/1.0.0";
+
+// 本节点报告的客户端版本，例如 p2p/0.1.0
+pub fn agent_version() -> String {
+    format!("p2p/{}", env!("CARGO_PKG_VERSION"))
+}
+
+// 是否编译了 identify
+pub fn is_supported() -> bool {
+    cfg!(feature = "identify")
+}
+
+// 创建 identify 行为，监听地址变化时主动推送给已连接的节点
+#[cfg(feature = "identify")]
+pub fn new_behaviour(public_key: PublicKey) -> Behaviour {
+    let config = libp2p::identify::Config::new(PROTOCOL_VERSION.to_string(), public_key)
+        .with_agent_version(agent_version())
+        .with_push_listen_addr_updates(true);
+    libp2p::identify::Behaviour::new(config)
+}
+#[cfg(not(feature = "identify"))]
+pub fn new_behaviour(_public_key: PublicKey) -> Behaviour {
+    libp2p::swarm::dummy::Behaviour
+}
+
+// 对端通过 identify 报告的信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerIdentity {
+    pub agent_version: String,
+    pub protocol_version: String,
+    pub protocols: Vec<String>,
+    pub listen_addrs: Vec<Multiaddr>,
+    // 对端看到的本节点地址
+    pub observed_addr: Multiaddr,
+}
+
+impl PeerIdentity {
+    #[cfg(feature = "identify")]
+    pub fn from_info(info: libp2p::identify::Info) -> Self {
+        PeerIdentity {
+            agent_version: info.agent_version,
+            protocol_version: info.protocol_version,
+            protocols: info.protocols.iter().map(ToString::to_string).collect(),
+            listen_addrs: info.listen_addrs,
+            observed_addr: info.observed_addr,
+        }
+    }
+
+    // 对端是否运行 Kademlia，只有这样的节点才加入路由表
+    pub fn supports_kademlia(&self) -> bool {
+        self.protocols.iter().any(|protocol| protocol.as_str() == kad::PROTOCOL_NAME.as_ref())
+    }
+
+    // 可以加入路由表的监听地址：去掉未指定地址和回环地址，对端只在回环地址上监听（同一台主机）时保留回环地址
+    pub fn routable_addrs(&self) -> Vec<Multiaddr> {
+        let usable = |addr: &&Multiaddr| address::ip(addr).is_none_or(|ip| !ip.is_unspecified());
+        let only_loopback = self.listen_addrs.iter().filter(usable).all(|addr| address::ip(addr).is_some_and(|ip| ip.is_loopback()));
+        self.listen_addrs
+            .iter()
+            .filter(usable)
+            .filter(|addr| only_loopback || address::ip(addr).is_none_or(|ip| !ip.is_loopback()))
+            .cloned()
+            .collect()
+    }
+}
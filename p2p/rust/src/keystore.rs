@@ -0,0 +1,292 @@
+// keystore.rs - 节点身份密钥的持久化存储
+// 密钥以 protobuf 编码（RSA 为 PKCS#8 DER）保存在 JSON 文件中，可选使用口令加密
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chrono::Utc;
+use crate::error::{Error, Result};
+use crate::persist;
+use libp2p::{PeerId, identity};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// 身份密钥默认保存的文件
+pub const KEYSTORE_FILE: &str = "KEYSTORE.json";
+// 保存口令的环境变量
+pub const PASSPHRASE_ENV: &str = "P2P_KEYSTORE_PASSPHRASE";
+
+// 当前的密钥文件格式版本
+const KEYSTORE_VERSION: u32 = 1;
+// 生成 RSA 密钥的长度
+const RSA_KEY_BITS: usize = 2048;
+
+// 支持的密钥类型
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyType {
+    Ed25519,
+    Secp256k1,
+    Ecdsa,
+    Rsa,
+}
+
+impl KeyType {
+    // 生成该类型的新密钥对
+    pub fn generate(self) -> Result<identity::Keypair> {
+        Ok(generate_encoded(self)?.0)
+    }
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            KeyType::Ed25519 => "ed25519",
+            KeyType::Secp256k1 => "secp256k1",
+            KeyType::Ecdsa => "ecdsa",
+            KeyType::Rsa => "rsa",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for KeyType {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ed25519" => Ok(KeyType::Ed25519),
+            "secp256k1" => Ok(KeyType::Secp256k1),
+            "ecdsa" => Ok(KeyType::Ecdsa),
+            "rsa" => Ok(KeyType::Rsa),
+            other => Err(format!("unknown key type '{}', expected ed25519, secp256k1, ecdsa or rsa", other)),
+        }
+    }
+}
+
+// 密钥文件中不需要口令即可查看的信息
+#[derive(Debug, Clone)]
+pub struct KeystoreInfo {
+    pub peer_id: PeerId,
+    pub key_type: KeyType,
+    pub encrypted: bool,
+    pub created_at: String,
+}
+
+// 密钥文件结构
+#[derive(Serialize, Deserialize, Debug)]
+struct KeystoreFile {
+    version: u32,
+    key_type: KeyType,
+    peer_id: String,
+    created_at: String, // 生成时间戳
+    encryption: Option<Encryption>, // 未加密时为空
+    key: String, // 十六进制编码的密钥（或密文）
+}
+
+// 口令加密参数：Argon2id 派生密钥，ChaCha20-Poly1305 加密
+#[derive(Serialize, Deserialize, Debug)]
+struct Encryption {
+    kdf: String,
+    salt: String,
+    cipher: String,
+    nonce: String,
+}
+
+// 读取环境变量中的口令，未设置或为空时不加密
+pub fn passphrase_from_env() -> Option<String> {
+    std::env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty())
+}
+
+// 生成新密钥并写入密钥文件，文件已存在时报错
+// 存在性检查在文件锁内进行，同时首次启动的多个进程不会各自写入不同的身份
+pub fn generate(path: &Path, key_type: KeyType, passphrase: Option<&str>) -> Result<identity::Keypair> {
+    let lock = persist::lock(path).map_err(|e| Error::persist(path, e))?;
+    if path.exists() {
+        return Err(Error::KeystoreExists { path: path.to_path_buf() });
+    }
+    generate_locked(&lock, key_type, passphrase)
+}
+
+// 从密钥文件加载密钥
+pub fn load(path: &Path, passphrase: Option<&str>) -> Result<identity::Keypair> {
+    let file = read_keystore(path)?;
+    let mut encoded = hex_field(path, &file.key)?;
+
+    if let Some(encryption) = &file.encryption {
+        let passphrase = passphrase.ok_or_else(|| Error::PassphraseRequired { path: path.to_path_buf() })?;
+        encoded = decrypt(path, &encoded, encryption, passphrase)?;
+    }
+
+    let keypair = match file.key_type {
+        KeyType::Rsa => identity::Keypair::rsa_from_pkcs8(&mut encoded).map_err(|e| corrupted(path, e))?,
+        _ => identity::Keypair::from_protobuf_encoding(&encoded).map_err(|e| corrupted(path, e))?,
+    };
+
+    // 校验密钥与文件中记录的 PeerId 一致
+    let peer_id = keypair.public().to_peer_id();
+    if peer_id.to_string() != file.peer_id {
+        return Err(corrupted(path, format!("key does not match peer id {}", file.peer_id)));
+    }
+    Ok(keypair)
+}
+
+// 加载已有密钥，没有密钥文件时生成新的
+pub fn load_or_generate(path: &Path, key_type: KeyType, passphrase: Option<&str>) -> Result<identity::Keypair> {
+    let lock = persist::lock(path).map_err(|e| Error::persist(path, e))?;
+    if path.exists() {
+        load(path, passphrase)
+    } else {
+        generate_locked(&lock, key_type, passphrase)
+    }
+}
+
+// 查看密钥文件信息，不需要口令
+pub fn show(path: &Path) -> Result<KeystoreInfo> {
+    let file = read_keystore(path)?;
+    Ok(KeystoreInfo {
+        peer_id: file.peer_id.parse().map_err(|e| corrupted(path, e))?,
+        key_type: file.key_type,
+        encrypted: file.encryption.is_some(),
+        created_at: file.created_at,
+    })
+}
+
+// 生成新密钥替换旧密钥，旧文件复制一份备份，返回新密钥和备份路径
+pub fn rotate(path: &Path, key_type: KeyType, passphrase: Option<&str>) -> Result<(identity::Keypair, PathBuf)> {
+    // 备份和替换都在文件锁内完成，备份的一定是被替换掉的那份密钥
+    let lock = persist::lock(path).map_err(|e| Error::persist(path, e))?;
+
+    // 先确认旧密钥可以解开，避免用错误的口令轮换
+    load(path, passphrase)?;
+
+    let backup = PathBuf::from(format!("{}.{}.bak", path.display(), Utc::now().format("%Y%m%d%H%M%S")));
+    fs::copy(path, &backup).map_err(|e| Error::persist(&backup, e))?;
+
+    // 新密钥原子地替换旧文件，写入失败时旧密钥保持不变
+    let keypair = generate_locked(&lock, key_type, passphrase)?;
+    Ok((keypair, backup))
+}
+
+// 调用方已经持有密钥文件的锁
+fn generate_locked(lock: &persist::FileLock, key_type: KeyType, passphrase: Option<&str>) -> Result<identity::Keypair> {
+    let (keypair, encoded) = generate_encoded(key_type)?;
+    write_keystore(lock, &keypair, key_type, encoded, passphrase)?;
+    Ok(keypair)
+}
+
+// 生成新密钥对及其存储编码
+// libp2p 不支持 RSA 私钥的 protobuf 编码，RSA 改存 PKCS#8 DER
+fn generate_encoded(key_type: KeyType) -> Result<(identity::Keypair, Vec<u8>)> {
+    let keypair = match key_type {
+        KeyType::Ed25519 => identity::Keypair::generate_ed25519(),
+        KeyType::Secp256k1 => identity::Keypair::generate_secp256k1(),
+        KeyType::Ecdsa => identity::Keypair::generate_ecdsa(),
+        KeyType::Rsa => {
+            let der = generate_rsa_pkcs8()?;
+            // rsa_from_pkcs8 会清零传入的缓冲区
+            let keypair = identity::Keypair::rsa_from_pkcs8(&mut der.clone()).map_err(|e| Error::Key(e.to_string()))?;
+            return Ok((keypair, der));
+        }
+    };
+    let encoded = keypair.to_protobuf_encoding().map_err(|e| Error::Key(e.to_string()))?;
+    Ok((keypair, encoded))
+}
+
+fn write_keystore(
+    lock: &persist::FileLock,
+    keypair: &identity::Keypair,
+    key_type: KeyType,
+    encoded: Vec<u8>,
+    passphrase: Option<&str>,
+) -> Result<()> {
+    let (encryption, key) = match passphrase {
+        Some(passphrase) => {
+            let (encryption, ciphertext) = encrypt(&encoded, passphrase)?;
+            (Some(encryption), ciphertext)
+        }
+        None => (None, encoded),
+    };
+
+    let file = KeystoreFile {
+        version: KEYSTORE_VERSION,
+        key_type,
+        peer_id: keypair.public().to_peer_id().to_string(),
+        created_at: Utc::now().to_rfc3339(),
+        encryption,
+        key: hex::encode(key),
+    };
+
+    let json_string = serde_json::to_string_pretty(&file).map_err(|e| Error::json(lock.path(), e))?;
+    lock.write_private(json_string.as_bytes()).map_err(|e| Error::persist(lock.path(), e))
+}
+
+fn read_keystore(path: &Path) -> Result<KeystoreFile> {
+    let content = fs::read_to_string(path).map_err(|e| Error::persist(path, e))?;
+    let file: KeystoreFile = serde_json::from_str(&content).map_err(|e| Error::json(path, e))?;
+    if file.version != KEYSTORE_VERSION {
+        return Err(Error::UnsupportedVersion { path: path.to_path_buf(), version: file.version.into(), supported: KEYSTORE_VERSION });
+    }
+    Ok(file)
+}
+
+// 密钥文件内容损坏
+fn corrupted(path: &Path, reason: impl fmt::Display) -> Error {
+    Error::Corrupted { path: path.to_path_buf(), reason: reason.to_string() }
+}
+
+// 解码密钥文件中十六进制编码的字段
+fn hex_field(path: &Path, value: &str) -> Result<Vec<u8>> {
+    hex::decode(value).map_err(|e| corrupted(path, e))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| Error::Key(format!("key derivation failed: {}", e)))?;
+    Ok(key)
+}
+
+fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<(Encryption, Vec<u8>)> {
+    let salt: [u8; 16] = rand::random();
+    let nonce: [u8; 12] = rand::random();
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| Error::Key("failed to encrypt keystore".to_string()))?;
+
+    let encryption = Encryption {
+        kdf: "argon2id".to_string(),
+        salt: hex::encode(salt),
+        cipher: "chacha20poly1305".to_string(),
+        nonce: hex::encode(nonce),
+    };
+    Ok((encryption, ciphertext))
+}
+
+fn decrypt(path: &Path, ciphertext: &[u8], encryption: &Encryption, passphrase: &str) -> Result<Vec<u8>> {
+    if encryption.kdf != "argon2id" || encryption.cipher != "chacha20poly1305" {
+        return Err(corrupted(path, format!("unsupported encryption {}/{}", encryption.kdf, encryption.cipher)));
+    }
+    let salt = hex_field(path, &encryption.salt)?;
+    let nonce = hex_field(path, &encryption.nonce)?;
+    if nonce.len() != 12 {
+        return Err(corrupted(path, "invalid nonce"));
+    }
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext)
+        .map_err(|_| Error::WrongPassphrase { path: path.to_path_buf() })?;
+    Ok(plaintext)
+}
+
+// 生成 RSA 私钥的 PKCS#8 DER 编码
+fn generate_rsa_pkcs8() -> Result<Vec<u8>> {
+    use rsa::pkcs8::EncodePrivateKey;
+
+    let private_key = rsa::RsaPrivateKey::new(&mut rsa::rand_core::OsRng, RSA_KEY_BITS).map_err(|e| Error::Key(e.to_string()))?;
+    Ok(private_key.to_pkcs8_der().map_err(|e| Error::Key(e.to_string()))?.as_bytes().to_vec())
+}
@@ -1,2 +1,25 @@
 // lib.rs - P2P节点软件库
-pub mod performance_benchmark;
\ No newline at end of file
+pub mod address;
+pub mod autonat;
+pub mod bootstrap;
+pub mod cli;
+pub mod commands;
+pub mod config;
+pub mod error;
+pub mod identify;
+pub mod keystore;
+pub mod nat;
+pub mod node;
+pub mod output;
+pub mod peer_stun;
+pub mod performance_benchmark;
+pub mod persist;
+pub mod relay;
+pub mod report;
+pub mod signal;
+pub mod stun;
+pub mod stun_auth;
+pub mod stun_server;
+pub mod stun_servers;
+pub mod turn;
+pub mod turn_transport;
@@ -0,0 +1,186 @@
+// nat.rs - RFC 5780 NAT 行为发现
+// 通过支持 RFC 5780 的 STUN 服务器（响应中带 OTHER-ADDRESS）判断 NAT 的映射行为和过滤行为：
+// 映射测试从同一个本地端口分别向主地址、备用 IP 和备用 IP+端口发送请求，比较映射地址；
+// 过滤测试在新的本地端口上用 CHANGE-REQUEST 要求服务器从其它 IP/端口应答，看响应能否通过 NAT
+use crate::error::{Error, Result};
+use crate::stun::{self, StunConfig, StunResult};
+use serde::Serialize;
+use std::fmt;
+use std::net::SocketAddr;
+use stun_codec::rfc5780::attributes::ChangeRequest;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+// 过滤测试以超时判定响应被 NAT 丢弃，每个事务最多等待的时间
+pub const NAT_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+// NAT 行为发现使用的重传参数
+pub fn discovery_config() -> StunConfig {
+    StunConfig { timeout: Some(NAT_DISCOVERY_TIMEOUT), ..StunConfig::default() }
+}
+
+// NAT 映射行为（RFC 4787 4.1）
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MappingBehavior {
+    // 映射地址就是本机地址，没有 NAT
+    NoNat,
+    // 所有目标看到同一个映射地址
+    EndpointIndependent,
+    // 不同目标 IP 看到不同的映射地址
+    AddressDependent,
+    // 不同目标 IP 或端口看到不同的映射地址
+    AddressAndPortDependent,
+}
+
+impl fmt::Display for MappingBehavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let behavior = match self {
+            MappingBehavior::NoNat => "no-nat",
+            MappingBehavior::EndpointIndependent => "endpoint-independent",
+            MappingBehavior::AddressDependent => "address-dependent",
+            MappingBehavior::AddressAndPortDependent => "address-and-port-dependent",
+        };
+        f.write_str(behavior)
+    }
+}
+
+// NAT 过滤行为（RFC 4787 5）
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FilteringBehavior {
+    // 任何地址发来的报文都能通过映射
+    EndpointIndependent,
+    // 只接受本机发送过报文的 IP
+    AddressDependent,
+    // 只接受本机发送过报文的 IP 和端口
+    AddressAndPortDependent,
+}
+
+impl fmt::Display for FilteringBehavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let behavior = match self {
+            FilteringBehavior::EndpointIndependent => "endpoint-independent",
+            FilteringBehavior::AddressDependent => "address-dependent",
+            FilteringBehavior::AddressAndPortDependent => "address-and-port-dependent",
+        };
+        f.write_str(behavior)
+    }
+}
+
+// NAT 行为发现的结果
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct NatType {
+    pub mapping: MappingBehavior,
+    pub filtering: FilteringBehavior,
+    // 映射测试中主地址看到的公网地址
+    pub public_address: SocketAddr,
+    // 完成测试的服务器
+    pub server: String,
+}
+
+impl NatType {
+    // RFC 3489 中的传统名称
+    pub fn classic_name(&self) -> &'static str {
+        match (self.mapping, self.filtering) {
+            (MappingBehavior::NoNat, FilteringBehavior::EndpointIndependent) => "open internet",
+            (MappingBehavior::NoNat, _) => "firewalled",
+            (MappingBehavior::EndpointIndependent, FilteringBehavior::EndpointIndependent) => "full cone",
+            (MappingBehavior::EndpointIndependent, FilteringBehavior::AddressDependent) => "restricted cone",
+            (MappingBehavior::EndpointIndependent, FilteringBehavior::AddressAndPortDependent) => "port restricted cone",
+            _ => "symmetric",
+        }
+    }
+
+    // 映射与目标无关时，对端可以使用 STUN 发现的地址打洞
+    pub fn supports_hole_punching(&self) -> bool {
+        matches!(self.mapping, MappingBehavior::NoNat | MappingBehavior::EndpointIndependent)
+    }
+}
+
+impl fmt::Display for NatType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (mapping: {}, filtering: {})", self.classic_name(), self.mapping, self.filtering)
+    }
+}
+
+// 依次使用列表中的服务器进行 NAT 行为发现，返回第一个完成测试的结果
+// 所有服务器都失败（包括不支持 RFC 5780）时返回 StunFailed
+pub async fn discover_nat_type(stun_servers: &[&str], config: &StunConfig) -> Result<NatType> {
+    let mut failures = Vec::new();
+    for server in stun_servers {
+        match discover_with_server(server, config).await {
+            Ok(nat_type) => return Ok(nat_type),
+            Err(e) => failures.push(e),
+        }
+    }
+    Err(Error::StunFailed(failures))
+}
+
+async fn discover_with_server(server: &str, config: &StunConfig) -> Result<NatType> {
+    let primary = stun::resolve(server).await?;
+
+    // 测试 I：向主地址发送请求，获取映射地址和备用地址
+    let socket = bind(server, primary).await?;
+    let first = binding(&socket, server, primary, config).await?;
+    let other = match first.other_address {
+        Some(other) if other.ip() != primary.ip() && other.port() != primary.port() => other,
+        _ => return Err(Error::NatDiscoveryUnsupported { server: server.to_string() }),
+    };
+
+    let mapping = if first.mapped_address == local_address(&socket, primary).await? {
+        MappingBehavior::NoNat
+    } else {
+        // 测试 II：备用 IP、主端口
+        let second = binding(&socket, server, SocketAddr::new(other.ip(), primary.port()), config).await?;
+        if second.mapped_address == first.mapped_address {
+            MappingBehavior::EndpointIndependent
+        } else {
+            // 测试 III：备用 IP、备用端口
+            let third = binding(&socket, server, other, config).await?;
+            if third.mapped_address == second.mapped_address {
+                MappingBehavior::AddressDependent
+            } else {
+                MappingBehavior::AddressAndPortDependent
+            }
+        }
+    };
+
+    // 映射测试已经向备用地址发送过请求，NAT 会放行这些地址的响应，过滤测试需要新的本地端口
+    let socket = bind(server, primary).await?;
+    let change_both = ChangeRequest::new(true, true);
+    let filtering = match stun::change_request(&socket, server, primary, change_both, other, config).await {
+        Ok(_) => FilteringBehavior::EndpointIndependent,
+        Err(e) if e.is_timeout() => {
+            let change_port = ChangeRequest::new(false, true);
+            let source = SocketAddr::new(primary.ip(), other.port());
+            match stun::change_request(&socket, server, primary, change_port, source, config).await {
+                Ok(_) => FilteringBehavior::AddressDependent,
+                Err(e) if e.is_timeout() => FilteringBehavior::AddressAndPortDependent,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(NatType { mapping, filtering, public_address: first.mapped_address, server: server.to_string() })
+}
+
+// 绑定与服务器地址族相同的本地套接字
+async fn bind(server: &str, server_address: SocketAddr) -> Result<UdpSocket> {
+    let bind_address = if server_address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    UdpSocket::bind(bind_address).await.map_err(|source| Error::StunIo { server: server.to_string(), source })
+}
+
+async fn binding(socket: &UdpSocket, server: &str, destination: SocketAddr, config: &StunConfig) -> Result<StunResult> {
+    stun::binding_requests(socket, &[(server.to_string(), destination)], &stun::StunCredentialMap::new(), config).await.remove(0)
+}
+
+// 套接字绑定在未指定地址上，用一个连接到服务器的临时套接字找出发往服务器时使用的本机 IP
+async fn local_address(socket: &UdpSocket, server_address: SocketAddr) -> Result<SocketAddr> {
+    let io_error = |source| Error::StunIo { server: server_address.to_string(), source };
+    let bound = socket.local_addr().map_err(io_error)?;
+    let probe = UdpSocket::bind(SocketAddr::new(bound.ip(), 0)).await.map_err(io_error)?;
+    probe.connect(server_address).await.map_err(io_error)?;
+    Ok(SocketAddr::new(probe.local_addr().map_err(io_error)?.ip(), bound.port()))
+}
@@ -0,0 +1,1271 @@
+// node.rs - 可嵌入的P2P节点
+// Swarm 由后台任务驱动，调用方通过 NodeHandle 发送命令，通过 NodeEvents 接收事件
+use crate::address;
+use crate::autonat::{self, ReachabilityStatus};
+use crate::bootstrap::{self, BootstrapNode, NodeStatus, RoutingTableEntry};
+use crate::config::{Config, KademliaMode};
+use crate::error::Error;
+use crate::identify::{self, PeerIdentity};
+use crate::peer_stun::{self, RequestId};
+use crate::relay::{self, RelayServerConfig};
+use crate::stun::{StunProbe, StunResult};
+use crate::stun_auth::StunCredentials;
+use crate::stun_server;
+use crate::turn_transport::{TurnPermissions, TurnTransport};
+use libp2p::{
+    identity,
+    Multiaddr,
+    PeerId,
+    Swarm,
+    core::{ConnectedPoint, muxing::StreamMuxerBox, transport::ListenerId},
+    kad::{self, Mode, QueryId, QueryResult, GetProvidersOk, GetRecordOk, PeerRecord, Quorum, Record, RecordKey},
+    ping,
+    swarm::{ConnectionError, DialError, ListenError, NetworkBehaviour, SwarmEvent, dial_opts::DialOpts},
+    Transport, tcp, yamux, noise,
+    futures::StreamExt,
+};
+use either::Either;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+// 定义节点的行为，结合节点间 STUN、Kademlia DHT、Ping、identify、AutoNAT、中继客户端、中继服务端和 DCUtR 打洞
+// 使用 #[derive(NetworkBehaviour)] 宏自动生成组合行为，按字段顺序轮询：
+// peer_stun 排在 kademlia 前面，查询刚找到的提供者在查询结束前被拨号，拨号时还能取得查询中的地址
+#[derive(NetworkBehaviour)]
+pub struct MyBehaviour {
+    pub peer_stun: peer_stun::Behaviour,
+    pub kademlia: kad::Behaviour<kad::store::MemoryStore>,
+    pub ping: ping::Behaviour,
+    // 未编译 identify、AutoNAT 或中继支持时为占位行为
+    pub identify: identify::Behaviour,
+    pub autonat: autonat::Behaviour,
+    pub relay_client: relay::ClientBehaviour,
+    pub relay_server: relay::ServerBehaviour,
+    pub dcutr: relay::DcutrBehaviour,
+}
+
+// 节点向调用方报告的事件
+#[derive(Debug)]
+pub enum NodeEvent {
+    // 新的监听地址
+    NewListenAddr { address: Multiaddr },
+    // 连接建立
+    ConnectionEstablished { peer_id: PeerId, endpoint: ConnectedPoint },
+    // 连接关闭
+    ConnectionClosed { peer_id: PeerId, cause: Option<ConnectionError> },
+    // 出站连接失败
+    OutgoingConnectionError { peer_id: Option<PeerId>, error: Error },
+    // 入站连接失败
+    IncomingConnectionError { local_addr: Multiaddr, send_back_addr: Multiaddr, error: ListenError },
+    // Bootstrap 查询进展
+    Bootstrap(kad::BootstrapResult),
+    // GetClosestPeers 查询结果
+    ClosestPeers(kad::GetClosestPeersResult),
+    // 路由表加入或更新了节点
+    RoutingUpdated { peer: PeerId },
+    // Ping 结果，成功时为往返时间
+    Ping { peer: PeerId, result: Result<Duration, ping::Failure> },
+    // 未能从 TURN 服务器得到中继地址，或中继地址失效
+    RelayFailed { error: io::Error },
+    // 在 TURN 服务器上安装权限失败，相应的对端暂时不能经中继连接本节点，下次刷新时重试
+    RelayPermissionFailed { error: io::Error },
+    // 在中继节点上预留了位置（renewal 为续期），其它节点可以经 /p2p-circuit 地址连接本节点
+    RelayReservation { relay: PeerId, renewal: bool },
+    // 经中继的连接上 DCUtR 打洞的结果，成功时双方已建立直接连接
+    HolePunch { peer_id: PeerId, result: Result<(), Error> },
+    // 本节点作为中继服务端的预留和转发情况
+    RelayServer(relay::ServerEvent),
+    // AutoNAT 探测后可达性或置信度发生变化
+    Reachability(ReachabilityStatus),
+    // 收到对端的 identify 信息
+    Identified { peer_id: PeerId, identity: PeerIdentity },
+}
+
+// 关闭连接时等待对端确认的最长时间
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 节点停止时的最终状态
+#[derive(Debug, Clone)]
+pub struct NodeSnapshot {
+    pub bootstrap_nodes: Vec<BootstrapNode>,
+    pub routing_table: Vec<RoutingTableEntry>,
+}
+
+impl NodeSnapshot {
+    // 将 Bootstrap 节点列表和路由表写入当前目录下的默认文件
+    pub fn save(&self) -> crate::error::Result<()> {
+        self.save_to(Path::new(bootstrap::BOOTSTRAPS_FILE), Path::new(bootstrap::ROUTING_TABLE_FILE))
+    }
+
+    pub fn save_to(&self, bootstraps_path: &Path, routing_table_path: &Path) -> crate::error::Result<()> {
+        bootstrap::save_bootstrap_nodes_to_path(&self.bootstrap_nodes, bootstraps_path)?;
+        bootstrap::save_routing_table_to_path(&self.routing_table, routing_table_path)?;
+        Ok(())
+    }
+}
+
+// 节点在 DHT 中宣告自己地址的键：以 PeerId 为键的提供者记录带有节点的外部地址，
+// 其它节点对该键执行 get_providers 即可得到可拨号的公网地址
+pub fn address_key(peer_id: &PeerId) -> RecordKey {
+    RecordKey::new(&peer_id.to_bytes())
+}
+
+// 开启 STUN 应答的节点以该键发布提供者记录，其它节点通过 get_providers 找到可用的“节点 STUN”
+pub fn stun_responder_key() -> RecordKey {
+    RecordKey::new(b"/p2p/stun-responders")
+}
+
+// 节点分享能正常应答的 STUN 服务器列表的记录键，值为 JSON 格式的 stun_servers::SharedStunServers
+pub fn stun_servers_key() -> RecordKey {
+    RecordKey::new(b"/p2p/stun-servers")
+}
+
+// 提供 Circuit Relay v2 中继服务的节点以该键发布提供者记录，中继客户端通过 get_providers 找到它们
+pub fn relay_key() -> RecordKey {
+    RecordKey::new(b"/p2p/relays")
+}
+
+// 一次节点 STUN 查询最多询问的节点数
+pub const DEFAULT_STUN_PEERS: usize = 4;
+
+// 节点事件流
+pub type NodeEvents = UnboundedReceiverStream<NodeEvent>;
+
+// 调用方发往节点任务的命令
+enum Command {
+    Dial { opts: DialOpts, reply: oneshot::Sender<Result<(), Error>> },
+    Bootstrap { reply: oneshot::Sender<Result<(), Error>> },
+    GetClosestPeers { peer: PeerId },
+    Publish { key: RecordKey, value: Vec<u8>, reply: oneshot::Sender<Result<(), Error>> },
+    Lookup { key: RecordKey, remote_only: bool, reply: oneshot::Sender<Result<Record, Error>> },
+    ListenAddresses { reply: oneshot::Sender<Vec<Multiaddr>> },
+    AddExternalAddress { address: Multiaddr, reply: oneshot::Sender<Result<(), Error>> },
+    ObserveAddress { peer: PeerId, reply: oneshot::Sender<Result<StunResult, Error>> },
+    PeerStun { limit: usize, reply: oneshot::Sender<Result<StunProbe, Error>> },
+    DialPeer { peer: PeerId, addresses: Vec<Multiaddr>, reply: oneshot::Sender<Result<(), Error>> },
+    PermitRelayPeer { ip: IpAddr, reply: oneshot::Sender<bool> },
+    DialBootstrapNodes { reply: oneshot::Sender<usize> },
+    BootstrapNodes { reply: oneshot::Sender<Vec<BootstrapNode>> },
+    Shutdown { reply: oneshot::Sender<NodeSnapshot> },
+}
+
+// 节点构建器
+pub struct NodeBuilder {
+    keypair: Option<identity::Keypair>,
+    listen_addrs: Vec<Multiaddr>,
+    bootstrap_nodes: Vec<BootstrapNode>,
+    saved_bootstrap_nodes: Vec<BootstrapNode>,
+    max_bootstrap_failures: u32,
+    kademlia_mode: Mode,
+    // 按 AutoNAT 确认的可达性切换 Kademlia 模式
+    reachability_mode: bool,
+    query_timeout: Option<Duration>,
+    ping_interval: Duration,
+    stun_responder: Option<u16>,
+    // TURN 服务器（host:port）及其凭据
+    turn_relay: Option<(String, Option<StunCredentials>)>,
+    // 在几个中继节点上预留位置
+    relay_reservations: usize,
+    // 为其它节点提供中继服务
+    relay_server: Option<RelayServerConfig>,
+}
+
+impl Default for NodeBuilder {
+    fn default() -> Self {
+        NodeBuilder {
+            keypair: None,
+            listen_addrs: Vec::new(),
+            bootstrap_nodes: Vec::new(),
+            saved_bootstrap_nodes: Vec::new(),
+            max_bootstrap_failures: bootstrap::DEFAULT_MAX_BOOTSTRAP_FAILURES,
+            kademlia_mode: Mode::Server,
+            reachability_mode: false,
+            query_timeout: None,
+            ping_interval: Duration::from_secs(10),
+            stun_responder: None,
+            turn_relay: None,
+            relay_reservations: relay::DEFAULT_RELAY_RESERVATIONS,
+            relay_server: None,
+        }
+    }
+}
+
+impl NodeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 按配置设置监听地址、Bootstrap 节点、Kademlia 模式、查询超时、Ping 间隔、STUN 应答、TURN 中继、中继预留和中继服务端
+    pub fn from_config(config: &Config) -> Self {
+        let mut builder = Self::new()
+            .kademlia_mode(config.kademlia_mode.into())
+            .reachability_mode(config.kademlia_mode == KademliaMode::Auto)
+            .ping_interval(config.ping_interval())
+            .relay_reservations(config.relay_reservations);
+        builder.query_timeout = config.query_timeout();
+        if config.stun_responder {
+            builder = builder.stun_responder(config.stun_responder_port);
+        }
+        if let Some(server) = &config.turn_server {
+            builder = builder.turn_relay(server, config.turn_credentials());
+        }
+        if config.relay_server {
+            builder = builder.relay_server(config.relay_server_config());
+        }
+        for addr in config.listen_multiaddrs() {
+            builder = builder.listen_on(addr);
+        }
+        for addr in config.bootstrap_multiaddrs() {
+            builder = builder.bootstrap(addr);
+        }
+        builder
+    }
+
+    // 节点身份密钥，未设置时生成随机的 Ed25519 密钥对
+    pub fn keypair(mut self, keypair: identity::Keypair) -> Self {
+        self.keypair = Some(keypair);
+        self
+    }
+
+    // 监听地址，可多次调用
+    pub fn listen_on(mut self, addr: Multiaddr) -> Self {
+        self.listen_addrs.push(addr);
+        self
+    }
+
+    // Bootstrap 节点地址，必须包含 /p2p/ PeerId，否则会被忽略
+    pub fn bootstrap(mut self, addr: Multiaddr) -> Self {
+        if let Some(peer_id) = bootstrap::peer_id_from_multiaddr(&addr) {
+            self.bootstrap_nodes.push(BootstrapNode::new(&addr, &peer_id));
+        }
+        self
+    }
+
+    // 上次运行保存的 Bootstrap 节点，与内置节点合并去重
+    pub fn saved_bootstrap_nodes(mut self, nodes: impl IntoIterator<Item = BootstrapNode>) -> Self {
+        self.saved_bootstrap_nodes.extend(nodes);
+        self
+    }
+
+    // Bootstrap 节点连续失败多少次后被移除
+    pub fn max_bootstrap_failures(mut self, max_failures: u32) -> Self {
+        self.max_bootstrap_failures = max_failures;
+        self
+    }
+
+    // Kademlia 模式，默认为服务器模式
+    pub fn kademlia_mode(mut self, mode: Mode) -> Self {
+        self.kademlia_mode = mode;
+        self
+    }
+
+    // 按 AutoNAT 确认的可达性切换 Kademlia 模式：公网可达时为服务器，不可达时为客户端，确认前保持 kademlia_mode
+    // 需要以 --features autonat 编译，否则可达性始终未知，模式不变
+    pub fn reachability_mode(mut self, enabled: bool) -> Self {
+        self.reachability_mode = enabled;
+        self
+    }
+
+    // Kademlia 查询超时时间
+    pub fn query_timeout(mut self, timeout: Duration) -> Self {
+        self.query_timeout = Some(timeout);
+        self
+    }
+
+    // Ping 间隔
+    pub fn ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = interval;
+        self
+    }
+
+    // 开启 STUN 应答：在 UDP 端口 port（0 为随机端口）和 libp2p 协议流上告诉其它节点它们被观察到的地址
+    pub fn stun_responder(mut self, port: u16) -> Self {
+        self.stun_responder = Some(port);
+        self
+    }
+
+    // 通过 TURN 服务器（host:port）申请中继地址并在其上监听，直接连接都失败时其它节点经中继连接本节点
+    // 中继地址会作为外部地址宣告；服务器只转发来自有权限 IP 的数据，路由表中节点的 IP 自动获得权限
+    pub fn turn_relay(mut self, server: &str, credentials: Option<StunCredentials>) -> Self {
+        self.turn_relay = Some((server.to_string(), credentials));
+        self
+    }
+
+    // 在最多 count 个中继节点上预留位置（0 为不预留），中继节点通过 DHT 中 relay_key 的提供者记录找到
+    // 预留的 /p2p-circuit 地址作为外部地址宣告；未编译中继支持（--features relay）时忽略
+    pub fn relay_reservations(mut self, count: usize) -> Self {
+        self.relay_reservations = count;
+        self
+    }
+
+    // 作为 Circuit Relay v2 中继服务端，按 config 的限制接受其它节点的预留和转发请求
+    // 有外部地址后以 relay_key 发布提供者记录，中继客户端由此找到本节点；需要以 --features relay 编译
+    pub fn relay_server(mut self, config: RelayServerConfig) -> Self {
+        self.relay_server = Some(config);
+        self
+    }
+
+    // 创建传输层、行为和 Swarm，并开始监听
+    pub fn build(self) -> crate::error::Result<Node> {
+        let local_key = self.keypair.unwrap_or_else(identity::Keypair::generate_ed25519);
+        // 从公钥获取 PeerId
+        let local_peer_id = PeerId::from(local_key.public());
+
+        // 中继传输层排在 TCP 之后，只处理 TCP 不支持的 UDP 地址；配置了 TURN 服务器时才能在中继地址上监听
+        let turn = match &self.turn_relay {
+            Some((server, credentials)) => TurnTransport::with_server(server, credentials.clone())?,
+            None => TurnTransport::new(),
+        };
+        let turn_listen_addr = turn.listen_address().cloned();
+        let turn_permissions = turn_listen_addr.as_ref().map(|_| turn.permissions());
+
+        // 中继客户端：传输层只处理 /p2p-circuit 地址，行为负责预留和建立经中继的连接
+        #[cfg(feature = "relay")]
+        let (relay_transport, relay_client) = libp2p::relay::client::new(local_peer_id);
+        #[cfg(not(feature = "relay"))]
+        let relay_client = libp2p::swarm::dummy::Behaviour;
+
+        // 创建TCP传输层和中继传输层
+        let transport = tcp::tokio::Transport::new(tcp::Config::default())
+            .or_transport(turn)
+            .map_err(|e| match e {
+                Either::Left(e) | Either::Right(e) => e,
+            });
+        #[cfg(feature = "relay")]
+        let transport = transport.or_transport(relay_transport).map_err(|e| match e {
+            Either::Left(e) => e,
+            Either::Right(e) => io::Error::other(e),
+        });
+
+        // 添加噪声协议和Yamux多路复用器，经中继的连接同样端到端加密
+        let transport = transport
+            .upgrade(libp2p::core::upgrade::Version::V1)
+            .authenticate(noise::Config::new(&local_key).map_err(|e| Error::Transport(e.to_string()))?)
+            .multiplex(yamux::Config::default())
+            // 传输层的 IO 错误原样保留，拨号失败时可以取得 ErrorKind
+            .map_err(|e| match e {
+                Either::Left(Either::Left(e)) => e,
+                e => io::Error::other(e),
+            })
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)));
+
+        // QUIC 自带 TLS 加密和多路复用，与 TCP 组合后按地址选择：/udp/<port>/quic-v1 走 QUIC，其余交给 TCP 和中继
+        #[cfg(feature = "quic")]
+        let transport = libp2p::quic::tokio::Transport::new(libp2p::quic::Config::new(&local_key))
+            .or_transport(transport)
+            .map_err(|e| match e {
+                Either::Left(e) => io::Error::other(e),
+                Either::Right(e) => e,
+            })
+            .map(|output, _| match output {
+                libp2p::futures::future::Either::Left((peer_id, connection)) => (peer_id, StreamMuxerBox::new(connection)),
+                libp2p::futures::future::Either::Right(output) => output,
+            });
+
+        let transport = transport.boxed();
+
+        // 创建 Kademlia 行为
+        let mut cfg = kad::Config::default();
+        if let Some(timeout) = self.query_timeout {
+            cfg.set_query_timeout(timeout);
+        }
+        let store = kad::store::MemoryStore::new(local_peer_id);
+        let mut kademlia = kad::Behaviour::with_config(local_peer_id, store, cfg);
+        kademlia.set_mode(Some(self.kademlia_mode));
+
+        // 添加 DHT Bootstrap 节点，并记录下来以便跟踪其状态
+        let mut bootstrap_nodes = bootstrap::merge_bootstrap_nodes(self.bootstrap_nodes, self.saved_bootstrap_nodes);
+        bootstrap::prune_dead_bootstrap_nodes(&mut bootstrap_nodes, self.max_bootstrap_failures);
+        bootstrap::rank_bootstrap_nodes(&mut bootstrap_nodes);
+        for node in &bootstrap_nodes {
+            if let Some((peer_id, addresses)) = node.dial_addresses() {
+                for addr in addresses {
+                    kademlia.add_address(&peer_id, addr);
+                }
+            }
+        }
+
+        // 与每个连接的节点交换版本和地址信息
+        let identify = identify::new_behaviour(local_key.public());
+
+        // AutoNAT 请 Bootstrap 节点和已连接的节点回拨本节点的地址
+        let autonat_servers: Vec<(PeerId, Multiaddr)> = bootstrap_nodes
+            .iter()
+            .filter_map(BootstrapNode::dial_addresses)
+            .filter_map(|(peer_id, addresses)| Some((peer_id, addresses.into_iter().next()?)))
+            .collect();
+        let autonat = autonat::new_behaviour(local_peer_id, &autonat_servers);
+
+        // 创建 Ping 行为
+        let ping = ping::Behaviour::new(ping::Config::new().with_interval(self.ping_interval));
+
+        // 经中继连接后协调双方同时拨号，打洞成功后改用直接连接
+        #[cfg(feature = "relay")]
+        let dcutr = libp2p::dcutr::Behaviour::new(local_peer_id);
+        #[cfg(not(feature = "relay"))]
+        let dcutr = libp2p::swarm::dummy::Behaviour;
+
+        // 中继服务端，未编译中继支持时不能开启
+        if self.relay_server.is_some() && !relay::is_supported() {
+            return Err(Error::FeatureDisabled { feature: "relay" });
+        }
+        let relay_server = relay::server_behaviour(local_peer_id, self.relay_server.as_ref());
+
+        // 节点间 STUN，只有开启应答时才绑定 UDP 端口
+        let peer_stun = peer_stun::Behaviour::new(self.stun_responder.is_some());
+        // IPv4 套接字必须绑定成功；主机没有 IPv6 时只在 IPv4 上应答
+        let mut stun_sockets = Vec::new();
+        if let Some(port) = self.stun_responder {
+            stun_sockets.push(stun_server::bind_udp_responder(SocketAddr::from(([0, 0, 0, 0], port)))?);
+            if let Ok(socket) = stun_server::bind_udp_responder(SocketAddr::from(([0u16; 8], port))) {
+                stun_sockets.push(socket);
+            }
+        }
+
+        // 创建Swarm
+        let mut swarm = Swarm::new(
+            transport,
+            MyBehaviour { peer_stun, kademlia, ping, identify, autonat, relay_client, relay_server, dcutr },
+            local_peer_id,
+            libp2p::swarm::Config::with_executor(|fut| { tokio::spawn(fut); }), // 使用tokio执行器
+        );
+
+        // 双栈监听时主机可能不支持其中一个地址族，只有全部监听地址都失败时才报错
+        let mut listeners = Vec::new();
+        let mut listen_errors = Vec::new();
+        let mut last_error = None;
+        for addr in self.listen_addrs {
+            match swarm.listen_on(addr.clone()) {
+                Ok(listener) => listeners.push(listener),
+                Err(e) => {
+                    listen_errors.push((addr.clone(), e.to_string()));
+                    last_error = Some(Error::Listen { address: addr, source: e });
+                }
+            }
+        }
+        if let (true, Some(e)) = (listeners.is_empty(), last_error) {
+            return Err(e);
+        }
+        // 中继地址在后台向 TURN 服务器申请，申请失败时以 ListenerClosed 结束，不影响其它监听地址
+        let turn_listener = match turn_listen_addr {
+            Some(address) => Some(swarm.listen_on(address.clone()).map_err(|source| Error::Listen { address, source })?),
+            None => None,
+        };
+
+        Ok(Node {
+            swarm,
+            listeners,
+            listen_errors,
+            bootstrap_nodes,
+            max_bootstrap_failures: self.max_bootstrap_failures,
+            stun_sockets,
+            turn_listener,
+            turn_permissions,
+            relay_reservations: if relay::is_supported() { self.relay_reservations } else { 0 },
+            relay_server: self.relay_server.is_some(),
+            reachability_mode: self.reachability_mode,
+        })
+    }
+}
+
+// 已构建但尚未运行的节点
+pub struct Node {
+    swarm: Swarm<MyBehaviour>,
+    listeners: Vec<ListenerId>,
+    listen_errors: Vec<(Multiaddr, String)>,
+    bootstrap_nodes: Vec<BootstrapNode>,
+    max_bootstrap_failures: u32,
+    stun_sockets: Vec<tokio::net::UdpSocket>,
+    turn_listener: Option<ListenerId>,
+    turn_permissions: Option<TurnPermissions>,
+    relay_reservations: usize,
+    relay_server: bool,
+    reachability_mode: bool,
+}
+
+impl Node {
+    pub fn local_peer_id(&self) -> PeerId {
+        *self.swarm.local_peer_id()
+    }
+
+    // STUN 应答的 UDP 地址（IPv4 在前），未开启应答时为空
+    pub fn stun_responder_addresses(&self) -> Vec<SocketAddr> {
+        self.stun_sockets.iter().filter_map(|socket| socket.local_addr().ok()).collect()
+    }
+
+    // 未能监听的地址及原因
+    pub fn listen_errors(&self) -> &[(Multiaddr, String)] {
+        &self.listen_errors
+    }
+
+    // 在 tokio 任务中运行事件循环，返回命令句柄和事件流
+    pub fn spawn(self) -> (NodeHandle, NodeEvents) {
+        let local_peer_id = self.local_peer_id();
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+
+        let stun_responders = self.stun_sockets.into_iter().map(|socket| tokio::spawn(stun_server::serve_udp(socket))).collect();
+        let mut listeners = self.listeners;
+        listeners.extend(self.turn_listener);
+        let task = NodeTask {
+            swarm: self.swarm,
+            listeners,
+            bootstrap_nodes: self.bootstrap_nodes,
+            max_bootstrap_failures: self.max_bootstrap_failures,
+            commands: command_rx,
+            events: event_tx,
+            pending_publish: HashMap::new(),
+            pending_lookup: HashMap::new(),
+            pending_observe: HashMap::new(),
+            pending_peer_stun: HashMap::new(),
+            peer_stun_queries: HashMap::new(),
+            stun_responders,
+            turn_listener: self.turn_listener,
+            turn_permissions: self.turn_permissions,
+            dial_fallback: HashMap::new(),
+            relay_reservations: self.relay_reservations,
+            relay_queries: HashSet::new(),
+            relay_candidates: HashSet::new(),
+            relay_listeners: HashMap::new(),
+            relay_server: self.relay_server,
+            reachability: ReachabilityStatus::default(),
+            reachability_mode: self.reachability_mode,
+            identities: HashMap::new(),
+        };
+        tokio::spawn(task.run());
+
+        (
+            NodeHandle { local_peer_id, commands: command_tx },
+            UnboundedReceiverStream::new(event_rx),
+        )
+    }
+}
+
+// 节点命令句柄，可克隆后在多个任务间共享
+#[derive(Clone)]
+pub struct NodeHandle {
+    local_peer_id: PeerId,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl NodeHandle {
+    pub fn local_peer_id(&self) -> PeerId {
+        self.local_peer_id
+    }
+
+    // 发起拨号，连接结果通过事件流报告
+    pub async fn dial(&self, opts: impl Into<DialOpts>) -> Result<(), Error> {
+        let opts = opts.into();
+        self.request(|reply| Command::Dial { opts, reply }).await?
+    }
+
+    // 拨号到 peer：addresses 和路由表中已知的地址按 address::rank_addresses 排序，
+    // 本节点和对方都有公网 IPv6 时优先直接用 IPv6 连接；QUIC、TCP 和中继地址依次分批拨号，前一批全部失败后才拨号下一批
+    pub async fn dial_peer(&self, peer: PeerId, addresses: Vec<Multiaddr>) -> Result<(), Error> {
+        self.request(|reply| Command::DialPeer { peer, addresses, reply }).await?
+    }
+
+    // 允许 ip 经 TURN 中继连接本节点，返回是否为新加入的 IP；未配置 TURN 服务器时返回 false
+    pub async fn permit_relay_peer(&self, ip: IpAddr) -> Result<bool, Error> {
+        self.request(|reply| Command::PermitRelayPeer { ip, reply }).await
+    }
+
+    // 启动一次 Bootstrap 查询，查询进展通过事件流报告
+    pub async fn bootstrap(&self) -> Result<(), Error> {
+        self.request(|reply| Command::Bootstrap { reply }).await?
+    }
+
+    // 查找距离 peer 最近的节点，结果通过事件流报告
+    pub fn get_closest_peers(&self, peer: PeerId) -> Result<(), Error> {
+        self.commands.send(Command::GetClosestPeers { peer }).map_err(|_| Error::Stopped)
+    }
+
+    // 在 DHT 中发布记录，等待查询完成
+    pub async fn publish(&self, key: RecordKey, value: Vec<u8>) -> Result<(), Error> {
+        self.request(|reply| Command::Publish { key, value, reply }).await?
+    }
+
+    // 在 DHT 中查找记录，返回找到的第一条
+    pub async fn lookup(&self, key: RecordKey) -> Result<Record, Error> {
+        self.request(|reply| Command::Lookup { key, remote_only: false, reply }).await?
+    }
+
+    // 在 DHT 中查找其它节点发布的记录，跳过本节点发布的记录
+    // 多个节点在同一个键下发布时，本地存储的总是自己的版本
+    pub async fn lookup_remote(&self, key: RecordKey) -> Result<Record, Error> {
+        self.request(|reply| Command::Lookup { key, remote_only: true, reply }).await?
+    }
+
+    // 当前的监听地址
+    pub async fn listen_addresses(&self) -> Result<Vec<Multiaddr>, Error> {
+        self.request(|reply| Command::ListenAddresses { reply }).await
+    }
+
+    // 登记经过确认的外部地址，并以 address_key 重新发布提供者记录，让其它节点通过 DHT 得到该地址
+    pub async fn add_external_address(&self, address: Multiaddr) -> Result<(), Error> {
+        self.request(|reply| Command::AddExternalAddress { address, reply }).await?
+    }
+
+    // 请求已知节点报告它看到的本机地址，对端需要开启 STUN 应答
+    pub async fn observe_address(&self, peer: PeerId) -> Result<StunResult, Error> {
+        self.request(|reply| Command::ObserveAddress { peer, reply }).await?
+    }
+
+    // 通过 DHT 找到开启 STUN 应答的节点，最多询问 limit 个，结果格式与查询 STUN 服务器相同
+    pub async fn peer_stun(&self, limit: usize) -> Result<StunProbe, Error> {
+        self.request(|reply| Command::PeerStun { limit, reply }).await?
+    }
+
+    // 按评分从高到低拨号尚未连接的 Bootstrap 节点，返回发起的拨号数
+    pub async fn dial_bootstrap_nodes(&self) -> Result<usize, Error> {
+        self.request(|reply| Command::DialBootstrapNodes { reply }).await
+    }
+
+    // 当前跟踪的 Bootstrap 节点状态快照
+    pub async fn bootstrap_nodes(&self) -> Result<Vec<BootstrapNode>, Error> {
+        self.request(|reply| Command::BootstrapNodes { reply }).await
+    }
+
+    // 停止监听、关闭所有连接并停止事件循环，返回最终的 Bootstrap 节点状态和路由表
+    pub async fn shutdown(&self) -> Result<NodeSnapshot, Error> {
+        self.request(|reply| Command::Shutdown { reply }).await
+    }
+
+    async fn request<T>(&self, command: impl FnOnce(oneshot::Sender<T>) -> Command) -> Result<T, Error> {
+        let (reply, response) = oneshot::channel();
+        self.commands.send(command(reply)).map_err(|_| Error::Stopped)?;
+        response.await.map_err(|_| Error::Stopped)
+    }
+}
+
+// 在后台运行的节点任务
+struct NodeTask {
+    swarm: Swarm<MyBehaviour>,
+    listeners: Vec<ListenerId>,
+    bootstrap_nodes: Vec<BootstrapNode>,
+    max_bootstrap_failures: u32,
+    commands: mpsc::UnboundedReceiver<Command>,
+    events: mpsc::UnboundedSender<NodeEvent>,
+    pending_publish: HashMap<QueryId, oneshot::Sender<Result<(), Error>>>,
+    // 查询 ID 到（是否跳过本节点发布的记录，应答）
+    pending_lookup: HashMap<QueryId, (bool, oneshot::Sender<Result<Record, Error>>)>,
+    pending_observe: HashMap<RequestId, oneshot::Sender<Result<StunResult, Error>>>,
+    pending_peer_stun: HashMap<QueryId, PeerStunQuery>,
+    // 节点 STUN 请求所属的 get_providers 查询
+    peer_stun_queries: HashMap<RequestId, QueryId>,
+    // UDP STUN 应答任务，每个地址族一个
+    stun_responders: Vec<JoinHandle<io::Result<()>>>,
+    // TURN 中继的监听器和对端权限
+    turn_listener: Option<ListenerId>,
+    turn_permissions: Option<TurnPermissions>,
+    // 分阶段拨号的节点：前一批地址全部失败后再拨号的地址（QUIC 之后是 TCP，直接地址之后是中继地址）
+    dial_fallback: HashMap<PeerId, VecDeque<Vec<Multiaddr>>>,
+    // 中继预留：目标数量、查找中继的 get_providers 查询、等待连接的中继和在中继上的监听器
+    relay_reservations: usize,
+    relay_queries: HashSet<QueryId>,
+    relay_candidates: HashSet<PeerId>,
+    relay_listeners: HashMap<ListenerId, PeerId>,
+    // 是否为其它节点提供中继服务
+    relay_server: bool,
+    // AutoNAT 的最新结论，以及是否据此切换 Kademlia 模式；未编译 AutoNAT 时不使用
+    #[cfg_attr(not(feature = "autonat"), allow(dead_code))]
+    reachability: ReachabilityStatus,
+    #[cfg_attr(not(feature = "autonat"), allow(dead_code))]
+    reachability_mode: bool,
+    // 对端最近一次通过 identify 报告的信息，保存路由表时写入版本
+    identities: HashMap<PeerId, PeerIdentity>,
+}
+
+// 进行中的节点 STUN 查询：对 get_providers 找到的每个提供者发送请求，查询结束且所有请求完成后应答
+struct PeerStunQuery {
+    limit: usize,
+    peers: Vec<PeerId>,
+    outstanding: HashSet<RequestId>,
+    finished: bool,
+    probe: StunProbe,
+    reply: oneshot::Sender<Result<StunProbe, Error>>,
+}
+
+impl NodeTask {
+    async fn run(mut self) {
+        loop {
+            tokio::select! {
+                event = self.swarm.select_next_some() => self.handle_swarm_event(event),
+                command = self.commands.recv() => match command {
+                    Some(Command::Shutdown { reply }) => {
+                        let snapshot = self.snapshot();
+                        self.close().await;
+                        let _ = reply.send(snapshot);
+                        break;
+                    }
+                    Some(command) => self.handle_command(command),
+                    // 所有句柄都已释放
+                    None => break,
+                },
+            }
+        }
+        for responder in self.stun_responders.drain(..) {
+            responder.abort();
+        }
+    }
+
+    // 本节点是否有公网 IPv6 地址（监听在公网 IPv6 接口上，或已确认的外部地址）
+    fn has_global_ipv6(&self) -> bool {
+        self.swarm.listeners().chain(self.swarm.external_addresses()).any(address::is_global_ipv6_addr)
+    }
+
+    fn snapshot(&mut self) -> NodeSnapshot {
+        let mut routing_table = Vec::new();
+        for bucket in self.swarm.behaviour_mut().kademlia.kbuckets() {
+            for entry in bucket.iter() {
+                let peer = entry.node.key.preimage();
+                let identity = self.identities.get(peer);
+                routing_table.push(RoutingTableEntry {
+                    peer_id: peer.to_string(),
+                    addresses: entry.node.value.iter().map(|addr| addr.to_string()).collect(),
+                    agent_version: identity.map(|identity| identity.agent_version.clone()),
+                    protocol_version: identity.map(|identity| identity.protocol_version.clone()),
+                });
+            }
+        }
+        NodeSnapshot { bootstrap_nodes: self.bootstrap_nodes.clone(), routing_table }
+    }
+
+    // 停止接受新连接并关闭现有连接，等待连接关闭完成或超时
+    async fn close(&mut self) {
+        for listener in self.listeners.drain(..) {
+            self.swarm.remove_listener(listener);
+        }
+        let peers: Vec<PeerId> = self.swarm.connected_peers().copied().collect();
+        for peer in peers {
+            let _ = self.swarm.disconnect_peer_id(peer);
+        }
+        let swarm = &mut self.swarm;
+        let _ = timeout(CLOSE_TIMEOUT, async {
+            while swarm.connected_peers().next().is_some() {
+                swarm.select_next_some().await;
+            }
+        })
+        .await;
+    }
+
+    fn emit(&self, event: NodeEvent) {
+        // 调用方不再接收事件时忽略
+        let _ = self.events.send(event);
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        let kademlia = &mut self.swarm.behaviour_mut().kademlia;
+        match command {
+            Command::Dial { opts, reply } => {
+                let peer_id = opts.get_peer_id();
+                let _ = reply.send(self.swarm.dial(opts).map_err(|e| Error::from_dial(peer_id, &e)));
+            }
+            Command::Bootstrap { reply } => {
+                let _ = reply.send(kademlia.bootstrap().map(|_| ()).map_err(|_| Error::NoKnownPeers));
+            }
+            Command::GetClosestPeers { peer } => {
+                kademlia.get_closest_peers(peer);
+            }
+            Command::Publish { key, value, reply } => {
+                match kademlia.put_record(Record::new(key, value), Quorum::One) {
+                    Ok(query_id) => {
+                        self.pending_publish.insert(query_id, reply);
+                    }
+                    Err(e) => {
+                        let _ = reply.send(Err(Error::Store(e)));
+                    }
+                }
+            }
+            Command::Lookup { key, remote_only, reply } => {
+                let query_id = kademlia.get_record(key);
+                self.pending_lookup.insert(query_id, (remote_only, reply));
+            }
+            Command::ListenAddresses { reply } => {
+                let _ = reply.send(self.swarm.listeners().cloned().collect());
+            }
+            Command::AddExternalAddress { address, reply } => {
+                let key = address_key(self.swarm.local_peer_id());
+                // 同时作为地址候选告诉其它行为，DCUtR 打洞时把它发给对方
+                self.swarm.behaviour_mut().peer_stun.add_address_candidate(address.clone());
+                self.swarm.add_external_address(address);
+                let behaviour = self.swarm.behaviour_mut();
+                let mut provided = behaviour.kademlia.start_providing(key).map(|_| ());
+                // 有了外部地址，提供者记录才能告诉其它节点如何连接本节点的 STUN 应答和中继服务
+                if provided.is_ok() && behaviour.peer_stun.is_responder() {
+                    provided = behaviour.kademlia.start_providing(stun_responder_key()).map(|_| ());
+                }
+                if provided.is_ok() && self.relay_server {
+                    provided = behaviour.kademlia.start_providing(relay_key()).map(|_| ());
+                }
+                let _ = reply.send(provided.map_err(Error::Store));
+            }
+            Command::ObserveAddress { peer, reply } => {
+                let request_id = self.swarm.behaviour_mut().peer_stun.observe(peer);
+                self.pending_observe.insert(request_id, reply);
+            }
+            Command::PeerStun { limit, reply } => {
+                let query_id = kademlia.get_providers(stun_responder_key());
+                let query = PeerStunQuery {
+                    limit,
+                    peers: Vec::new(),
+                    outstanding: HashSet::new(),
+                    finished: false,
+                    probe: StunProbe::default(),
+                    reply,
+                };
+                self.pending_peer_stun.insert(query_id, query);
+            }
+            Command::DialPeer { peer, mut addresses, reply } => {
+                addresses.extend(self.routing_addresses(peer));
+                let mut seen = HashSet::new();
+                addresses.retain(|addr| seen.insert(addr.clone()));
+                address::rank_addresses(&mut addresses, self.has_global_ipv6());
+                // 中继地址是最后手段：有直接地址时先只拨号直接地址，全部失败后再拨号中继地址
+                // 对方同时提供 QUIC 和 TCP 地址时先只拨号 QUIC，UDP 映射更容易穿透 NAT，QUIC 全部失败后再用 TCP
+                let (relayed, direct): (Vec<Multiaddr>, Vec<Multiaddr>) =
+                    addresses.into_iter().partition(address::is_relayed);
+                let (quic, tcp): (Vec<Multiaddr>, Vec<Multiaddr>) = if cfg!(feature = "quic") {
+                    direct.into_iter().partition(address::is_quic_address)
+                } else {
+                    (Vec::new(), direct)
+                };
+                let mut stages: VecDeque<Vec<Multiaddr>> =
+                    [quic, tcp, relayed].into_iter().filter(|stage| !stage.is_empty()).collect();
+                let addresses = stages.pop_front().unwrap_or_default();
+                if !stages.is_empty() {
+                    self.dial_fallback.insert(peer, stages);
+                }
+                // Swarm 按顺序并发拨号前几个地址（dial_concurrency_factor），排在前面的地址先尝试
+                let opts = DialOpts::peer_id(peer).addresses(addresses).build();
+                let _ = reply.send(self.swarm.dial(opts).map_err(|e| Error::from_dial(Some(peer), &e)));
+            }
+            Command::PermitRelayPeer { ip, reply } => {
+                let _ = reply.send(self.turn_permissions.as_ref().is_some_and(|permissions| permissions.permit(ip)));
+            }
+            Command::DialBootstrapNodes { reply } => {
+                bootstrap::rank_bootstrap_nodes(&mut self.bootstrap_nodes);
+                let local_has_ipv6 = self.has_global_ipv6();
+                let mut dialed = 0;
+                for node in &self.bootstrap_nodes {
+                    let Some((peer_id, mut addresses)) = node.dial_addresses() else { continue };
+                    if self.swarm.is_connected(&peer_id) {
+                        continue;
+                    }
+                    address::rank_addresses(&mut addresses, local_has_ipv6);
+                    let opts = DialOpts::peer_id(peer_id).addresses(addresses).build();
+                    if self.swarm.dial(opts).is_ok() {
+                        dialed += 1;
+                    }
+                }
+                let _ = reply.send(dialed);
+            }
+            Command::BootstrapNodes { reply } => {
+                let _ = reply.send(self.bootstrap_nodes.clone());
+            }
+            Command::Shutdown { .. } => unreachable!("shutdown is handled by the event loop"),
+        }
+    }
+
+    fn handle_swarm_event(&mut self, event: SwarmEvent<MyBehaviourEvent>) {
+        match event {
+            SwarmEvent::NewListenAddr { listener_id, address } => {
+                // TURN 中继地址和中继节点上的 /p2p-circuit 地址对其它节点可达，直接作为外部地址宣告
+                if Some(listener_id) == self.turn_listener || self.relay_listeners.contains_key(&listener_id) {
+                    let key = address_key(self.swarm.local_peer_id());
+                    self.swarm.add_external_address(address.clone());
+                    let _ = self.swarm.behaviour_mut().kademlia.start_providing(key);
+                }
+                self.emit(NodeEvent::NewListenAddr { address });
+            }
+            SwarmEvent::ListenerClosed { listener_id, reason: Err(error), .. } if Some(listener_id) == self.turn_listener => {
+                self.turn_listener = None;
+                self.emit(NodeEvent::RelayFailed { error });
+            }
+            SwarmEvent::ListenerError { listener_id, error } if Some(listener_id) == self.turn_listener => {
+                self.emit(NodeEvent::RelayPermissionFailed { error });
+            }
+            // 中继拒绝预留或连接断开，改找其它中继
+            SwarmEvent::ListenerClosed { listener_id, .. } if self.relay_listeners.contains_key(&listener_id) => {
+                self.relay_listeners.remove(&listener_id);
+                self.find_relays();
+            }
+            SwarmEvent::Behaviour(MyBehaviourEvent::RelayClient(event)) => self.handle_relay_client_event(event),
+            SwarmEvent::Behaviour(MyBehaviourEvent::Identify(event)) => self.handle_identify_event(event),
+            SwarmEvent::Behaviour(MyBehaviourEvent::Autonat(event)) => self.handle_autonat_event(event),
+            SwarmEvent::Behaviour(MyBehaviourEvent::RelayServer(event)) => self.handle_relay_server_event(event),
+            SwarmEvent::Behaviour(MyBehaviourEvent::Dcutr(event)) => self.handle_dcutr_event(event),
+            SwarmEvent::Behaviour(MyBehaviourEvent::Kademlia(kad_event)) => {
+                self.handle_kademlia_event(kad_event);
+            }
+            SwarmEvent::Behaviour(MyBehaviourEvent::PeerStun(peer_stun::Event { request_id, result, .. })) => {
+                if let Some(reply) = self.pending_observe.remove(&request_id) {
+                    let _ = reply.send(result);
+                } else if let Some(query_id) = self.peer_stun_queries.remove(&request_id) {
+                    if let Some(query) = self.pending_peer_stun.get_mut(&query_id) {
+                        query.outstanding.remove(&request_id);
+                        match result {
+                            Ok(result) => query.probe.results.push(result),
+                            Err(e) => query.probe.failures.push(e),
+                        }
+                    }
+                    self.complete_peer_stun(query_id);
+                }
+            }
+            SwarmEvent::Behaviour(MyBehaviourEvent::Ping(ping::Event { peer, result, .. })) => {
+                match &result {
+                    Ok(rtt) => {
+                        bootstrap::update_bootstrap_node_response_time(&mut self.bootstrap_nodes, &peer.to_string(), *rtt);
+                        bootstrap::touch_bootstrap_node(&mut self.bootstrap_nodes, &peer.to_string());
+                    }
+                    Err(_) => self.mark_bootstrap_failure(peer),
+                }
+                self.emit(NodeEvent::Ping { peer, result });
+            }
+            SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                self.dial_fallback.remove(&peer_id);
+                // 连上了 DHT 中找到的中继，用这条连接的地址在中继上预留位置
+                if self.relay_candidates.contains(&peer_id)
+                    && let ConnectedPoint::Dialer { address, .. } = &endpoint
+                    && !address::is_relayed(address)
+                {
+                    self.listen_via_relay(peer_id, &address.clone());
+                }
+                // 每条新连接计一次成功
+                bootstrap::update_bootstrap_node_status(&mut self.bootstrap_nodes, &peer_id.to_string(), NodeStatus::Active);
+                self.emit(NodeEvent::ConnectionEstablished { peer_id, endpoint });
+            }
+            // 正常关闭（空闲超时、打洞成功后关闭中继连接、本节点关闭）不算失败，失败只来自拨号和 Ping
+            SwarmEvent::ConnectionClosed { peer_id, cause, num_established, .. } => {
+                if num_established == 0 {
+                    self.forget_identity(&peer_id);
+                }
+                self.emit(NodeEvent::ConnectionClosed { peer_id, cause });
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                if let Some(peer_id) = peer_id {
+                    self.relay_candidates.remove(&peer_id);
+                    self.dial_next_stage(peer_id);
+                    self.mark_bootstrap_failure(peer_id);
+                    // 对端身份与路由表中的不符，移除旧的路由信息
+                    if let DialError::WrongPeerId { .. } = error {
+                        self.swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
+                        if let Some(permissions) = &self.turn_permissions {
+                            permissions.revoke_peer(&peer_id);
+                        }
+                        self.forget_identity(&peer_id);
+                    }
+                }
+                self.emit(NodeEvent::OutgoingConnectionError { peer_id, error: Error::from_dial(peer_id, &error) });
+            }
+            SwarmEvent::IncomingConnectionError { local_addr, send_back_addr, error, .. } => {
+                self.emit(NodeEvent::IncomingConnectionError { local_addr, send_back_addr, error });
+            }
+            _ => {}
+        }
+    }
+
+    // 路由表中 peer 的地址
+    fn routing_addresses(&mut self, peer: PeerId) -> Vec<Multiaddr> {
+        for bucket in self.swarm.behaviour_mut().kademlia.kbuckets() {
+            if let Some(entry) = bucket.iter().find(|entry| *entry.node.key.preimage() == peer) {
+                return entry.node.value.iter().cloned().collect();
+            }
+        }
+        Vec::new()
+    }
+
+    // 已预留和正在连接的中继不足 relay_reservations 个时，在 DHT 中查找提供中继服务的节点
+    fn find_relays(&mut self) {
+        let reserved = self.relay_listeners.len() + self.relay_candidates.len();
+        if reserved >= self.relay_reservations || !self.relay_queries.is_empty() {
+            return;
+        }
+        let query_id = self.swarm.behaviour_mut().kademlia.get_providers(relay_key());
+        self.relay_queries.insert(query_id);
+    }
+
+    // 在查询找到的中继上预留位置：路由表中有中继的直接地址时直接监听，否则先拨号（地址来自查询），连接建立后再监听
+    fn reserve_on_relays(&mut self, providers: HashSet<PeerId>) {
+        let local_peer_id = *self.swarm.local_peer_id();
+        for relay in providers {
+            if self.relay_listeners.len() + self.relay_candidates.len() >= self.relay_reservations {
+                break;
+            }
+            if relay == local_peer_id
+                || self.relay_candidates.contains(&relay)
+                || self.relay_listeners.values().any(|reserved| *reserved == relay)
+            {
+                continue;
+            }
+            match self.routing_addresses(relay).into_iter().find(|addr| !address::is_relayed(addr)) {
+                Some(addr) => self.listen_via_relay(relay, &addr),
+                None => {
+                    if self.swarm.dial(DialOpts::peer_id(relay).build()).is_ok() {
+                        self.relay_candidates.insert(relay);
+                    }
+                }
+            }
+        }
+    }
+
+    // 监听 <中继地址>/p2p/<中继>/p2p-circuit，中继客户端向中继申请预留，成功后报告新的监听地址
+    fn listen_via_relay(&mut self, relay: PeerId, relay_addr: &Multiaddr) {
+        self.relay_candidates.remove(&relay);
+        if let Ok(listener) = self.swarm.listen_on(relay::circuit_listen_address(relay_addr, relay)) {
+            self.relay_listeners.insert(listener, relay);
+            self.listeners.push(listener);
+        }
+    }
+
+    #[cfg(feature = "relay")]
+    fn handle_relay_client_event(&mut self, event: libp2p::relay::client::Event) {
+        if let libp2p::relay::client::Event::ReservationReqAccepted { relay_peer_id, renewal, .. } = event {
+            self.emit(NodeEvent::RelayReservation { relay: relay_peer_id, renewal });
+        }
+    }
+
+    #[cfg(not(feature = "relay"))]
+    fn handle_relay_client_event(&mut self, event: std::convert::Infallible) {
+        match event {}
+    }
+
+    #[cfg(feature = "identify")]
+    fn handle_identify_event(&mut self, event: libp2p::identify::Event) {
+        if let libp2p::identify::Event::Received { peer_id, info, .. } = event {
+            self.peer_identified(peer_id, PeerIdentity::from_info(info));
+        }
+    }
+
+    #[cfg(not(feature = "identify"))]
+    fn handle_identify_event(&mut self, event: std::convert::Infallible) {
+        match event {}
+    }
+
+    // 运行 Kademlia 的对端以其报告的监听地址加入路由表，连接的地址可能是临时端口或 NAT 映射，不能用来回连
+    // 版本信息记入 Bootstrap 节点和路由表；观察到的本节点地址已由 identify 行为作为外部地址候选报告，AutoNAT 会探测它
+    #[cfg_attr(not(feature = "identify"), allow(dead_code))]
+    fn peer_identified(&mut self, peer_id: PeerId, identity: PeerIdentity) {
+        if identity.supports_kademlia() {
+            let kademlia = &mut self.swarm.behaviour_mut().kademlia;
+            for addr in identity.routable_addrs() {
+                kademlia.add_address(&peer_id, addr);
+            }
+        }
+        bootstrap::update_bootstrap_node_versions(
+            &mut self.bootstrap_nodes,
+            &peer_id.to_string(),
+            &identity.agent_version,
+            &identity.protocol_version,
+            &identity.protocols,
+        );
+        self.identities.insert(peer_id, identity.clone());
+        self.emit(NodeEvent::Identified { peer_id, identity });
+    }
+
+    // 对端既没有连接也不在路由表中时丢弃它的 identify 信息，长时间运行的节点不会无限积累
+    fn forget_identity(&mut self, peer_id: &PeerId) {
+        if self.swarm.is_connected(peer_id) {
+            return;
+        }
+        let in_routing_table = self
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .kbucket(*peer_id)
+            .is_some_and(|bucket| bucket.iter().any(|entry| entry.node.key.preimage() == peer_id));
+        if !in_routing_table {
+            self.identities.remove(peer_id);
+        }
+    }
+
+    // 每次探测结束或状态变化后读取 AutoNAT 的结论，变化时报告，并在确认后切换 Kademlia 模式
+    #[cfg(feature = "autonat")]
+    fn handle_autonat_event(&mut self, event: libp2p::autonat::Event) {
+        if let libp2p::autonat::Event::InboundProbe(_) = event {
+            return;
+        }
+        let status = ReachabilityStatus::from_behaviour(&self.swarm.behaviour().autonat);
+        if status == self.reachability {
+            return;
+        }
+        if self.reachability_mode
+            && let Some(mode) = status.kademlia_mode()
+        {
+            self.swarm.behaviour_mut().kademlia.set_mode(Some(mode));
+        }
+        self.reachability = status.clone();
+        self.emit(NodeEvent::Reachability(status));
+    }
+
+    #[cfg(not(feature = "autonat"))]
+    fn handle_autonat_event(&mut self, event: std::convert::Infallible) {
+        match event {}
+    }
+
+    #[cfg(feature = "relay")]
+    fn handle_relay_server_event(&mut self, event: libp2p::relay::Event) {
+        if let Some(event) = relay::ServerEvent::from_libp2p(event) {
+            self.emit(NodeEvent::RelayServer(event));
+        }
+    }
+
+    #[cfg(not(feature = "relay"))]
+    fn handle_relay_server_event(&mut self, event: std::convert::Infallible) {
+        match event {}
+    }
+
+    #[cfg(feature = "relay")]
+    fn handle_dcutr_event(&mut self, event: libp2p::dcutr::Event) {
+        let peer_id = event.remote_peer_id;
+        let result = event.result.map(|_| ()).map_err(|e| Error::HolePunch { peer_id, message: e.to_string() });
+        self.emit(NodeEvent::HolePunch { peer_id, result });
+    }
+
+    #[cfg(not(feature = "relay"))]
+    fn handle_dcutr_event(&mut self, event: std::convert::Infallible) {
+        match event {}
+    }
+
+    // 上一批地址全部失败后拨号该节点的下一批地址
+    fn dial_next_stage(&mut self, peer: PeerId) {
+        let Some(stages) = self.dial_fallback.get_mut(&peer) else { return };
+        let next = stages.pop_front();
+        if stages.is_empty() {
+            self.dial_fallback.remove(&peer);
+        }
+        let Some(addresses) = next else { return };
+        if self.swarm.is_connected(&peer) {
+            self.dial_fallback.remove(&peer);
+            return;
+        }
+        let opts = DialOpts::peer_id(peer).addresses(addresses).build();
+        let _ = self.swarm.dial(opts);
+    }
+
+    // 记录一次失败，连续失败达到阈值的 Bootstrap 节点从列表和路由表中移除
+    fn mark_bootstrap_failure(&mut self, peer_id: PeerId) {
+        bootstrap::update_bootstrap_node_status(&mut self.bootstrap_nodes, &peer_id.to_string(), NodeStatus::Inactive);
+        let dead = bootstrap::prune_dead_bootstrap_nodes(&mut self.bootstrap_nodes, self.max_bootstrap_failures);
+        for node in dead {
+            if let Some((peer_id, addresses)) = node.dial_addresses() {
+                for addr in &addresses {
+                    self.swarm.behaviour_mut().kademlia.remove_address(&peer_id, addr);
+                }
+            }
+        }
+    }
+
+    // 向新找到的 STUN 提供者发送请求，查询仍在进行，拨号可以使用查询中得到的地址
+    fn observe_providers(&mut self, query_id: QueryId, providers: HashSet<PeerId>) {
+        let local_peer_id = *self.swarm.local_peer_id();
+        let Some(query) = self.pending_peer_stun.get_mut(&query_id) else { return };
+        for peer in providers {
+            if peer == local_peer_id || query.peers.contains(&peer) || query.peers.len() >= query.limit {
+                continue;
+            }
+            let request_id = self.swarm.behaviour_mut().peer_stun.observe(peer);
+            query.peers.push(peer);
+            query.outstanding.insert(request_id);
+            self.peer_stun_queries.insert(request_id, query_id);
+        }
+    }
+
+    // 查询结束且所有请求完成时应答，没有成功结果时返回各节点的失败原因
+    fn complete_peer_stun(&mut self, query_id: QueryId) {
+        let done = self.pending_peer_stun.get(&query_id).is_some_and(|query| query.finished && query.outstanding.is_empty());
+        if !done {
+            return;
+        }
+        let Some(PeerStunQuery { peers, mut probe, reply, .. }) = self.pending_peer_stun.remove(&query_id) else { return };
+        let result = if peers.is_empty() {
+            Err(Error::NoStunPeers)
+        } else if probe.results.is_empty() {
+            Err(Error::StunFailed(probe.failures))
+        } else {
+            probe.results.sort_by_key(|result| result.rtt);
+            Ok(probe)
+        };
+        let _ = reply.send(result);
+    }
+
+    fn handle_kademlia_event(&mut self, event: kad::Event) {
+        match event {
+            kad::Event::OutboundQueryProgressed { id, result, .. } => match result {
+                QueryResult::Bootstrap(result) => {
+                    // Bootstrap 完成后路由表里有了足够的节点，开始查找中继
+                    if matches!(result, Ok(kad::BootstrapOk { num_remaining: 0, .. })) {
+                        self.find_relays();
+                    }
+                    self.emit(NodeEvent::Bootstrap(result));
+                }
+                QueryResult::GetClosestPeers(result) => self.emit(NodeEvent::ClosestPeers(result)),
+                QueryResult::PutRecord(result) => {
+                    if let Some(reply) = self.pending_publish.remove(&id) {
+                        let _ = reply.send(result.map(|_| ()).map_err(Error::PutRecord));
+                    }
+                }
+                QueryResult::GetRecord(Ok(GetRecordOk::FoundRecord(PeerRecord { record, .. }))) => {
+                    let local = record.publisher == Some(*self.swarm.local_peer_id());
+                    if matches!(self.pending_lookup.get(&id), Some((true, _))) && local {
+                        return;
+                    }
+                    if let Some((_, reply)) = self.pending_lookup.remove(&id) {
+                        let _ = reply.send(Ok(record));
+                        // 已拿到记录，不必继续查询
+                        if let Some(mut query) = self.swarm.behaviour_mut().kademlia.query_mut(&id) {
+                            query.finish();
+                        }
+                    }
+                }
+                QueryResult::GetRecord(Ok(GetRecordOk::FinishedWithNoAdditionalRecord { .. })) => {
+                    if let Some((_, reply)) = self.pending_lookup.remove(&id) {
+                        let _ = reply.send(Err(Error::RecordNotFound));
+                    }
+                }
+                QueryResult::GetRecord(Err(e)) => {
+                    if let Some((_, reply)) = self.pending_lookup.remove(&id) {
+                        let _ = reply.send(Err(Error::GetRecord(e)));
+                    }
+                }
+                QueryResult::GetProviders(Ok(GetProvidersOk::FoundProviders { providers, .. })) => {
+                    if self.relay_queries.contains(&id) {
+                        self.reserve_on_relays(providers);
+                    } else {
+                        self.observe_providers(id, providers);
+                    }
+                }
+                // 查询结束或超时，已找到的提供者仍然有效
+                QueryResult::GetProviders(_) => {
+                    self.relay_queries.remove(&id);
+                    if let Some(query) = self.pending_peer_stun.get_mut(&id) {
+                        query.finished = true;
+                    }
+                    self.complete_peer_stun(id);
+                }
+                _ => {}
+            },
+            kad::Event::RoutingUpdated { peer, addresses, old_peer, .. } => {
+                // 路由表中的节点可能需要经中继连接本节点，为其公网 IP 安装 TURN 权限；被挤出路由表的节点不再需要
+                if let Some(permissions) = &self.turn_permissions {
+                    permissions.permit_peer(peer, addresses.iter().filter_map(address::ip).filter(address::is_global).collect());
+                    if let Some(old_peer) = old_peer {
+                        permissions.revoke_peer(&old_peer);
+                    }
+                }
+                if let Some(old_peer) = old_peer {
+                    self.forget_identity(&old_peer);
+                }
+                self.emit(NodeEvent::RoutingUpdated { peer });
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// output.rs - 命令行输出格式
+// JSON 模式下标准输出只包含最终结果，进度信息改为输出到标准错误
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+// 输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+pub fn set_format(format: OutputFormat) {
+    JSON_OUTPUT.store(format == OutputFormat::Json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+// 输出进度信息，用法与 println! 相同
+#[macro_export]
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if $crate::output::is_json() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+// 输出命令结果：JSON 模式下输出一行 JSON，否则调用 text 输出可读文本
+pub fn print_result<T: Serialize + ?Sized>(value: &T, text: impl FnOnce(&T)) {
+    if is_json() {
+        match serde_json::to_string(value) {
+            Ok(json) => println!("{}", json),
+            Err(e) => eprintln!("Failed to serialize result: {}", e),
+        }
+    } else {
+        text(value);
+    }
+}
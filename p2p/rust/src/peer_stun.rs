@@ -0,0 +1,320 @@
+// peer_stun.rs - 节点之间的 STUN 协议（/p2p/stun/1.0.0）
+// 请求方在协议流上发送 Binding Request，应答方以连接的远端地址作为 XOR-MAPPED-ADDRESS 返回，
+// 报文格式与 TCP 上的 STUN 相同（RFC 5389 7.2.2）。只有开启了应答的节点接受该协议
+use crate::address::socket_addr;
+use crate::error::Error;
+use crate::stun::{self, StunResult};
+use crate::stun_server;
+use libp2p::{
+    Multiaddr,
+    PeerId,
+    StreamProtocol,
+    core::{Endpoint, transport::PortUse, upgrade::{DeniedUpgrade, ReadyUpgrade}},
+    futures::{FutureExt, StreamExt, future::{self, BoxFuture}, stream::FuturesUnordered},
+    swarm::{
+        ConnectionDenied, ConnectionHandler, ConnectionHandlerEvent, ConnectionId, DialError, FromSwarm,
+        NetworkBehaviour, NotifyHandler, Stream, StreamUpgradeError, SubstreamProtocol, THandler, THandlerInEvent,
+        THandlerOutEvent, ToSwarm,
+        dial_opts::DialOpts,
+        handler::{ConnectionEvent, DialUpgradeError, FullyNegotiatedInbound, FullyNegotiatedOutbound},
+    },
+};
+use either::Either;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+pub const PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/p2p/stun/1.0.0");
+
+// 一次请求从打开协议流到收到应答的最长时间
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 每条连接上同时应答的流数量上限，超出的流直接关闭
+const MAX_INBOUND_STREAMS: usize = 4;
+
+// 请求编号，用于把结果对应到 observe 的调用
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(u64);
+
+// 一次请求的结果，成功时 server 为对端的 PeerId，server_address 为连接的远端地址
+#[derive(Debug)]
+pub struct Event {
+    pub peer: PeerId,
+    pub request_id: RequestId,
+    pub result: Result<StunResult, Error>,
+}
+
+pub struct Behaviour {
+    // 是否应答其它节点的请求
+    responder: bool,
+    next_request_id: u64,
+    connections: HashMap<PeerId, HashSet<ConnectionId>>,
+    // 等待连接建立的请求
+    pending_dial: HashMap<PeerId, Vec<RequestId>>,
+    // 已交给连接处理的请求，连接关闭时报告失败
+    in_flight: HashMap<ConnectionId, HashSet<RequestId>>,
+    events: VecDeque<ToSwarm<Event, RequestId>>,
+}
+
+impl Behaviour {
+    pub fn new(responder: bool) -> Self {
+        Behaviour {
+            responder,
+            next_request_id: 0,
+            connections: HashMap::new(),
+            pending_dial: HashMap::new(),
+            in_flight: HashMap::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn is_responder(&self) -> bool {
+        self.responder
+    }
+
+    // 请求 peer 报告它看到的本机地址，未连接时先拨号（地址来自 Kademlia），结果以 Event 报告
+    pub fn observe(&mut self, peer: PeerId) -> RequestId {
+        let request_id = RequestId(self.next_request_id);
+        self.next_request_id += 1;
+
+        match self.connections.get(&peer).and_then(|connections| connections.iter().next()) {
+            Some(connection) => self.send(peer, *connection, request_id),
+            None => {
+                let pending = self.pending_dial.entry(peer).or_default();
+                if pending.is_empty() {
+                    self.events.push_back(ToSwarm::Dial { opts: DialOpts::peer_id(peer).build() });
+                }
+                pending.push(request_id);
+            }
+        }
+        request_id
+    }
+
+    // 把 STUN 确认的本机地址作为外部地址候选通知其它行为，例如 DCUtR 打洞时交换的地址
+    pub fn add_address_candidate(&mut self, address: Multiaddr) {
+        self.events.push_back(ToSwarm::NewExternalAddrCandidate(address));
+    }
+
+    fn send(&mut self, peer: PeerId, connection: ConnectionId, request_id: RequestId) {
+        self.in_flight.entry(connection).or_default().insert(request_id);
+        self.events.push_back(ToSwarm::NotifyHandler { peer_id: peer, handler: NotifyHandler::One(connection), event: request_id });
+    }
+
+    fn fail(&mut self, peer: PeerId, request_id: RequestId, error: Error) {
+        self.events.push_back(ToSwarm::GenerateEvent(Event { peer, request_id, result: Err(error) }));
+    }
+
+    fn handler(&self, peer: PeerId, remote: &Multiaddr) -> Handler {
+        Handler::new(peer, socket_addr(remote), self.responder)
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = Handler;
+    type ToSwarm = Event;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler(peer, remote_addr))
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        _role_override: Endpoint,
+        _port_use: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler(peer, addr))
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        match event {
+            FromSwarm::ConnectionEstablished(established) => {
+                self.connections.entry(established.peer_id).or_default().insert(established.connection_id);
+                for request_id in self.pending_dial.remove(&established.peer_id).unwrap_or_default() {
+                    self.send(established.peer_id, established.connection_id, request_id);
+                }
+            }
+            FromSwarm::ConnectionClosed(closed) => {
+                if let Some(connections) = self.connections.get_mut(&closed.peer_id) {
+                    connections.remove(&closed.connection_id);
+                    if connections.is_empty() {
+                        self.connections.remove(&closed.peer_id);
+                    }
+                }
+                for request_id in self.in_flight.remove(&closed.connection_id).unwrap_or_default() {
+                    let source = io::Error::from(io::ErrorKind::ConnectionAborted);
+                    self.fail(closed.peer_id, request_id, Error::StunIo { server: closed.peer_id.to_string(), source });
+                }
+            }
+            FromSwarm::DialFailure(failure) => {
+                let Some(peer) = failure.peer_id else { return };
+                // 已有其它拨号进行中时等待它的结果
+                if matches!(failure.error, DialError::DialPeerConditionFalse(_)) || self.connections.contains_key(&peer) {
+                    return;
+                }
+                for request_id in self.pending_dial.remove(&peer).unwrap_or_default() {
+                    self.fail(peer, request_id, Error::from_dial(Some(peer), failure.error));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer: PeerId,
+        connection: ConnectionId,
+        (request_id, result): THandlerOutEvent<Self>,
+    ) {
+        if let Some(requests) = self.in_flight.get_mut(&connection) {
+            requests.remove(&request_id);
+        }
+        self.events.push_back(ToSwarm::GenerateEvent(Event { peer, request_id, result }));
+    }
+
+    fn poll(&mut self, _cx: &mut Context<'_>) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        match self.events.pop_front() {
+            Some(event) => Poll::Ready(event),
+            None => Poll::Pending,
+        }
+    }
+}
+
+type RequestFuture = BoxFuture<'static, (RequestId, Result<StunResult, Error>)>;
+
+// 每条连接的协议处理
+pub struct Handler {
+    peer: PeerId,
+    // 连接的远端地址，不是 IP 和端口（例如中继连接）时无法应答，也无法作为请求结果
+    remote: Option<SocketAddr>,
+    responder: bool,
+    // 等待打开协议流的请求
+    pending: VecDeque<RequestId>,
+    // 已打开流但协商失败的请求
+    failed: VecDeque<(RequestId, Error)>,
+    // 正在协商的流数量
+    opening: usize,
+    outbound: FuturesUnordered<RequestFuture>,
+    inbound: FuturesUnordered<BoxFuture<'static, ()>>,
+}
+
+impl Handler {
+    fn new(peer: PeerId, remote: Option<SocketAddr>, responder: bool) -> Self {
+        Handler {
+            peer,
+            remote,
+            responder,
+            pending: VecDeque::new(),
+            failed: VecDeque::new(),
+            opening: 0,
+            outbound: FuturesUnordered::new(),
+            inbound: FuturesUnordered::new(),
+        }
+    }
+
+    fn on_dial_upgrade_error(&mut self, DialUpgradeError { info, error }: DialUpgradeError<RequestId, ReadyUpgrade<StreamProtocol>>) {
+        self.opening -= 1;
+        let server = self.peer.to_string();
+        let error = match error {
+            StreamUpgradeError::NegotiationFailed => Error::PeerStunUnsupported { peer_id: self.peer },
+            StreamUpgradeError::Timeout => Error::StunTimeout { server },
+            StreamUpgradeError::Apply(e) => match e {},
+            StreamUpgradeError::Io(source) => Error::StunIo { server, source },
+        };
+        self.failed.push_back((info, error));
+    }
+}
+
+impl ConnectionHandler for Handler {
+    type FromBehaviour = RequestId;
+    type ToBehaviour = (RequestId, Result<StunResult, Error>);
+    type InboundProtocol = Either<ReadyUpgrade<StreamProtocol>, DeniedUpgrade>;
+    type OutboundProtocol = ReadyUpgrade<StreamProtocol>;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = RequestId;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, ()> {
+        if self.responder && self.remote.is_some() {
+            SubstreamProtocol::new(Either::Left(ReadyUpgrade::new(PROTOCOL_NAME)), ())
+        } else {
+            SubstreamProtocol::new(Either::Right(DeniedUpgrade), ())
+        }
+    }
+
+    fn on_behaviour_event(&mut self, request_id: RequestId) {
+        self.pending.push_back(request_id);
+    }
+
+    // 有请求未完成时保持连接
+    fn connection_keep_alive(&self) -> bool {
+        !self.pending.is_empty() || self.opening > 0 || !self.outbound.is_empty()
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<ConnectionHandlerEvent<Self::OutboundProtocol, RequestId, Self::ToBehaviour>> {
+        while let Poll::Ready(Some(())) = self.inbound.poll_next_unpin(cx) {}
+
+        if let Some((request_id, error)) = self.failed.pop_front() {
+            return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour((request_id, Err(error))));
+        }
+        if let Poll::Ready(Some(outcome)) = self.outbound.poll_next_unpin(cx) {
+            return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(outcome));
+        }
+        if let Some(request_id) = self.pending.pop_front() {
+            self.opening += 1;
+            let protocol = SubstreamProtocol::new(ReadyUpgrade::new(PROTOCOL_NAME), request_id).with_timeout(REQUEST_TIMEOUT);
+            return Poll::Ready(ConnectionHandlerEvent::OutboundSubstreamRequest { protocol });
+        }
+        Poll::Pending
+    }
+
+    fn on_connection_event(&mut self, event: ConnectionEvent<Self::InboundProtocol, Self::OutboundProtocol, (), RequestId>) {
+        match event {
+            ConnectionEvent::FullyNegotiatedInbound(FullyNegotiatedInbound { protocol, .. }) => {
+                // 协商结果使用 futures 的 Either
+                let (future::Either::Left(stream), Some(observed)) = (protocol, self.remote) else { return };
+                if self.inbound.len() >= MAX_INBOUND_STREAMS {
+                    return;
+                }
+                self.inbound.push(respond(stream, observed).boxed());
+            }
+            ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound { protocol: stream, info: request_id }) => {
+                self.opening -= 1;
+                let (peer, remote) = (self.peer, self.remote);
+                self.outbound.push(async move { (request_id, request(stream, peer, remote).await) }.boxed());
+            }
+            ConnectionEvent::DialUpgradeError(error) => self.on_dial_upgrade_error(error),
+            _ => {}
+        }
+    }
+}
+
+// 应答一条入站流上的请求，对端关闭流或超时后结束
+async fn respond(stream: Stream, observed: SocketAddr) {
+    let mut stream = stream.compat();
+    let _ = tokio::time::timeout(REQUEST_TIMEOUT, stun_server::serve_stream(&mut stream, observed)).await;
+}
+
+async fn request(stream: Stream, peer: PeerId, remote: Option<SocketAddr>) -> Result<StunResult, Error> {
+    let server = peer.to_string();
+    let Some(server_address) = remote else {
+        let source = io::Error::new(io::ErrorKind::Unsupported, "connection address is not an IP address and port");
+        return Err(Error::StunIo { server, source });
+    };
+    let mut stream = stream.compat();
+    stun::stream_binding_request(&mut stream, server, server_address, None, Instant::now() + REQUEST_TIMEOUT).await
+}
@@ -1,6 +1,12 @@
 // performance_benchmark.rs - 性能基准测试模块
+use crate::address::{self, TransportKind};
+use crate::persist;
+use libp2p::core::ConnectedPoint;
+use libp2p::{ping, PeerId};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+use std::time::Duration;
 
 // 性能测试结果结构体
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,14 +44,79 @@ pub async fn save_performance_test_results(
     test_results: Vec<PerformanceTestResult>,
     output_file: &str,
 ) -> Result<(), Box<dyn Error>> {
-    use tokio::fs::File;
-    use tokio::io::AsyncWriteExt;
-    use serde_json;
-
     let json_data = serde_json::to_string_pretty(&test_results)?;
-    let mut file = File::create(output_file).await?;
-    file.write_all(json_data.as_bytes()).await?;
-    file.flush().await?;
+    let path = std::path::PathBuf::from(output_file);
+    tokio::task::spawn_blocking(move || persist::write_atomic(&path, json_data.as_bytes())).await??;
     println!("性能测试结果已保存到: {}", output_file);
     Ok(())
-}
\ No newline at end of file
+}
+
+// 一种传输方式上的连接和 Ping 结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransportMetrics {
+    pub transport: TransportKind,
+    pub connections: u32,
+    pub connections_closed: u32,
+    pub pings: u32,
+    pub ping_failures: u32,
+    // 平均 Ping 往返时间(毫秒)，没有成功的 Ping 时为 0
+    pub avg_ping_ms: f64,
+}
+
+// 按传输方式（TCP、QUIC、中继）分别统计连接，bench 用它比较各传输方式的表现
+#[derive(Debug, Default)]
+pub struct TransportBenchmark {
+    metrics: BTreeMap<TransportKind, TransportMetrics>,
+    ping_total: BTreeMap<TransportKind, Duration>,
+    // 每个节点最近一次建立连接使用的传输方式，Ping 结果按它归类
+    peers: HashMap<PeerId, TransportKind>,
+}
+
+impl TransportBenchmark {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn connection_established(&mut self, peer: PeerId, endpoint: &ConnectedPoint) {
+        let transport = address::connection_transport(endpoint);
+        self.peers.insert(peer, transport);
+        self.entry(transport).connections += 1;
+    }
+
+    pub fn connection_closed(&mut self, peer: PeerId) {
+        if let Some(transport) = self.peers.get(&peer).copied() {
+            self.entry(transport).connections_closed += 1;
+        }
+    }
+
+    pub fn ping(&mut self, peer: PeerId, result: &Result<Duration, ping::Failure>) {
+        let Some(transport) = self.peers.get(&peer).copied() else { return };
+        match result {
+            Ok(rtt) => {
+                let total = self.ping_total.entry(transport).or_default();
+                *total += *rtt;
+                let total = *total;
+                let metrics = self.entry(transport);
+                metrics.pings += 1;
+                metrics.avg_ping_ms = total.as_secs_f64() * 1000.0 / metrics.pings as f64;
+            }
+            Err(_) => self.entry(transport).ping_failures += 1,
+        }
+    }
+
+    // 各传输方式的统计，按 TCP、QUIC、中继的顺序
+    pub fn results(&self) -> Vec<TransportMetrics> {
+        self.metrics.values().cloned().collect()
+    }
+
+    fn entry(&mut self, transport: TransportKind) -> &mut TransportMetrics {
+        self.metrics.entry(transport).or_insert(TransportMetrics {
+            transport,
+            connections: 0,
+            connections_closed: 0,
+            pings: 0,
+            ping_failures: 0,
+            avg_ping_ms: 0.0,
+        })
+    }
+}
@@ -0,0 +1,205 @@
+// persist.rs - 文件持久化
+// 先写入同目录下的临时文件并 fsync，再重命名到目标位置，崩溃时不会留下只写了一半的文件
+// 写入期间持有 <文件名>.lock 上的建议锁，同一目录下运行的多个节点不会交错写入，释放锁时删除锁文件
+// 等待锁时会阻塞线程，异步代码应在 spawn_blocking 中调用
+use crate::error::{Error, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// 等待其它进程释放锁的最长时间
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 两次尝试加锁之间的间隔
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+// 文件锁，释放时自动解锁
+#[derive(Debug)]
+pub struct FileLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl FileLock {
+    // 持有锁时原子地替换目标文件的内容
+    pub fn write_atomic(&self, contents: &[u8]) -> io::Result<()> {
+        write_atomic_locked(&self.path, contents, false)
+    }
+
+    // 与 write_atomic 相同，但文件只允许所有者读写（用于密钥文件）
+    pub fn write_private(&self, contents: &[u8]) -> io::Result<()> {
+        write_atomic_locked(&self.path, contents, true)
+    }
+
+    // 持有锁时以 JSON 格式原子地替换目标文件
+    pub fn write_json<T: Serialize>(&self, value: &T) -> Result<()> {
+        let json_string = serde_json::to_string_pretty(value).map_err(|e| Error::json(&self.path, e))?;
+        self.write_atomic(json_string.as_bytes()).map_err(|e| Error::persist(&self.path, e))
+    }
+
+    // 锁住的目标文件
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for FileLock {
+    // 先删除锁文件再解锁：等在旧锁文件上的进程拿到锁后发现它已被删除，会重新创建锁文件并加锁
+    fn drop(&mut self) {
+        remove_lock_file(&lock_path(&self.path));
+        let _ = self.file.unlock();
+    }
+}
+
+// 目标文件对应的锁文件，锁不能加在目标文件本身上，因为重命名会替换它
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+// 获取 path 的写锁，其它进程持有锁时最多等待 LOCK_TIMEOUT
+// 需要先检查再写入的调用方（如只在文件不存在时创建）应在持有锁期间完成检查
+pub fn lock(path: &Path) -> io::Result<FileLock> {
+    create_parent_dir(path)?;
+    let lock_path = lock_path(path);
+
+    let deadline = Instant::now() + LOCK_TIMEOUT;
+    loop {
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path)?;
+        match file.try_lock() {
+            // 加锁前锁文件可能已被上一个持有者删除，此时锁住的文件已经不是 lock_path，需要重试
+            Ok(()) if is_current_lock_file(&file, &lock_path) => {
+                return Ok(FileLock { file, path: path.to_path_buf() });
+            }
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => std::thread::sleep(LOCK_RETRY_INTERVAL),
+            Err(TryLockError::WouldBlock) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("{} is locked by another process", path.display()),
+                ));
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+    }
+}
+
+// 锁住的文件仍然是 lock_path 指向的文件
+#[cfg(unix)]
+fn is_current_lock_file(file: &File, lock_path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(lock_path)) {
+        (Ok(locked), Ok(current)) => locked.dev() == current.dev() && locked.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(unix)]
+fn remove_lock_file(lock_path: &Path) {
+    let _ = fs::remove_file(lock_path);
+}
+
+// 其它平台不能删除仍被打开的文件，锁文件保留
+#[cfg(not(unix))]
+fn is_current_lock_file(_file: &File, _lock_path: &Path) -> bool {
+    true
+}
+
+#[cfg(not(unix))]
+fn remove_lock_file(_lock_path: &Path) {}
+
+// 加锁后原子地替换 path 的内容
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    lock(path)?.write_atomic(contents)
+}
+
+// 与 write_atomic 相同，但文件只允许所有者读写（用于密钥文件）
+pub fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    lock(path)?.write_private(contents)
+}
+
+// 加锁后以 JSON 格式原子地替换 path 的内容
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    lock(path).map_err(|e| Error::persist(path, e))?.write_json(value)
+}
+
+// 读取 JSON 格式的文件，文件不存在时返回 None
+pub fn read_json(path: &Path) -> Result<Option<serde_json::Value>> {
+    let json_string = match fs::read_to_string(path) {
+        Ok(json_string) => json_string,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::persist(path, e)),
+    };
+    serde_json::from_str(&json_string).map(Some).map_err(|e| Error::json(path, e))
+}
+
+// 把 read_json 读出的内容解析为 T，错误中带上文件路径
+pub fn from_json<T: DeserializeOwned>(path: &Path, value: serde_json::Value) -> Result<T> {
+    serde_json::from_value(value).map_err(|e| Error::json(path, e))
+}
+
+// 调用方已经持有锁
+fn write_atomic_locked(path: &Path, contents: &[u8], private: bool) -> io::Result<()> {
+    create_parent_dir(path)?;
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(name);
+
+    let result = write_and_rename(&temp_path, path, contents, private);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn write_and_rename(temp_path: &Path, path: &Path, contents: &[u8], private: bool) -> io::Result<()> {
+    let mut file = File::create(temp_path)?;
+    if private {
+        restrict_permissions(temp_path)?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(temp_path, path)?;
+    sync_parent_dir(path)
+}
+
+fn create_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir),
+        _ => Ok(()),
+    }
+}
+
+// 重命名只有在目录项写入磁盘后才算持久化
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+// 文件只允许所有者读写
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,194 @@
+// relay.rs - Circuit Relay v2 客户端、中继服务端和 DCUtR 打洞
+// 双方都在 NAT 之后时，先在 DHT 中找到的中继节点上预留位置，对方经 /p2p-circuit 地址连接本节点，
+// 连接建立后 DCUtR 协调双方同时拨号，打洞成功后改用直接连接。有公网地址的节点可以开启中继服务端，
+// 在 DHT 中宣告自己供其它节点预留。需要以 --features relay 编译，
+// 未编译时各行为用 dummy::Behaviour 占位，MyBehaviour 的字段和事件保持不变
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use std::collections::HashSet;
+use std::fmt;
+use std::time::Duration;
+
+// 中继客户端行为
+#[cfg(feature = "relay")]
+pub type ClientBehaviour = libp2p::relay::client::Behaviour;
+#[cfg(not(feature = "relay"))]
+pub type ClientBehaviour = libp2p::swarm::dummy::Behaviour;
+
+// DCUtR 打洞行为
+#[cfg(feature = "relay")]
+pub type DcutrBehaviour = libp2p::dcutr::Behaviour;
+#[cfg(not(feature = "relay"))]
+pub type DcutrBehaviour = libp2p::swarm::dummy::Behaviour;
+
+// 中继服务端行为，未开启服务端时为 Toggle 的空行为
+#[cfg(feature = "relay")]
+pub type ServerBehaviour = libp2p::swarm::behaviour::toggle::Toggle<libp2p::relay::Behaviour>;
+#[cfg(not(feature = "relay"))]
+pub type ServerBehaviour = libp2p::swarm::dummy::Behaviour;
+
+// 每个节点默认在几个中继上预留位置
+pub const DEFAULT_RELAY_RESERVATIONS: usize = 2;
+
+// 中继服务端的默认限制，与 libp2p 的默认值相同
+pub const DEFAULT_RELAY_MAX_RESERVATIONS: usize = 128;
+pub const DEFAULT_RELAY_MAX_CIRCUITS: usize = 16;
+pub const DEFAULT_RELAY_MAX_CIRCUIT_BYTES: u64 = 1 << 17;
+pub const DEFAULT_RELAY_MAX_CIRCUIT_DURATION: Duration = Duration::from_secs(2 * 60);
+
+// 中继服务端的配置
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelayServerConfig {
+    // 同时保留的预留数
+    pub max_reservations: usize,
+    // 同时转发的连接数
+    pub max_circuits: usize,
+    // 每条转发连接最多转发的字节数（每个方向）
+    pub max_circuit_bytes: u64,
+    // 每条转发连接的最长时间
+    pub max_circuit_duration: Duration,
+    // 允许预留和发起转发连接的节点，为空时不限制
+    pub allowlist: HashSet<PeerId>,
+}
+
+impl Default for RelayServerConfig {
+    fn default() -> Self {
+        RelayServerConfig {
+            max_reservations: DEFAULT_RELAY_MAX_RESERVATIONS,
+            max_circuits: DEFAULT_RELAY_MAX_CIRCUITS,
+            max_circuit_bytes: DEFAULT_RELAY_MAX_CIRCUIT_BYTES,
+            max_circuit_duration: DEFAULT_RELAY_MAX_CIRCUIT_DURATION,
+            allowlist: HashSet::new(),
+        }
+    }
+}
+
+impl RelayServerConfig {
+    // 节点是否可以使用本中继
+    pub fn allows(&self, peer: &PeerId) -> bool {
+        self.allowlist.is_empty() || self.allowlist.contains(peer)
+    }
+
+    // 转换为 libp2p 的中继配置，保留默认的按节点和按 IP 限速，允许列表作为额外的限速器
+    #[cfg(feature = "relay")]
+    fn to_libp2p(&self) -> libp2p::relay::Config {
+        let mut config = libp2p::relay::Config {
+            max_reservations: self.max_reservations,
+            max_circuits: self.max_circuits,
+            max_circuit_bytes: self.max_circuit_bytes,
+            max_circuit_duration: self.max_circuit_duration,
+            ..Default::default()
+        };
+        config.max_reservations_per_peer = config.max_reservations_per_peer.min(self.max_reservations);
+        config.max_circuits_per_peer = config.max_circuits_per_peer.min(self.max_circuits);
+        if !self.allowlist.is_empty() {
+            config.reservation_rate_limiters.push(self.allowlist_limiter());
+            config.circuit_src_rate_limiters.push(self.allowlist_limiter());
+        }
+        config
+    }
+
+    #[cfg(feature = "relay")]
+    fn allowlist_limiter(&self) -> Box<dyn libp2p::relay::RateLimiter> {
+        let config = self.clone();
+        Box::new(move |peer: PeerId, _: &Multiaddr, _: std::time::Instant| config.allows(&peer))
+    }
+}
+
+// 创建中继服务端行为，config 为空时不提供中继服务
+#[cfg(feature = "relay")]
+pub fn server_behaviour(local_peer_id: PeerId, config: Option<&RelayServerConfig>) -> ServerBehaviour {
+    config.map(|config| libp2p::relay::Behaviour::new(local_peer_id, config.to_libp2p())).into()
+}
+#[cfg(not(feature = "relay"))]
+pub fn server_behaviour(_local_peer_id: PeerId, _config: Option<&RelayServerConfig>) -> ServerBehaviour {
+    libp2p::swarm::dummy::Behaviour
+}
+
+// 中继服务端的事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerEvent {
+    // 接受了节点的预留请求（renewed 为续期）
+    ReservationAccepted { peer: PeerId, renewed: bool },
+    // 拒绝了预留请求：超过限制或不在允许列表中
+    ReservationDenied { peer: PeerId },
+    // 预留到期
+    ReservationTimedOut { peer: PeerId },
+    // 开始为 src 到 dst 转发连接
+    CircuitAccepted { src: PeerId, dst: PeerId },
+    // 拒绝了转发请求
+    CircuitDenied { src: PeerId, dst: PeerId },
+    // 转发连接关闭（可能因为达到字节数或时间限制）
+    CircuitClosed { src: PeerId, dst: PeerId },
+}
+
+impl fmt::Display for ServerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerEvent::ReservationAccepted { peer, renewed: false } => write!(f, "accepted reservation from {}", peer),
+            ServerEvent::ReservationAccepted { peer, renewed: true } => write!(f, "renewed reservation of {}", peer),
+            ServerEvent::ReservationDenied { peer } => write!(f, "denied reservation from {}", peer),
+            ServerEvent::ReservationTimedOut { peer } => write!(f, "reservation of {} timed out", peer),
+            ServerEvent::CircuitAccepted { src, dst } => write!(f, "relaying circuit from {} to {}", src, dst),
+            ServerEvent::CircuitDenied { src, dst } => write!(f, "denied circuit from {} to {}", src, dst),
+            ServerEvent::CircuitClosed { src, dst } => write!(f, "circuit from {} to {} closed", src, dst),
+        }
+    }
+}
+
+impl ServerEvent {
+    // 转换 libp2p 的中继服务端事件，已弃用的失败事件只记录在 libp2p 的日志中
+    #[cfg(feature = "relay")]
+    #[allow(deprecated)]
+    pub fn from_libp2p(event: libp2p::relay::Event) -> Option<ServerEvent> {
+        use libp2p::relay::Event;
+        match event {
+            Event::ReservationReqAccepted { src_peer_id, renewed } => {
+                Some(ServerEvent::ReservationAccepted { peer: src_peer_id, renewed })
+            }
+            Event::ReservationReqDenied { src_peer_id, .. } => Some(ServerEvent::ReservationDenied { peer: src_peer_id }),
+            Event::ReservationTimedOut { src_peer_id } => Some(ServerEvent::ReservationTimedOut { peer: src_peer_id }),
+            Event::CircuitReqAccepted { src_peer_id, dst_peer_id } => {
+                Some(ServerEvent::CircuitAccepted { src: src_peer_id, dst: dst_peer_id })
+            }
+            Event::CircuitReqDenied { src_peer_id, dst_peer_id, .. } => {
+                Some(ServerEvent::CircuitDenied { src: src_peer_id, dst: dst_peer_id })
+            }
+            Event::CircuitClosed { src_peer_id, dst_peer_id, .. } => {
+                Some(ServerEvent::CircuitClosed { src: src_peer_id, dst: dst_peer_id })
+            }
+            _ => None,
+        }
+    }
+}
+
+// 是否编译了中继客户端
+pub fn is_supported() -> bool {
+    cfg!(feature = "relay")
+}
+
+// 是否为经中继节点转发的地址（包含 /p2p-circuit）
+pub fn is_circuit_address(addr: &Multiaddr) -> bool {
+    addr.iter().any(|protocol| matches!(protocol, Protocol::P2pCircuit))
+}
+
+// 在中继上预留位置时监听的地址：<中继地址>/p2p/<中继 PeerId>/p2p-circuit
+pub fn circuit_listen_address(relay_addr: &Multiaddr, relay: PeerId) -> Multiaddr {
+    let mut address: Multiaddr = relay_addr.iter().filter(|protocol| !matches!(protocol, Protocol::P2p(_))).collect();
+    address.push(Protocol::P2p(relay));
+    address.push(Protocol::P2pCircuit);
+    address
+}
+
+// 中继地址对应的中继节点 PeerId（/p2p-circuit 之前的 /p2p）
+pub fn circuit_relay_peer(addr: &Multiaddr) -> Option<PeerId> {
+    let mut relay = None;
+    for protocol in addr.iter() {
+        match protocol {
+            Protocol::P2p(peer_id) => relay = Some(peer_id),
+            Protocol::P2pCircuit => return relay,
+            _ => {}
+        }
+    }
+    None
+}
@@ -0,0 +1,143 @@
+// report.rs - NAT穿透测试报告
+use crate::address::TransportKind;
+use crate::autonat::ReachabilityStatus;
+use crate::nat::NatType;
+use crate::persist;
+use crate::progress;
+use libp2p::PeerId;
+use std::path::Path;
+
+// NAT穿透测试报告默认保存的文件
+pub const NAT_TRAVERSAL_REPORT_FILE: &str = "NAT_TRAVERSAL_TEST_REPORT.txt";
+
+// 定义连接尝试结果
+#[derive(Debug, Clone)]
+pub struct ConnectionAttempt {
+    pub peer_id: PeerId,
+    pub timestamp: String,
+    pub result: String, // "success", "relayed", "hole_punch_failed", "timeout", "refused", "error"
+    pub error_message: Option<String>,
+    // 建立的连接使用的传输方式，失败时为空
+    pub transport: Option<TransportKind>,
+}
+
+// 连接是直接的还是经中继转发的，例如 "direct (quic)"、"relayed"
+pub fn connection_label(transport: Option<TransportKind>) -> String {
+    match transport {
+        Some(transport) if transport.is_relayed() => "relayed".to_string(),
+        Some(transport) => format!("direct ({})", transport),
+        None => "none".to_string(),
+    }
+}
+
+// 最终使用的连接：最后一次建立的连接，打洞成功时直接连接排在中继连接之后
+pub fn final_connection(connection_results: &[ConnectionAttempt]) -> Option<TransportKind> {
+    connection_results.iter().rev().find_map(|attempt| attempt.transport)
+}
+
+// 生成测试报告的函数
+#[allow(clippy::too_many_arguments)]
+pub fn generate_test_report(
+    success: bool,
+    connection_results: &[ConnectionAttempt],
+    duration: u64,
+    attempts: u32,
+    max_attempts: u32,
+    nat_type: Option<&NatType>,
+    reachability: &ReachabilityStatus,
+    path: &Path,
+) {
+    let final_connection = final_connection(connection_results);
+    // NAT 行为发现失败时类型未知
+    let nat_type = nat_type.map_or("unknown".to_string(), |nat_type| nat_type.to_string());
+
+    progress!("\n=== NAT TRAVERSAL TEST REPORT ===");
+    progress!("Test Result: {}", if success { "PASSED" } else { "FAILED" });
+    progress!("Test Duration: {} seconds", duration);
+    progress!("Connection Attempts: {}/{}", attempts, max_attempts);
+    progress!("NAT Type: {}", nat_type);
+    progress!("Reachability: {}", reachability);
+    progress!("Final Connection: {}", connection_label(final_connection));
+
+    if !connection_results.is_empty() {
+        progress!("\nConnection Attempts Details:");
+        for attempt in connection_results {
+            progress!("  Peer: {:?} | Time: {} | Result: {} | Connection: {} | Error: {:?}",
+                     attempt.peer_id,
+                     attempt.timestamp,
+                     attempt.result,
+                     connection_label(attempt.transport),
+                     attempt.error_message.as_ref().unwrap_or(&"None".to_string()));
+        }
+    }
+
+    // 统计失败原因
+    let timeout_count = connection_results.iter().filter(|a| a.result == "timeout").count();
+    let refused_count = connection_results.iter().filter(|a| a.result == "refused").count();
+    let other_count = connection_results.iter().filter(|a| a.result == "error").count();
+    // 打洞失败时测试仍经中继连接通过，不算作错误
+    let hole_punch_count = connection_results.iter().filter(|a| a.result == "hole_punch_failed").count();
+
+    progress!("\nFailure Statistics:");
+    progress!("  Timeout Errors: {}", timeout_count);
+    progress!("  Connection Refused: {}", refused_count);
+    progress!("  Other Errors: {}", other_count);
+    progress!("  Hole Punch Failures: {}", hole_punch_count);
+
+    // 保存报告到文件
+    let report = format!(
+        "NAT TRAVERSAL TEST REPORT
+
+        =========================
+
+        Test Result: {}
+
+        Test Duration: {} seconds
+
+        Connection Attempts: {}/{}
+
+        NAT Type: {}
+
+        Reachability: {}
+
+        Final Connection: {}
+
+
+
+        Connection Attempts Details:
+
+        {}
+
+
+
+        Failure Statistics:
+
+        - Timeout Errors: {}
+
+        - Connection Refused: {}
+
+        - Other Errors: {}
+
+        - Hole Punch Failures: {}
+",
+        if success { "PASSED" } else { "FAILED" },
+        duration,
+        attempts,
+        max_attempts,
+        nat_type,
+        reachability,
+        connection_label(final_connection),
+        connection_results.iter().map(|a| format!("  Peer: {:?} | Time: {} | Result: {} | Connection: {} | Error: {:?}",
+            a.peer_id, a.timestamp, a.result, connection_label(a.transport), a.error_message.as_ref().unwrap_or(&"None".to_string())))
+            .collect::<Vec<_>>().join("\n"),
+        timeout_count,
+        refused_count,
+        other_count,
+        hole_punch_count
+    );
+
+    match persist::write_atomic(path, report.as_bytes()) {
+        Ok(()) => progress!("\nDetailed report saved to {}", path.display()),
+        Err(e) => progress!("\nFailed to save report to {}: {}", path.display(), e),
+    }
+}
@@ -0,0 +1,79 @@
+// signal.rs - 退出信号处理
+// 信号在启动时注册，事件循环通过 tokio::select! 等待 ShutdownSignals::recv
+use serde::Serialize;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::fmt;
+use std::io;
+
+// 收到的退出信号
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ShutdownSignal {
+    // SIGINT / Ctrl-C
+    #[serde(rename = "SIGINT")]
+    Interrupt,
+    // SIGTERM
+    #[serde(rename = "SIGTERM")]
+    Terminate,
+}
+
+impl ShutdownSignal {
+    // 按惯例以 128 + 信号编号作为退出码，与正常退出和测试失败的退出码区分
+    pub fn exit_code(self) -> i32 {
+        128 + match self {
+            ShutdownSignal::Interrupt => SIGINT,
+            ShutdownSignal::Terminate => SIGTERM,
+        }
+    }
+}
+
+impl fmt::Display for ShutdownSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownSignal::Interrupt => write!(f, "SIGINT"),
+            ShutdownSignal::Terminate => write!(f, "SIGTERM"),
+        }
+    }
+}
+
+// 已注册的退出信号监听器
+pub struct ShutdownSignals {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+    #[cfg(windows)]
+    ctrl_c: tokio::signal::windows::CtrlC,
+}
+
+impl ShutdownSignals {
+    pub fn new() -> io::Result<Self> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            Ok(ShutdownSignals {
+                interrupt: signal(SignalKind::interrupt())?,
+                terminate: signal(SignalKind::terminate())?,
+            })
+        }
+        #[cfg(windows)]
+        {
+            Ok(ShutdownSignals { ctrl_c: tokio::signal::windows::ctrl_c()? })
+        }
+    }
+
+    // 等待下一个退出信号
+    pub async fn recv(&mut self) -> ShutdownSignal {
+        #[cfg(unix)]
+        {
+            tokio::select! {
+                _ = self.interrupt.recv() => ShutdownSignal::Interrupt,
+                _ = self.terminate.recv() => ShutdownSignal::Terminate,
+            }
+        }
+        #[cfg(windows)]
+        {
+            self.ctrl_c.recv().await;
+            ShutdownSignal::Interrupt
+        }
+    }
+}
@@ -0,0 +1,705 @@
+// stun.rs - RFC 5389 STUN 客户端，用于发现节点的公网地址
+// 请求使用随机事务 ID，按 RFC 5389 7.2.1 的 RTO 翻倍规则重传，
+// 只接受来自目标服务器、事务 ID 相同且 FINGERPRINT 正确的响应
+use crate::error::{Error, Result};
+use crate::stun_auth::{LongTermAuth, MessageIntegritySha256, PasswordAlgorithm, PasswordAlgorithms, StunCredentials};
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use libp2p::futures::future::join_all;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{lookup_host, TcpSocket, UdpSocket};
+use tokio::time::{timeout_at, Instant};
+// 引入 STUN 相关库
+use bytecodec::DecodeExt;
+use bytecodec::EncodeExt;
+use stun_codec::define_attribute_enums;
+use stun_codec::rfc5389::attributes::{
+    AlternateServer, ErrorCode, Fingerprint, MappedAddress, MessageIntegrity, Nonce, Realm, Software,
+    UnknownAttributes, Username, XorMappedAddress,
+};
+use stun_codec::rfc5389::methods::BINDING;
+use stun_codec::rfc5780::attributes::{ChangeRequest, OtherAddress, ResponseOrigin, ResponsePort};
+use stun_codec::{Message, MessageClass, MessageDecoder, MessageEncoder, TransactionId};
+
+// readme 中指定的 STUN 服务器列表（在中国大陆可用）
+pub const DEFAULT_STUN_SERVERS: [&str; 4] = [
+    "fwa.lifesizecloud.com:3478",
+    "stun.isp.net.au:3478",
+    "stun.freeswitch.org:3478",
+    "stun.voip.blackberry.com:3478",
+];
+
+// 客户端能解析的属性：RFC 5389 的全部属性、RFC 8489 的认证属性和 RFC 5780 的 NAT 行为发现属性
+// stun_codec 0.4 解码 CHANGE-REQUEST 时位序与 RFC 5780 不符（编码正确），客户端只发送不解析该属性
+define_attribute_enums!(
+    Attribute,
+    AttributeDecoder,
+    AttributeEncoder,
+    [
+        // RFC 5389
+        MappedAddress,
+        Username,
+        MessageIntegrity,
+        ErrorCode,
+        UnknownAttributes,
+        Realm,
+        Nonce,
+        XorMappedAddress,
+        Software,
+        AlternateServer,
+        Fingerprint,
+        // RFC 8489
+        MessageIntegritySha256,
+        PasswordAlgorithm,
+        PasswordAlgorithms,
+        // RFC 5780
+        ChangeRequest,
+        ResponseOrigin,
+        ResponsePort,
+        OtherAddress
+    ]
+);
+
+// 查询多个服务器时每个服务器的最长等待时间
+const STUN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 接收缓冲区大小，足够容纳不分片的 UDP 报文
+const RECV_BUFFER_SIZE: usize = 1500;
+
+// 401 和 438 响应后最多重新发送的次数
+pub(crate) const MAX_AUTH_RETRIES: u32 = 2;
+
+// 各服务器（host:port）要求的长期凭据，不在其中的服务器不认证
+pub type StunCredentialMap = HashMap<String, StunCredentials>;
+
+// 重传参数，默认值为 RFC 5389 推荐值：RTO 500ms，最多发送 7 次，最后一次等待 16 倍 RTO
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StunConfig {
+    // 初始重传超时，每次重传后翻倍
+    pub rto: Duration,
+    // 最多发送的请求数（Rc）
+    pub max_requests: u32,
+    // 最后一次请求后等待 rto 的倍数（Rm）
+    pub last_wait_factor: u32,
+    // 整个事务的最长时间，未设置时只受重传次数限制
+    pub timeout: Option<Duration>,
+}
+
+impl StunConfig {
+    // 不重传时（TCP）整个事务的最长时间，默认为 RFC 5389 的 Ti = 39.5 秒
+    pub fn transaction_timeout(&self) -> Duration {
+        // max_requests 可以任意设置，计算时饱和而不溢出
+        let retransmissions = self.rto.saturating_mul(2u32.saturating_pow(self.max_requests.saturating_sub(1)) - 1);
+        let timeout = retransmissions.saturating_add(self.rto.saturating_mul(self.last_wait_factor));
+        self.timeout.map_or(timeout, |limit| limit.min(timeout))
+    }
+}
+
+impl Default for StunConfig {
+    fn default() -> Self {
+        StunConfig {
+            rto: Duration::from_millis(500),
+            max_requests: 7,
+            last_wait_factor: 16,
+            timeout: None,
+        }
+    }
+}
+
+// 一次成功的 Binding 事务
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StunResult {
+    // 服务器看到的本机地址
+    pub mapped_address: SocketAddr,
+    // 配置中的服务器名称（host:port）
+    pub server: String,
+    // 实际应答的服务器地址
+    pub server_address: SocketAddr,
+    // 从最后一次发送请求到收到响应的时间
+    pub rtt: Duration,
+    // 支持 RFC 5780 的服务器在 OTHER-ADDRESS 中给出的备用地址
+    pub other_address: Option<SocketAddr>,
+}
+
+// 多个服务器的查询结果，可能同时包含 IPv4 和 IPv6 的映射地址
+// 同一个本地端口在不同服务器看来映射地址不同，说明 NAT 按目标地址分配映射（对称型 NAT）
+#[derive(Debug, Default)]
+pub struct StunProbe {
+    // 成功的结果，按往返时间排序
+    pub results: Vec<StunResult>,
+    // 失败的服务器及原因
+    pub failures: Vec<Error>,
+}
+
+impl StunProbe {
+    // 各映射地址及报告该地址的服务器数量，数量多的在前，数量相同时往返时间短的在前
+    pub fn mapped_addresses(&self) -> Vec<(SocketAddr, usize)> {
+        let mut counts: Vec<(SocketAddr, usize)> = Vec::new();
+        for result in &self.results {
+            match counts.iter_mut().find(|(addr, _)| *addr == result.mapped_address) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((result.mapped_address, 1)),
+            }
+        }
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        counts
+    }
+
+    // 多数服务器报告的公网地址，有 IPv4 结果时为 IPv4 地址
+    pub fn consensus(&self) -> Option<SocketAddr> {
+        self.consensus_for(false).or_else(|| self.consensus_for(true))
+    }
+
+    // 指定地址族中多数服务器报告的公网地址
+    pub fn consensus_for(&self, ipv6: bool) -> Option<SocketAddr> {
+        self.mapped_addresses().into_iter().find(|(addr, _)| addr.is_ipv6() == ipv6).map(|(addr, _)| addr)
+    }
+
+    // 同一地址族中不同服务器报告了不同的映射地址
+    pub fn disagrees(&self) -> bool {
+        self.disagrees_for(false) || self.disagrees_for(true)
+    }
+
+    pub fn disagrees_for(&self, ipv6: bool) -> bool {
+        self.mapped_addresses().iter().filter(|(addr, _)| addr.is_ipv6() == ipv6).count() > 1
+    }
+
+    // 合并另一次查询的结果（例如节点 STUN 和服务器的结果），保持按往返时间排序
+    pub fn extend(&mut self, other: StunProbe) {
+        self.results.extend(other.results);
+        self.failures.extend(other.failures);
+        self.results.sort_by_key(|result| result.rtt);
+    }
+}
+
+// STUN 请求使用的传输协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StunTransport {
+    Udp,
+    // RFC 5389 7.2.2，每个服务器一条 TCP 连接，不重传
+    Tcp,
+}
+
+// 分别从 IPv4 和 IPv6 的同一个本地端口并发查询所有服务器，每个服务器的事务最多持续 STUN_TIMEOUT
+// 没有 IPv6 连接或服务器没有 IPv6 地址很常见，IPv6 全部失败时只报告 IPv4 的结果
+// 至少一个服务器成功时返回各服务器的结果，全部失败时返回 StunFailed
+pub async fn perform_stun_request(stun_servers: &[&str], credentials: &StunCredentialMap) -> Result<StunProbe> {
+    let (ipv4, ipv6) = tokio::join!(
+        probe_from(stun_servers, SocketAddr::from(([0, 0, 0, 0], 0)), StunTransport::Udp, credentials),
+        probe_from(stun_servers, SocketAddr::from(([0u16; 8], 0)), StunTransport::Udp, credentials)
+    );
+    match (ipv4, ipv6) {
+        (Ok(mut probe), Ok(ipv6)) => {
+            probe.extend(ipv6);
+            Ok(probe)
+        }
+        (Ok(probe), Err(_)) => Ok(probe),
+        (Err(Error::StunFailed(failures)), Ok(mut probe)) => {
+            probe.failures.extend(failures);
+            Ok(probe)
+        }
+        (Err(Error::StunFailed(mut failures)), Err(Error::StunFailed(ipv6))) => {
+            failures.extend(ipv6);
+            Err(Error::StunFailed(failures))
+        }
+        (Err(e), _) => Err(e),
+    }
+}
+
+// 从指定的本地地址查询所有服务器，local 可以是 Swarm 正在监听的地址：
+// 套接字设置了地址和端口复用，服务器看到的映射就是其它节点连接监听端口时经过的映射
+// 服务器地址按 local 的地址族解析，credentials 中列出的服务器使用长期凭据
+pub async fn probe_from(
+    stun_servers: &[&str],
+    local: SocketAddr,
+    transport: StunTransport,
+    credentials: &StunCredentialMap,
+) -> Result<StunProbe> {
+    let config = StunConfig { timeout: Some(STUN_TIMEOUT), ..StunConfig::default() };
+
+    let resolved = join_all(stun_servers.iter().map(|server| resolve_family(server, local.is_ipv6()))).await;
+    let mut failures = Vec::new();
+    let mut targets = Vec::new();
+    for (server, address) in stun_servers.iter().zip(resolved) {
+        match address {
+            Ok(address) => targets.push((server.to_string(), address)),
+            Err(e) => failures.push(e),
+        }
+    }
+
+    let outcomes = match transport {
+        _ if targets.is_empty() => Vec::new(),
+        StunTransport::Udp => {
+            let socket = bind_udp(local).map_err(|source| Error::StunIo { server: stun_servers.join(","), source })?;
+            binding_requests(&socket, &targets, credentials, &config).await
+        }
+        StunTransport::Tcp => {
+            join_all(targets.iter().map(|(server, address)| {
+                tcp_binding_request(server, *address, local, credentials.get(server), &config)
+            }))
+            .await
+        }
+    };
+
+    let mut results = Vec::new();
+    for outcome in outcomes {
+        match outcome {
+            Ok(result) => results.push(result),
+            Err(e) => failures.push(e),
+        }
+    }
+
+    if results.is_empty() {
+        return Err(Error::StunFailed(failures));
+    }
+    results.sort_by_key(|result| result.rtt);
+    Ok(StunProbe { results, failures })
+}
+
+// 创建允许地址和端口复用的套接字，其它套接字（Swarm 的监听套接字）已经绑定同一端口时也能绑定
+fn reuse_socket(local: SocketAddr, socket_type: Type, protocol: Protocol) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(local), socket_type, Some(protocol))?;
+    if local.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+pub(crate) fn bind_udp(local: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = reuse_socket(local, Type::DGRAM, Protocol::UDP)?;
+    socket.bind(&local.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+// 通过 TCP 发送 Binding Request（RFC 5389 7.2.2），连接从 local 发起，服务器要求认证时使用 credentials
+pub async fn tcp_binding_request(
+    stun_server: &str,
+    server_address: SocketAddr,
+    local: SocketAddr,
+    credentials: Option<&StunCredentials>,
+    config: &StunConfig,
+) -> Result<StunResult> {
+    let server = stun_server.to_string();
+    let io_error = |source| Error::StunIo { server: server.clone(), source };
+    let started = Instant::now();
+    let deadline = started + config.transaction_timeout();
+
+    let socket = reuse_socket(local, Type::STREAM, Protocol::TCP).map_err(io_error)?;
+    socket.bind(&local.into()).map_err(io_error)?;
+    let socket = TcpSocket::from_std_stream(socket.into());
+    let mut stream = match timeout_at(deadline, socket.connect(server_address)).await {
+        Ok(stream) => stream.map_err(io_error)?,
+        Err(_) => return Err(Error::StunTimeout { server }),
+    };
+
+    let mut auth = credentials.cloned().map(LongTermAuth::new);
+    stream_binding_request(&mut stream, server, server_address, auth.as_mut(), deadline).await
+}
+
+// 在已建立的可靠连接上执行 Binding 事务（TCP 或节点之间的 libp2p 协议流），不重传
+// 有凭据时在 401/438 后带凭据在同一连接上重新发送
+pub(crate) async fn stream_binding_request<S>(
+    stream: &mut S,
+    server: String,
+    server_address: SocketAddr,
+    mut auth: Option<&mut LongTermAuth>,
+    deadline: Instant,
+) -> Result<StunResult>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let io_error = |source| Error::StunIo { server: server.clone(), source };
+    let mut attempts = 0;
+    loop {
+        let transaction_id = TransactionId::new(rand::random());
+        let signed = auth.as_deref().is_some_and(LongTermAuth::is_ready);
+        let request = encode_request(transaction_id, None, auth.as_deref()).map_err(io_error)?;
+        let exchange = async {
+            stream.write_all(&request).await?;
+            let sent_at = Instant::now();
+            // 连接上可能先收到其它报文，直到收到本事务的响应或连接关闭
+            loop {
+                let bytes = read_stream_message(stream).await?;
+                match decode_response(&bytes, transaction_id) {
+                    Response::Unrelated => continue,
+                    response => return Ok((response, bytes, sent_at.elapsed())),
+                }
+            }
+        };
+        let (response, bytes, rtt) = match timeout_at(deadline, exchange).await {
+            Ok(exchanged) => exchanged.map_err(io_error)?,
+            Err(_) => return Err(Error::StunTimeout { server }),
+        };
+        if signed
+            && response.is_authenticated()
+            && let Err(reason) = auth.as_deref().expect("signed with credentials").verify(&bytes)
+        {
+            return Err(Error::StunDecode { server, reason });
+        }
+
+        return match response {
+            Response::Success { mapped_address, other_address } => {
+                Ok(StunResult { mapped_address, server, server_address, rtt, other_address })
+            }
+            Response::Challenge { code, message, .. }
+                if attempts < MAX_AUTH_RETRIES
+                    && auth.as_deref_mut().is_some_and(|auth| auth.challenge(code, &message, signed)) =>
+            {
+                attempts += 1;
+                continue;
+            }
+            Response::Error { code, reason } | Response::Challenge { code, reason, .. } => {
+                Err(Error::StunErrorResponse { server, code, reason })
+            }
+            Response::NoMappedAddress => Err(Error::NoMappedAddress { server }),
+            // 可靠传输上收到无法解码的响应时不会再有正确的响应
+            Response::Invalid(reason) => Err(Error::StunDecode { server, reason }),
+            Response::Unrelated => unreachable!("unrelated responses are skipped"),
+        };
+    }
+}
+
+// 按 STUN 头部中的长度读取一个完整报文：20 字节头部加属性部分
+pub(crate) async fn read_stream_message<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<Vec<u8>> {
+    let mut message = vec![0; 20];
+    stream.read_exact(&mut message).await?;
+    let length = u16::from_be_bytes([message[2], message[3]]) as usize;
+    message.resize(20 + length, 0);
+    stream.read_exact(&mut message[20..]).await?;
+    Ok(message)
+}
+
+// 解析服务器地址，优先使用 IPv4 地址
+pub async fn resolve(stun_server: &str) -> Result<SocketAddr> {
+    let io_error = |source| Error::StunIo { server: stun_server.to_string(), source };
+    let addresses: Vec<SocketAddr> = lookup_host(stun_server).await.map_err(io_error)?.collect();
+    addresses
+        .iter()
+        .find(|addr| addr.is_ipv4())
+        .or(addresses.first())
+        .copied()
+        .ok_or_else(|| io_error(io::Error::new(io::ErrorKind::NotFound, "no address for STUN server")))
+}
+
+// 解析服务器在指定地址族中的地址
+pub async fn resolve_family(stun_server: &str, ipv6: bool) -> Result<SocketAddr> {
+    let io_error = |source| Error::StunIo { server: stun_server.to_string(), source };
+    let mut addresses = lookup_host(stun_server).await.map_err(io_error)?;
+    addresses.find(|addr| addr.is_ipv6() == ipv6).ok_or_else(|| {
+        let family = if ipv6 { "IPv6" } else { "IPv4" };
+        io_error(io::Error::new(io::ErrorKind::NotFound, format!("no {} address for STUN server", family)))
+    })
+}
+
+// 向单个 STUN 服务器发送 Binding Request，按配置重传直到收到有效响应
+pub async fn binding_request(stun_server: &str, config: &StunConfig) -> Result<StunResult> {
+    let server_address = resolve(stun_server).await?;
+    let bind_address = if server_address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind_address)
+        .await
+        .map_err(|source| Error::StunIo { server: stun_server.to_string(), source })?;
+
+    let targets = [(stun_server.to_string(), server_address)];
+    binding_requests(&socket, &targets, &StunCredentialMap::new(), config).await.remove(0)
+}
+
+// 在同一个套接字上并发执行多个 Binding 事务，结果顺序与 servers 相同
+// 响应按来源地址和事务 ID 分配给对应的事务；credentials 中列出的服务器使用长期凭据
+pub async fn binding_requests(
+    socket: &UdpSocket,
+    servers: &[(String, SocketAddr)],
+    credentials: &StunCredentialMap,
+    config: &StunConfig,
+) -> Vec<Result<StunResult>> {
+    let started = Instant::now();
+    let transactions = servers
+        .iter()
+        .map(|(server, address)| {
+            let auth = credentials.get(server).cloned().map(LongTermAuth::new);
+            Transaction::new(server, *address, None, *address, auth, started, config)
+        })
+        .collect();
+    run_transactions(socket, transactions, config).await
+}
+
+// 发送带 CHANGE-REQUEST 的 Binding Request（RFC 5780），要求服务器从其它地址或端口应答
+// 只接受来自 response_source 的响应；服务器未按要求更换地址时事务超时
+pub async fn change_request(
+    socket: &UdpSocket,
+    server: &str,
+    destination: SocketAddr,
+    change: ChangeRequest,
+    response_source: SocketAddr,
+    config: &StunConfig,
+) -> Result<StunResult> {
+    let transaction = Transaction::new(server, destination, Some(change), response_source, None, Instant::now(), config);
+    run_transactions(socket, vec![transaction], config).await.remove(0)
+}
+
+async fn run_transactions(
+    socket: &UdpSocket,
+    mut transactions: Vec<Transaction>,
+    config: &StunConfig,
+) -> Vec<Result<StunResult>> {
+    for transaction in &mut transactions {
+        transaction.send(socket, config).await;
+    }
+
+    let mut buffer = [0; RECV_BUFFER_SIZE];
+    while let Some(deadline) = transactions.iter().filter(|t| t.outcome.is_none()).map(|t| t.deadline).min() {
+        match timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+            Ok(Ok((len, from))) => {
+                // 忽略其它来源的报文和不属于任何事务的响应
+                for transaction in transactions.iter_mut().filter(|t| t.outcome.is_none() && t.response_source == from) {
+                    if transaction.receive(&buffer[..len], config) {
+                        break;
+                    }
+                }
+            }
+            // 部分系统会把 ICMP 错误报告给未连接的套接字，无法判断属于哪个事务，交给超时处理
+            Ok(Err(_)) => {}
+            Err(_) => {
+                let now = Instant::now();
+                for transaction in transactions.iter_mut().filter(|t| t.outcome.is_none() && t.deadline <= now) {
+                    transaction.expire(socket, config).await;
+                }
+            }
+        }
+    }
+
+    transactions.into_iter().map(|t| t.outcome.expect("transaction finished")).collect()
+}
+
+// 一个进行中的 Binding 事务
+struct Transaction {
+    server: String,
+    server_address: SocketAddr,
+    // 期望的响应来源，普通请求为 server_address
+    response_source: SocketAddr,
+    transaction_id: TransactionId,
+    change: Option<ChangeRequest>,
+    request: io::Result<Vec<u8>>,
+    // 服务器的认证状态，请求是否已签名以及 401/438 后重新发送的次数
+    auth: Option<LongTermAuth>,
+    signed: bool,
+    auth_attempts: u32,
+    requests_sent: u32,
+    rto: Duration,
+    sent_at: Instant,
+    // 下一次重传或放弃的时间
+    deadline: Instant,
+    give_up: Option<Instant>,
+    // 收到的无法解码的响应，超时后作为失败原因报告
+    decode_failure: Option<String>,
+    outcome: Option<Result<StunResult>>,
+}
+
+impl Transaction {
+    fn new(
+        server: &str,
+        server_address: SocketAddr,
+        change: Option<ChangeRequest>,
+        response_source: SocketAddr,
+        auth: Option<LongTermAuth>,
+        started: Instant,
+        config: &StunConfig,
+    ) -> Self {
+        let transaction_id = TransactionId::new(rand::random());
+        Transaction {
+            server: server.to_string(),
+            server_address,
+            response_source,
+            transaction_id,
+            request: encode_request(transaction_id, change.clone(), None),
+            change,
+            auth,
+            signed: false,
+            auth_attempts: 0,
+            requests_sent: 0,
+            rto: config.rto,
+            sent_at: started,
+            deadline: started,
+            give_up: config.timeout.map(|timeout| started + timeout),
+            decode_failure: None,
+            outcome: None,
+        }
+    }
+
+    // 发送（或重传）请求并计算下一个截止时间
+    async fn send(&mut self, socket: &UdpSocket, config: &StunConfig) {
+        let sent = match &self.request {
+            Ok(request) => socket.send_to(request, self.server_address).await.map(|_| ()),
+            Err(e) => Err(io::Error::new(e.kind(), e.to_string())),
+        };
+        if let Err(source) = sent {
+            self.outcome = Some(Err(Error::StunIo { server: self.server.clone(), source }));
+            return;
+        }
+
+        self.requests_sent += 1;
+        self.sent_at = Instant::now();
+        let wait = if self.requests_sent >= config.max_requests { config.rto.saturating_mul(config.last_wait_factor) } else { self.rto };
+        self.deadline = self.sent_at + wait;
+        if let Some(give_up) = self.give_up {
+            self.deadline = self.deadline.min(give_up);
+        }
+        self.rto = self.rto.saturating_mul(2);
+    }
+
+    // 截止时间已到：还有重传次数时重传，否则结束事务
+    async fn expire(&mut self, socket: &UdpSocket, config: &StunConfig) {
+        let gave_up = self.give_up.is_some_and(|give_up| Instant::now() >= give_up);
+        if self.requests_sent < config.max_requests && !gave_up {
+            self.send(socket, config).await;
+            return;
+        }
+        let server = self.server.clone();
+        self.outcome = Some(Err(match self.decode_failure.take() {
+            Some(reason) => Error::StunDecode { server, reason },
+            None => Error::StunTimeout { server },
+        }));
+    }
+
+    // 处理来自本事务服务器的报文，属于本事务时返回 true
+    fn receive(&mut self, bytes: &[u8], config: &StunConfig) -> bool {
+        let server = self.server.clone();
+        let response = decode_response(bytes, self.transaction_id);
+        // 签名请求只接受能用同一凭据验证的响应，否则视为伪造并丢弃
+        if self.signed
+            && response.is_authenticated()
+            && let Err(reason) = self.auth.as_ref().expect("signed with credentials").verify(bytes)
+        {
+            self.decode_failure = Some(reason);
+            return true;
+        }
+        let outcome = match response {
+            Response::Success { mapped_address, other_address } => Ok(StunResult {
+                mapped_address,
+                server,
+                server_address: self.server_address,
+                rtt: self.sent_at.elapsed(),
+                other_address,
+            }),
+            Response::Error { code, reason } => Err(Error::StunErrorResponse { server, code, reason }),
+            Response::Challenge { code, reason, message } => {
+                if self.authenticate(code, &message, config) {
+                    return true;
+                }
+                Err(Error::StunErrorResponse { server, code, reason })
+            }
+            Response::NoMappedAddress => Err(Error::NoMappedAddress { server }),
+            Response::Invalid(reason) => {
+                self.decode_failure = Some(reason);
+                return true;
+            }
+            Response::Unrelated => return false,
+        };
+        self.outcome = Some(outcome);
+        true
+    }
+
+    // 401/438 后以新的事务带凭据重新发送，返回是否重新发送
+    fn authenticate(&mut self, code: u16, response: &Message<Attribute>, config: &StunConfig) -> bool {
+        let Some(auth) = self.auth.as_mut() else { return false };
+        if self.auth_attempts >= MAX_AUTH_RETRIES || !auth.challenge(code, response, self.signed) {
+            return false;
+        }
+        self.auth_attempts += 1;
+        self.transaction_id = TransactionId::new(rand::random());
+        self.request = encode_request(self.transaction_id, self.change.clone(), Some(auth));
+        self.signed = true;
+        // 新的请求立即发送，重传计数从头开始
+        self.requests_sent = 0;
+        self.rto = config.rto;
+        self.deadline = Instant::now();
+        true
+    }
+}
+
+// 创建带 FINGERPRINT 的 Binding Request，已从服务器得到 REALM 和 NONCE 时带长期凭据
+fn encode_request(
+    transaction_id: TransactionId,
+    change: Option<ChangeRequest>,
+    auth: Option<&LongTermAuth>,
+) -> io::Result<Vec<u8>> {
+    let mut message = Message::<Attribute>::new(MessageClass::Request, BINDING, transaction_id);
+    if let Some(change) = change {
+        message.add_attribute(change);
+    }
+    if let Some(auth) = auth {
+        auth.sign(&mut message).map_err(io::Error::other)?;
+    }
+    let fingerprint = Fingerprint::new(&message).map_err(io::Error::other)?;
+    message.add_attribute(fingerprint);
+    MessageEncoder::new().encode_into_bytes(message).map_err(io::Error::other)
+}
+
+// 对一个收到的报文的判断
+enum Response {
+    // 成功响应中的映射地址和服务器的备用地址
+    Success { mapped_address: SocketAddr, other_address: Option<SocketAddr> },
+    // 成功响应但没有映射地址属性
+    NoMappedAddress,
+    // 错误响应
+    Error { code: u16, reason: String },
+    // 要求认证（401）或 NONCE 过期（438）的错误响应，其中有服务器给出的 REALM 和 NONCE
+    Challenge { code: u16, reason: String, message: Message<Attribute> },
+    // 无法解码或 FINGERPRINT 错误，丢弃
+    Invalid(String),
+    // 不是本事务的响应，丢弃
+    Unrelated,
+}
+
+impl Response {
+    // 服务器应当对签名请求的这些响应签名；401 和 438 响应不带 MESSAGE-INTEGRITY
+    fn is_authenticated(&self) -> bool {
+        matches!(self, Response::Success { .. } | Response::NoMappedAddress | Response::Error { .. })
+    }
+}
+
+fn decode_response(bytes: &[u8], transaction_id: TransactionId) -> Response {
+    // 外层错误为字节流格式错误，内层为属性解析失败（包括 FINGERPRINT 校验失败）
+    let message = match MessageDecoder::<Attribute>::new().decode_from_bytes(bytes) {
+        Ok(Ok(message)) => message,
+        Ok(Err(broken)) if broken.transaction_id() != transaction_id => return Response::Unrelated,
+        Ok(Err(broken)) => return Response::Invalid(broken.error().to_string()),
+        Err(e) => return Response::Invalid(e.to_string()),
+    };
+
+    if message.transaction_id() != transaction_id || message.method() != BINDING {
+        return Response::Unrelated;
+    }
+
+    match message.class() {
+        MessageClass::SuccessResponse => {
+            // 优先使用 XOR 映射地址，旧服务器只返回 MAPPED-ADDRESS
+            let mapped_address = message
+                .get_attribute::<XorMappedAddress>()
+                .map(XorMappedAddress::address)
+                .or_else(|| message.get_attribute::<MappedAddress>().map(MappedAddress::address));
+            match mapped_address {
+                Some(mapped_address) => {
+                    let other_address = message.get_attribute::<OtherAddress>().map(OtherAddress::address);
+                    Response::Success { mapped_address, other_address }
+                }
+                None => Response::NoMappedAddress,
+            }
+        }
+        MessageClass::ErrorResponse => match message.get_attribute::<ErrorCode>() {
+            Some(error) if matches!(error.code(), 401 | 438) => {
+                let (code, reason) = (error.code(), error.reason_phrase().to_string());
+                Response::Challenge { code, reason, message }
+            }
+            Some(error) => Response::Error { code: error.code(), reason: error.reason_phrase().to_string() },
+            None => Response::Invalid("error response without ERROR-CODE".to_string()),
+        },
+        _ => Response::Unrelated,
+    }
+}
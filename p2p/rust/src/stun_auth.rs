@@ -0,0 +1,308 @@
+// stun_auth.rs - STUN 长期凭据认证（RFC 5389 10.2、RFC 8489 9.2），STUN 和 TURN 客户端共用
+// 第一次请求不带凭据，服务器以 401 给出 REALM 和 NONCE，之后的请求带 USERNAME/REALM/NONCE 和 MESSAGE-INTEGRITY；
+// NONCE 过期时服务器返回 438 和新的 NONCE，更新后重新发送
+// 服务器支持 RFC 8489 时（NONCE 以 "obMatJos2" 开头或 401 响应中有 PASSWORD-ALGORITHMS）改用 MESSAGE-INTEGRITY-SHA256，
+// 密码算法按服务器列出的顺序选择 MD5 或 SHA-256，并在请求中带回 PASSWORD-ALGORITHMS 和选择的 PASSWORD-ALGORITHM
+// stun_codec 0.4 只实现了 RFC 5389 的属性，RFC 8489 新增的属性在这里按原始字节编解码
+use bytecodec::bytes::{BytesEncoder, RemainingBytesDecoder};
+use bytecodec::{ByteCount, Decode, Encode, EncodeExt, Eos, SizedEncode, TryTaggedDecode};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use stun_codec::convert::TryAsRef;
+use stun_codec::rfc5389::attributes::{MessageIntegrity, Nonce, Realm, Username};
+use stun_codec::{Attribute, AttributeType, Message, MessageEncoder};
+
+// RFC 8489 新增属性的类型码
+pub const MESSAGE_INTEGRITY_SHA256: u16 = 0x001C;
+pub const PASSWORD_ALGORITHM: u16 = 0x001D;
+pub const PASSWORD_ALGORITHMS: u16 = 0x8002;
+
+// RFC 8489 9.2 中服务器在 NONCE 开头放置的安全特性标记
+const NONCE_COOKIE: &str = "obMatJos2";
+
+// PASSWORD-ALGORITHM 中的算法编号
+const MD5_ALGORITHM: u16 = 0x0001;
+const SHA256_ALGORITHM: u16 = 0x0002;
+
+// 截断的 MESSAGE-INTEGRITY-SHA256 最短 16 字节（RFC 8489 14.6）
+const MIN_SHA256_INTEGRITY: usize = 16;
+
+// 服务器要求的长期凭据
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StunCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+// stun_codec 不认识的属性，值按原始字节保存
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OpaqueAttribute<const TYPE: u16>(Vec<u8>);
+
+impl<const TYPE: u16> OpaqueAttribute<TYPE> {
+    pub const CODEPOINT: u16 = TYPE;
+
+    pub fn new(value: Vec<u8>) -> Self {
+        OpaqueAttribute(value)
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const TYPE: u16> Attribute for OpaqueAttribute<TYPE> {
+    type Decoder = OpaqueAttributeDecoder<TYPE>;
+    type Encoder = OpaqueAttributeEncoder<TYPE>;
+
+    fn get_type(&self) -> AttributeType {
+        AttributeType::new(TYPE)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OpaqueAttributeDecoder<const TYPE: u16>(RemainingBytesDecoder);
+
+impl<const TYPE: u16> Decode for OpaqueAttributeDecoder<TYPE> {
+    type Item = OpaqueAttribute<TYPE>;
+
+    fn decode(&mut self, buf: &[u8], eos: Eos) -> bytecodec::Result<usize> {
+        self.0.decode(buf, eos)
+    }
+
+    fn finish_decoding(&mut self) -> bytecodec::Result<Self::Item> {
+        self.0.finish_decoding().map(OpaqueAttribute)
+    }
+
+    fn requiring_bytes(&self) -> ByteCount {
+        self.0.requiring_bytes()
+    }
+
+    fn is_idle(&self) -> bool {
+        self.0.is_idle()
+    }
+}
+
+impl<const TYPE: u16> TryTaggedDecode for OpaqueAttributeDecoder<TYPE> {
+    type Tag = AttributeType;
+
+    fn try_start_decoding(&mut self, tag: Self::Tag) -> bytecodec::Result<bool> {
+        Ok(tag.as_u16() == TYPE)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OpaqueAttributeEncoder<const TYPE: u16>(BytesEncoder);
+
+impl<const TYPE: u16> Encode for OpaqueAttributeEncoder<TYPE> {
+    type Item = OpaqueAttribute<TYPE>;
+
+    fn encode(&mut self, buf: &mut [u8], eos: Eos) -> bytecodec::Result<usize> {
+        self.0.encode(buf, eos)
+    }
+
+    fn start_encoding(&mut self, item: Self::Item) -> bytecodec::Result<()> {
+        self.0.start_encoding(item.0)
+    }
+
+    fn requiring_bytes(&self) -> ByteCount {
+        self.0.requiring_bytes()
+    }
+
+    fn is_idle(&self) -> bool {
+        self.0.is_idle()
+    }
+}
+
+impl<const TYPE: u16> SizedEncode for OpaqueAttributeEncoder<TYPE> {
+    fn exact_requiring_bytes(&self) -> u64 {
+        self.0.exact_requiring_bytes()
+    }
+}
+
+pub type MessageIntegritySha256 = OpaqueAttribute<MESSAGE_INTEGRITY_SHA256>;
+pub type PasswordAlgorithm = OpaqueAttribute<PASSWORD_ALGORITHM>;
+pub type PasswordAlgorithms = OpaqueAttribute<PASSWORD_ALGORITHMS>;
+
+// 签名需要的属性：STUN 和 TURN 客户端各自的属性枚举都包含这些属性
+pub trait AuthAttributes:
+    Attribute
+    + From<Username>
+    + From<Realm>
+    + From<Nonce>
+    + From<MessageIntegrity>
+    + From<MessageIntegritySha256>
+    + From<PasswordAlgorithm>
+    + From<PasswordAlgorithms>
+    + TryAsRef<Realm>
+    + TryAsRef<Nonce>
+    + TryAsRef<PasswordAlgorithms>
+{
+}
+
+impl<A> AuthAttributes for A where
+    A: Attribute
+        + From<Username>
+        + From<Realm>
+        + From<Nonce>
+        + From<MessageIntegrity>
+        + From<MessageIntegritySha256>
+        + From<PasswordAlgorithm>
+        + From<PasswordAlgorithms>
+        + TryAsRef<Realm>
+        + TryAsRef<Nonce>
+        + TryAsRef<PasswordAlgorithms>
+{
+}
+
+// 与一个服务器之间的认证状态：凭据和服务器最近给出的 REALM、NONCE 及算法
+#[derive(Debug, Clone)]
+pub struct LongTermAuth {
+    credentials: StunCredentials,
+    realm: Option<Realm>,
+    nonce: Option<Nonce>,
+    // 服务器支持 RFC 8489 时使用 MESSAGE-INTEGRITY-SHA256
+    sha256: bool,
+    // 服务器给出的 PASSWORD-ALGORITHMS，请求中原样带回
+    password_algorithms: Option<PasswordAlgorithms>,
+    algorithm: u16,
+}
+
+impl LongTermAuth {
+    pub fn new(credentials: StunCredentials) -> Self {
+        LongTermAuth { credentials, realm: None, nonce: None, sha256: false, password_algorithms: None, algorithm: MD5_ALGORITHM }
+    }
+
+    pub fn credentials(&self) -> &StunCredentials {
+        &self.credentials
+    }
+
+    // 已从服务器得到 REALM 和 NONCE，请求可以签名
+    pub fn is_ready(&self) -> bool {
+        self.realm.is_some() && self.nonce.is_some()
+    }
+
+    // 处理 401 或 438 错误响应，记录服务器给出的 REALM、NONCE 和算法，返回是否应带凭据重新发送
+    // 401：请求没有凭据，或 NONCE 已更换；用同一 NONCE 签名后仍然 401 说明凭据错误，不再重试
+    // 438：签名请求的 NONCE 过期
+    pub fn challenge<A: AuthAttributes>(&mut self, code: u16, response: &Message<A>, signed: bool) -> bool {
+        let Some(nonce) = response.get_attribute::<Nonce>() else { return false };
+        let retry = match code {
+            401 => !signed || Some(nonce) != self.nonce.as_ref(),
+            438 => signed,
+            _ => false,
+        };
+        if let Some(realm) = response.get_attribute::<Realm>() {
+            self.realm = Some(realm.clone());
+        }
+        self.nonce = Some(nonce.clone());
+        let algorithms = response.get_attribute::<PasswordAlgorithms>();
+        self.sha256 = nonce.value().starts_with(NONCE_COOKIE) || algorithms.is_some();
+        if let Some(algorithms) = algorithms {
+            match preferred_algorithm(algorithms.value()) {
+                Some(algorithm) => self.algorithm = algorithm,
+                // 服务器只支持未知的算法，无法计算密钥
+                None => return false,
+            }
+            self.password_algorithms = Some(algorithms.clone());
+        }
+        retry && self.realm.is_some()
+    }
+
+    // 添加 USERNAME、REALM、NONCE（以及 RFC 8489 的密码算法属性）和 MESSAGE-INTEGRITY(-SHA256)
+    // 调用方在之后添加 FINGERPRINT；尚未得到 REALM 和 NONCE 时不做任何修改并返回 false
+    pub fn sign<A: AuthAttributes>(&self, message: &mut Message<A>) -> bytecodec::Result<bool> {
+        let (Some(realm), Some(nonce)) = (&self.realm, &self.nonce) else { return Ok(false) };
+        let username = Username::new(self.credentials.username.clone())?;
+        message.add_attribute(username.clone());
+        message.add_attribute(realm.clone());
+        message.add_attribute(nonce.clone());
+        if let Some(algorithms) = &self.password_algorithms {
+            message.add_attribute(algorithms.clone());
+            let mut algorithm = self.algorithm.to_be_bytes().to_vec();
+            algorithm.extend_from_slice(&[0, 0]);
+            message.add_attribute(PasswordAlgorithm::new(algorithm));
+        }
+        if self.sha256 {
+            let mut bytes = MessageEncoder::default().encode_into_bytes(message.clone())?;
+            set_length(&mut bytes, 4 + 32);
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key()).expect("HMAC accepts keys of any size");
+            mac.update(&bytes);
+            message.add_attribute(MessageIntegritySha256::new(mac.finalize().into_bytes().to_vec()));
+        } else {
+            let integrity = MessageIntegrity::new_long_term_credential(message, &username, realm, &self.credentials.password)?;
+            message.add_attribute(integrity);
+        }
+        Ok(true)
+    }
+
+    // 验证签名请求的响应：必须带有与请求相同类型的完整性属性并且能用本机凭据验证，否则视为伪造
+    pub fn verify(&self, packet: &[u8]) -> Result<(), String> {
+        let codepoint = if self.sha256 { MESSAGE_INTEGRITY_SHA256 } else { MessageIntegrity::CODEPOINT };
+        let Some((offset, value)) = find_attribute(packet, codepoint) else {
+            return Err("response without MESSAGE-INTEGRITY".to_string());
+        };
+        let mut preceding = packet[..offset].to_vec();
+        set_length(&mut preceding, 4 + value.len());
+        let valid = if self.sha256 {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key()).expect("HMAC accepts keys of any size");
+            mac.update(&preceding);
+            value.len() >= MIN_SHA256_INTEGRITY && mac.verify_truncated_left(value).is_ok()
+        } else {
+            let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(&self.key()).expect("HMAC accepts keys of any size");
+            mac.update(&preceding);
+            mac.verify_slice(value).is_ok()
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err("response MESSAGE-INTEGRITY check failed".to_string())
+        }
+    }
+
+    // 长期凭据的密钥：MD5 或 SHA-256(username:realm:password)
+    fn key(&self) -> Vec<u8> {
+        let realm = self.realm.as_ref().map(Realm::text).unwrap_or_default();
+        let input = format!("{}:{}:{}", self.credentials.username, realm, self.credentials.password);
+        match self.algorithm {
+            SHA256_ALGORITHM => Sha256::digest(input.as_bytes()).to_vec(),
+            _ => md5::compute(input.as_bytes()).0.to_vec(),
+        }
+    }
+}
+
+// PASSWORD-ALGORITHMS 按服务器的偏好排列，选择第一个支持的算法
+fn preferred_algorithm(value: &[u8]) -> Option<u16> {
+    let mut rest = value;
+    while rest.len() >= 4 {
+        let algorithm = u16::from_be_bytes([rest[0], rest[1]]);
+        if algorithm == MD5_ALGORITHM || algorithm == SHA256_ALGORITHM {
+            return Some(algorithm);
+        }
+        let parameters = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+        rest = rest.get(4 + parameters.next_multiple_of(4)..)?;
+    }
+    None
+}
+
+// 把报文头部的长度改为到完整性属性结束为止，完整性按此计算（RFC 5389 15.4）
+fn set_length(bytes: &mut [u8], integrity_len: usize) {
+    let length = (bytes.len() - 20 + integrity_len) as u16;
+    bytes[2..4].copy_from_slice(&length.to_be_bytes());
+}
+
+// 在报文中查找属性，返回属性的起始位置和值
+fn find_attribute(packet: &[u8], codepoint: u16) -> Option<(usize, &[u8])> {
+    let mut offset = 20;
+    while offset + 4 <= packet.len() {
+        let attribute_type = u16::from_be_bytes([packet[offset], packet[offset + 1]]);
+        let length = u16::from_be_bytes([packet[offset + 2], packet[offset + 3]]) as usize;
+        let value = packet.get(offset + 4..offset + 4 + length)?;
+        if attribute_type == codepoint {
+            return Some((offset, value));
+        }
+        offset += 4 + length.next_multiple_of(4);
+    }
+    None
+}
@@ -0,0 +1,118 @@
+// stun_server.rs - 节点内置的 STUN Binding 应答（RFC 5389 第 10 节的最小服务器）
+// 公网可达的节点可以替代第三方 STUN 服务器，告诉其它节点它们被观察到的地址：
+// UDP 上按报文来源地址应答，libp2p 协议流上按连接的远端地址应答（见 peer_stun）
+use bytecodec::{DecodeExt, EncodeExt};
+use std::io;
+use std::net::SocketAddr;
+use stun_codec::rfc5389::attributes::{ErrorCode, Fingerprint, Software, UnknownAttributes, XorMappedAddress};
+use stun_codec::rfc5389::errors::{BadRequest, UnknownAttribute};
+use stun_codec::rfc5389::methods::BINDING;
+use stun_codec::rfc5389;
+use stun_codec::{Attribute as _, Message, MessageClass, MessageDecoder, MessageEncoder};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::UdpSocket;
+
+use crate::stun::{bind_udp, read_stream_message};
+
+// 应答中 SOFTWARE 属性的内容
+const SOFTWARE: &str = concat!("p2p ", env!("CARGO_PKG_VERSION"));
+
+// UDP 应答默认监听的端口（RFC 5389 的 STUN 端口）
+pub const DEFAULT_STUN_RESPONDER_PORT: u16 = 3478;
+
+// 接收缓冲区大小，足够容纳不分片的 UDP 报文
+const RECV_BUFFER_SIZE: usize = 1500;
+
+// 对收到的报文生成应答，observed 为看到的请求来源地址
+// 不是 STUN 请求、FINGERPRINT 错误或指示消息时返回 None，按 RFC 5389 静默丢弃
+pub fn respond(request: &[u8], observed: SocketAddr) -> Option<Vec<u8>> {
+    // 用 RFC 5389 的属性集解码，其它属性（包括 RFC 5780 的 CHANGE-REQUEST）作为未知属性保留
+    let request = match MessageDecoder::<rfc5389::Attribute>::new().decode_from_bytes(request) {
+        Ok(Ok(request)) => request,
+        _ => return None,
+    };
+    if request.class() != MessageClass::Request {
+        return None;
+    }
+
+    if request.method() != BINDING {
+        return encode_error(&request, ErrorCode::from(BadRequest), None);
+    }
+    // 不理解的必选属性（类型 < 0x8000）需要以 420 应答并列出（RFC 5389 7.3.1）
+    let unknowns: Vec<_> = request
+        .unknown_attributes()
+        .map(|attr| attr.get_type())
+        .filter(|attr_type| attr_type.is_comprehension_required())
+        .collect();
+    if !unknowns.is_empty() {
+        return encode_error(&request, ErrorCode::from(UnknownAttribute), Some(UnknownAttributes::new(unknowns)));
+    }
+
+    let mut response = Message::<rfc5389::Attribute>::new(MessageClass::SuccessResponse, BINDING, request.transaction_id());
+    response.add_attribute(XorMappedAddress::new(observed));
+    encode(response)
+}
+
+fn encode_error(
+    request: &Message<rfc5389::Attribute>,
+    error: ErrorCode,
+    unknowns: Option<UnknownAttributes>,
+) -> Option<Vec<u8>> {
+    let mut response = Message::<rfc5389::Attribute>::new(MessageClass::ErrorResponse, request.method(), request.transaction_id());
+    response.add_attribute(error);
+    if let Some(unknowns) = unknowns {
+        response.add_attribute(unknowns);
+    }
+    encode(response)
+}
+
+// 加上 SOFTWARE 和 FINGERPRINT 后编码
+fn encode(mut response: Message<rfc5389::Attribute>) -> Option<Vec<u8>> {
+    if let Ok(software) = Software::new(SOFTWARE.to_string()) {
+        response.add_attribute(software);
+    }
+    let fingerprint = Fingerprint::new(&response).ok()?;
+    response.add_attribute(fingerprint);
+    MessageEncoder::new().encode_into_bytes(response).ok()
+}
+
+// 在 local 上绑定 UDP 应答套接字，设置地址和端口复用
+pub fn bind_udp_responder(local: SocketAddr) -> io::Result<UdpSocket> {
+    bind_udp(local)
+}
+
+// 在 UDP 套接字上持续应答 Binding 请求，直到套接字出错
+pub async fn serve_udp(socket: UdpSocket) -> io::Result<()> {
+    let mut buffer = [0; RECV_BUFFER_SIZE];
+    loop {
+        let (len, from) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            // 之前的应答触发的 ICMP 错误不影响其它请求
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(response) = respond(&buffer[..len], from) {
+            // 发送失败只影响这一个请求
+            let _ = socket.send_to(&response, from).await;
+        }
+    }
+}
+
+// 在可靠的流上应答 Binding 请求，observed 为连接的远端地址，对端关闭流时返回
+pub(crate) async fn serve_stream<S>(stream: &mut S, observed: SocketAddr) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    loop {
+        let request = match read_stream_message(stream).await {
+            Ok(request) => request,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match respond(&request, observed) {
+            Some(response) => stream.write_all(&response).await?,
+            // 流上无法跳过损坏的报文，直接关闭
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid STUN request")),
+        }
+    }
+}
@@ -0,0 +1,360 @@
+// stun_servers.rs - STUN 服务器的健康记录与持久化
+// 每次查询后记录各服务器的成功率、往返时间、最近一次失败和解析到的 IP，保存在 STUN_SERVERS.json 中
+// 查询时优先使用健康的服务器，跳过连续失败的服务器；能正常应答的服务器列表可以通过 DHT 分享给其它节点
+// 其它节点分享的服务器只是候选：总数有上限，从未应答过的候选失败几次后即被删除
+// 要求认证的服务器在记录中设置长期凭据，凭据只保存在本地，不会分享
+use crate::error::{Error, Result};
+use crate::persist;
+use crate::stun::{StunCredentialMap, StunProbe, StunResult};
+use crate::stun_auth::StunCredentials;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// STUN 服务器记录默认保存的文件
+pub const STUN_SERVERS_FILE: &str = "STUN_SERVERS.json";
+
+// STUN_SERVERS.json 的当前格式版本
+pub const STUN_SERVERS_SCHEMA_VERSION: u32 = 1;
+
+// 连续失败达到该次数的服务器暂时不再查询
+pub const DEFAULT_MAX_STUN_FAILURES: u32 = 3;
+
+// 暂停查询的服务器在最近一次失败后经过该时长再重试，网络恢复或服务器恢复后可以重新使用
+pub const DEAD_SERVER_RETRY_HOURS: i64 = 6;
+
+// 分享给其它节点的服务器最多数量
+pub const MAX_SHARED_STUN_SERVERS: usize = 16;
+
+// 记录中保留的其它节点分享的服务器最多数量，已满时不再加入新的候选
+pub const MAX_SHARED_STUN_CANDIDATES: usize = 32;
+
+// 其它节点分享的服务器从未应答过，失败达到该次数后删除
+pub const MAX_SHARED_CANDIDATE_FAILURES: u32 = 3;
+
+// 每轮最多查询的服务器数量，按评分从高到低选取
+pub const MAX_STUN_SERVERS_PER_ROUND: usize = 8;
+
+// 评分中各项的权重：成功率、响应时间、最近活跃程度
+const SUCCESS_WEIGHT: f64 = 0.6;
+const LATENCY_WEIGHT: f64 = 0.3;
+const RECENCY_WEIGHT: f64 = 0.1;
+// 往返时间为该值时延迟得分为 0.5
+const REFERENCE_RTT_MS: f64 = 100.0;
+// 最近活跃得分每经过该时长减半
+const RECENCY_HALF_LIFE_HOURS: f64 = 24.0;
+
+// 一个 STUN 服务器的查询统计
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StunServerRecord {
+    pub server: String, // host:port
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolved: Vec<String>, // 应答过的服务器地址（IPv4 和 IPv6）
+    pub success_count: u32,
+    pub failure_count: u32,
+    pub consecutive_failures: u32, // 自上次成功以来的连续失败次数
+    pub rtt_ms: Option<u64>, // 最近一次成功的往返时间
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+    pub last_error: Option<String>, // 最近一次失败的原因
+    pub score: f64, // 综合评分，0 到 1 之间，越高越优先查询
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<StunCredentials>, // 服务器要求的长期凭据
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub shared: bool, // 由其它节点分享，不在本节点的配置中
+}
+
+impl StunServerRecord {
+    // 为一个尚未查询过的服务器创建记录
+    pub fn new(server: &str) -> Self {
+        StunServerRecord {
+            server: server.to_string(),
+            resolved: Vec::new(),
+            success_count: 0,
+            failure_count: 0,
+            consecutive_failures: 0,
+            rtt_ms: None,
+            last_success: None,
+            last_failure: None,
+            last_error: None,
+            score: 0.0,
+            credentials: None,
+            shared: false,
+        }
+    }
+
+    // 综合成功率、往返时间和最近一次成功的时间计算评分
+    pub fn compute_score(&self, now: DateTime<Utc>) -> f64 {
+        // 加一平滑，没有记录的服务器成功率为 0.5
+        let success_ratio =
+            (self.success_count as f64 + 1.0) / (self.success_count as f64 + self.failure_count as f64 + 2.0);
+
+        let latency = match self.rtt_ms {
+            Some(rtt) => REFERENCE_RTT_MS / (REFERENCE_RTT_MS + rtt as f64),
+            None => 0.5,
+        };
+
+        let recency = self
+            .last_success
+            .map(|t| {
+                let hours = (now - t).num_seconds().max(0) as f64 / 3600.0;
+                0.5f64.powf(hours / RECENCY_HALF_LIFE_HOURS)
+            })
+            .unwrap_or(0.0);
+
+        SUCCESS_WEIGHT * success_ratio + LATENCY_WEIGHT * latency + RECENCY_WEIGHT * recency
+    }
+
+    // 连续失败达到阈值，且距最近一次失败还不到重试间隔
+    pub fn is_dead(&self, max_failures: u32, now: DateTime<Utc>) -> bool {
+        self.consecutive_failures >= max_failures
+            && self.last_failure.is_some_and(|t| now - t < chrono::Duration::hours(DEAD_SERVER_RETRY_HOURS))
+    }
+
+    // 是否适合分享给其它节点：最近一次查询成功
+    pub fn is_healthy(&self) -> bool {
+        self.success_count > 0 && self.consecutive_failures == 0
+    }
+
+    fn record_success(&mut self, resolved: &str, rtt_ms: u64, now: DateTime<Utc>) {
+        if !self.resolved.iter().any(|addr| addr == resolved) {
+            self.resolved.push(resolved.to_string());
+        }
+        self.rtt_ms = Some(rtt_ms);
+        self.last_success = Some(now);
+    }
+
+    // 其它节点分享的服务器从未应答过，且已经失败多次
+    pub fn is_failed_candidate(&self) -> bool {
+        self.shared && self.success_count == 0 && self.failure_count >= MAX_SHARED_CANDIDATE_FAILURES
+    }
+
+    fn record_failure(&mut self, error: &str, now: DateTime<Utc>) {
+        self.last_failure = Some(now);
+        self.last_error = Some(error.to_string());
+    }
+}
+
+// STUN_SERVERS.json 的内容
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StunServerList {
+    pub version: u32,
+    pub servers: Vec<StunServerRecord>,
+    pub last_updated: DateTime<Utc>,
+}
+
+// 通过 DHT 分享的服务器列表，只包含服务器名称，接收方自己统计其健康状况
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SharedStunServers {
+    pub servers: Vec<String>,
+    pub last_updated: DateTime<Utc>,
+}
+
+// 合并配置的服务器和上次保存的记录：配置的服务器在前，保留已有的统计；
+// 只存在于保存记录中的服务器（例如其它节点分享的）也保留
+pub fn merge_stun_servers(configured: &[String], saved: Vec<StunServerRecord>) -> Vec<StunServerRecord> {
+    let mut merged: Vec<StunServerRecord> = Vec::new();
+    for server in configured {
+        if merged.iter().any(|record| record.server == *server) {
+            continue;
+        }
+        match saved.iter().find(|record| record.server == *server) {
+            // 其它节点分享过、后来又写进配置的服务器不再算作候选
+            Some(record) => merged.push(StunServerRecord { shared: false, ..record.clone() }),
+            None => merged.push(StunServerRecord::new(server)),
+        }
+    }
+    for record in saved {
+        if !merged.iter().any(|existing| existing.server == record.server) {
+            merged.push(record);
+        }
+    }
+    merged
+}
+
+// 加入其它节点分享的服务器，已知的服务器不受影响，返回新加入的数量
+// 记录中的候选达到 MAX_SHARED_STUN_CANDIDATES 时不再加入
+pub fn merge_shared_stun_servers(records: &mut Vec<StunServerRecord>, shared: &SharedStunServers) -> usize {
+    let mut candidates = records.iter().filter(|record| record.shared).count();
+    let mut added = 0;
+    for server in shared.servers.iter().take(MAX_SHARED_STUN_SERVERS) {
+        if candidates >= MAX_SHARED_STUN_CANDIDATES {
+            break;
+        }
+        // 只接受 host:port 形式的名称
+        let valid = matches!(server.rsplit_once(':'), Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok());
+        if valid && !records.iter().any(|record| record.server == *server) {
+            records.push(StunServerRecord { shared: true, ..StunServerRecord::new(server) });
+            candidates += 1;
+            added += 1;
+        }
+    }
+    added
+}
+
+// 删除其它节点分享的、从未应答且已多次失败的服务器，返回删除的数量
+pub fn prune_failed_candidates(records: &mut Vec<StunServerRecord>) -> usize {
+    let before = records.len();
+    records.retain(|record| !record.is_failed_candidate());
+    before - records.len()
+}
+
+// 一轮查询（可能包括多个地址族和传输协议）中各服务器的结果
+// 只保存服务器名称、地址和错误文本，可以移到阻塞线程中计入统计
+#[derive(Debug, Clone, Default)]
+pub struct StunRound {
+    succeeded: Vec<(String, String, u64)>, // 服务器、应答的服务器地址、往返时间（毫秒）
+    failed: Vec<(String, String)>, // 服务器、失败原因
+}
+
+impl StunRound {
+    pub fn from_outcomes<'a>(outcomes: impl IntoIterator<Item = &'a Result<StunProbe>>) -> Self {
+        let mut results: Vec<&StunResult> = Vec::new();
+        let mut failures: Vec<&Error> = Vec::new();
+        for outcome in outcomes {
+            match outcome {
+                Ok(probe) => {
+                    results.extend(&probe.results);
+                    failures.extend(&probe.failures);
+                }
+                Err(Error::StunFailed(errors)) => failures.extend(errors),
+                Err(error) => failures.push(error),
+            }
+        }
+        StunRound {
+            succeeded: results
+                .into_iter()
+                .map(|result| (result.server.clone(), result.server_address.to_string(), result.rtt.as_millis() as u64))
+                .collect(),
+            failed: failures
+                .into_iter()
+                .filter_map(|error| Some((error.stun_server()?.to_string(), error.to_string())))
+                .collect(),
+        }
+    }
+
+    // 计入各服务器的统计
+    // 服务器在任一查询中应答即算成功，例如没有 IPv6 地址的服务器不会因 IPv6 查询失败而被计为失败；
+    // 每个服务器每轮只计一次
+    pub fn record(&self, records: &mut [StunServerRecord]) {
+        let now = Utc::now();
+        let mut succeeded: Vec<&str> = Vec::new();
+        // 不在列表中的应答者（例如通过 DHT 询问的节点）不记录
+        for (server, resolved, rtt_ms) in &self.succeeded {
+            if let Some(record) = records.iter_mut().find(|record| record.server == *server) {
+                record.record_success(resolved, *rtt_ms, now);
+                succeeded.push(server);
+            }
+        }
+
+        let mut failed: Vec<&str> = Vec::new();
+        for (server, error) in &self.failed {
+            if succeeded.contains(&server.as_str()) {
+                continue;
+            }
+            if let Some(record) = records.iter_mut().find(|record| record.server == *server) {
+                record.record_failure(error, now);
+                failed.push(server);
+            }
+        }
+
+        for record in records.iter_mut() {
+            if succeeded.contains(&record.server.as_str()) {
+                record.success_count += 1;
+                record.consecutive_failures = 0;
+            } else if failed.contains(&record.server.as_str()) {
+                record.failure_count += 1;
+                record.consecutive_failures += 1;
+            }
+            record.score = record.compute_score(now);
+        }
+    }
+}
+
+// 把一轮查询的结果计入各服务器的统计
+pub fn record_stun_outcomes<'a>(records: &mut [StunServerRecord], outcomes: impl IntoIterator<Item = &'a Result<StunProbe>>) {
+    StunRound::from_outcomes(outcomes).record(records);
+}
+
+// 重新计算评分并按评分从高到低排序
+pub fn rank_stun_servers(records: &mut [StunServerRecord]) {
+    let now = Utc::now();
+    for record in records.iter_mut() {
+        record.score = record.compute_score(now);
+    }
+    records.sort_by(|a, b| b.score.total_cmp(&a.score));
+}
+
+// 按评分排序后选出本轮要查询的服务器（最多 MAX_STUN_SERVERS_PER_ROUND 个），跳过暂停查询的服务器；
+// 全部暂停时仍然查询评分最高的服务器
+pub fn select_stun_servers(records: &mut [StunServerRecord], max_failures: u32) -> Vec<String> {
+    rank_stun_servers(records);
+    let now = Utc::now();
+    let alive: Vec<String> = records
+        .iter()
+        .filter(|record| !record.is_dead(max_failures, now))
+        .take(MAX_STUN_SERVERS_PER_ROUND)
+        .map(|record| record.server.clone())
+        .collect();
+    if alive.is_empty() {
+        return records.iter().take(MAX_STUN_SERVERS_PER_ROUND).map(|record| record.server.clone()).collect();
+    }
+    alive
+}
+
+// 记录中设置了长期凭据的服务器及其凭据
+pub fn stun_credentials(records: &[StunServerRecord]) -> StunCredentialMap {
+    records
+        .iter()
+        .filter_map(|record| Some((record.server.clone(), record.credentials.clone()?)))
+        .collect()
+}
+
+// 生成分享给其它节点的列表：最近一次查询成功的服务器，按评分排序
+pub fn shared_stun_servers(records: &[StunServerRecord]) -> SharedStunServers {
+    let mut healthy: Vec<StunServerRecord> = records.iter().filter(|record| record.is_healthy()).cloned().collect();
+    rank_stun_servers(&mut healthy);
+    SharedStunServers {
+        servers: healthy.into_iter().take(MAX_SHARED_STUN_SERVERS).map(|record| record.server).collect(),
+        last_updated: Utc::now(),
+    }
+}
+
+// 从JSON文件加载保存的服务器记录，文件不存在时返回空列表
+pub fn load_stun_servers_from_path(path: &Path) -> Result<Vec<StunServerRecord>> {
+    let Some(value) = persist::read_json(path)? else {
+        return Ok(Vec::new());
+    };
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version != STUN_SERVERS_SCHEMA_VERSION as u64 {
+        return Err(Error::UnsupportedVersion { path: path.to_path_buf(), version, supported: STUN_SERVERS_SCHEMA_VERSION });
+    }
+    let list: StunServerList = persist::from_json(path, value)?;
+    Ok(list.servers)
+}
+
+// 按评分排序后原子地保存服务器记录
+pub fn save_stun_servers_to_path(records: &[StunServerRecord], path: &Path) -> Result<()> {
+    save_locked(&persist::lock(path).map_err(|e| Error::persist(path, e))?, records.to_vec())
+}
+
+// 在文件锁内读取、修改并保存服务器记录，同时运行的多个任务不会用各自读到的旧记录覆盖彼此的修改
+// 读取的记录先与配置的服务器合并，保存前删除失败的候选；返回 update 的结果
+pub fn update_stun_servers_at_path<T>(
+    path: &Path,
+    configured: &[String],
+    update: impl FnOnce(&mut Vec<StunServerRecord>) -> T,
+) -> Result<T> {
+    let lock = persist::lock(path).map_err(|e| Error::persist(path, e))?;
+    let mut records = merge_stun_servers(configured, load_stun_servers_from_path(path)?);
+    let result = update(&mut records);
+    prune_failed_candidates(&mut records);
+    save_locked(&lock, records)?;
+    Ok(result)
+}
+
+// 调用方已经持有文件锁
+fn save_locked(lock: &persist::FileLock, mut servers: Vec<StunServerRecord>) -> Result<()> {
+    rank_stun_servers(&mut servers);
+    lock.write_json(&StunServerList { version: STUN_SERVERS_SCHEMA_VERSION, servers, last_updated: Utc::now() })
+}
@@ -0,0 +1,420 @@
+// turn.rs - RFC 5766/8656 TURN 客户端
+// 双方都在对称型 NAT 之后时无法直接连接，TURN 服务器为本机分配一个公网中继地址，
+// 其它节点发往中继地址的 UDP 数据由服务器转发给本机，本机通过服务器向它们发送数据
+// 消息层与 stun.rs 相同，基于 stun_codec；请求按 StunConfig 的 RTO 重传，
+// 服务器要求认证（401）时使用长期凭据（stun_auth）重新发送，NONCE 过期（438）时更新后重发
+use crate::error::{Error, Result};
+use crate::stun::{self, StunConfig, MAX_AUTH_RETRIES};
+use crate::stun_auth::{LongTermAuth, MessageIntegritySha256, PasswordAlgorithm, PasswordAlgorithms, StunCredentials};
+use bytecodec::{DecodeExt, EncodeExt};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use stun_codec::define_attribute_enums;
+use stun_codec::rfc5389::attributes::{
+    ErrorCode, Fingerprint, MessageIntegrity, Nonce, Realm, Software, UnknownAttributes, Username, XorMappedAddress,
+};
+use stun_codec::rfc5766::attributes::{
+    ChannelNumber, Data, DontFragment, EvenPort, Lifetime, RequestedTransport, ReservationToken, XorPeerAddress,
+    XorRelayAddress,
+};
+use stun_codec::rfc5766::methods::{ALLOCATE, CHANNEL_BIND, CREATE_PERMISSION, DATA, REFRESH, SEND};
+use stun_codec::{Message, MessageClass, MessageDecoder, MessageEncoder, Method, TransactionId};
+use tokio::net::UdpSocket;
+use tokio::time::{timeout_at, Instant};
+
+// TURN 客户端能解析的属性：认证和错误相关的 RFC 5389 和 RFC 8489 属性，以及 RFC 5766 的全部属性
+define_attribute_enums!(
+    Attribute,
+    AttributeDecoder,
+    AttributeEncoder,
+    [
+        // RFC 5389
+        Username,
+        MessageIntegrity,
+        ErrorCode,
+        UnknownAttributes,
+        Realm,
+        Nonce,
+        XorMappedAddress,
+        Software,
+        Fingerprint,
+        // RFC 8489
+        MessageIntegritySha256,
+        PasswordAlgorithm,
+        PasswordAlgorithms,
+        // RFC 5766
+        ChannelNumber,
+        Lifetime,
+        XorPeerAddress,
+        Data,
+        XorRelayAddress,
+        EvenPort,
+        RequestedTransport,
+        DontFragment,
+        ReservationToken
+    ]
+);
+
+// 请求的分配有效期，服务器可能给出更短的时间
+pub const DEFAULT_ALLOCATION_LIFETIME: Duration = Duration::from_secs(600);
+
+// 权限的有效期（RFC 5766 8），需要在到期前重新安装
+pub const PERMISSION_LIFETIME: Duration = Duration::from_secs(300);
+
+// 通道绑定的有效期（RFC 5766 11）
+pub const CHANNEL_LIFETIME: Duration = Duration::from_secs(600);
+
+// 一个 CreatePermission 请求中最多的对端数量：IPv6 的 XOR-PEER-ADDRESS 占 24 字节，
+// 加上头部和认证属性后请求仍小于 IPv6 的最小 MTU（1280 字节），不会被分片或被服务器拒绝
+pub const MAX_PERMISSIONS_PER_REQUEST: usize = 32;
+
+// REQUESTED-TRANSPORT 中的 UDP 协议号
+const UDP_PROTOCOL: u8 = 17;
+
+const RECV_BUFFER_SIZE: usize = 65536;
+
+// 服务器分配的中继地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TurnAllocation {
+    // 其它节点发送数据的公网中继地址
+    pub relayed_address: SocketAddr,
+    // 服务器看到的本机地址
+    pub mapped_address: Option<SocketAddr>,
+    // 服务器给出的有效期，到期前需要 refresh
+    pub lifetime: Duration,
+}
+
+// 与一个 TURN 服务器之间的分配，allocate 成功后才能使用
+pub struct TurnClient {
+    socket: UdpSocket,
+    server: String,
+    server_address: SocketAddr,
+    config: StunConfig,
+    // 配置了凭据时与服务器之间的认证状态
+    auth: Option<LongTermAuth>,
+    allocation: TurnAllocation,
+    // 已绑定通道的对端及通道号
+    channels: HashMap<SocketAddr, u16>,
+    next_channel: u16,
+    // 等待事务响应期间收到的中继数据
+    received: VecDeque<(Vec<u8>, SocketAddr)>,
+}
+
+impl TurnClient {
+    // 向服务器（host:port）请求分配 UDP 中继地址，服务器要求认证时使用 credentials
+    pub async fn allocate(server: &str, credentials: Option<StunCredentials>, config: StunConfig) -> Result<TurnClient> {
+        let server_address = stun::resolve(server).await?;
+        let bind_address = if server_address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(bind_address)
+            .await
+            .map_err(|source| Error::StunIo { server: server.to_string(), source })?;
+
+        let mut client = TurnClient {
+            socket,
+            server: server.to_string(),
+            server_address,
+            auth: credentials.map(LongTermAuth::new),
+            config,
+            allocation: TurnAllocation {
+                relayed_address: server_address,
+                mapped_address: None,
+                lifetime: Duration::ZERO,
+            },
+            channels: HashMap::new(),
+            next_channel: ChannelNumber::MIN,
+            received: VecDeque::new(),
+        };
+
+        let response = client
+            .request(ALLOCATE, |message| {
+                message.add_attribute(RequestedTransport::new(UDP_PROTOCOL));
+                if let Ok(lifetime) = Lifetime::new(DEFAULT_ALLOCATION_LIFETIME) {
+                    message.add_attribute(lifetime);
+                }
+            })
+            .await?;
+        let relayed_address = response
+            .get_attribute::<XorRelayAddress>()
+            .map(XorRelayAddress::address)
+            .ok_or_else(|| client.decode_error("allocate response without XOR-RELAYED-ADDRESS"))?;
+        client.allocation = TurnAllocation {
+            relayed_address,
+            mapped_address: response.get_attribute::<XorMappedAddress>().map(XorMappedAddress::address),
+            lifetime: lifetime_of(&response),
+        };
+        Ok(client)
+    }
+
+    // 配置中的服务器名称
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    pub fn server_address(&self) -> SocketAddr {
+        self.server_address
+    }
+
+    pub fn allocation(&self) -> TurnAllocation {
+        self.allocation
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    // 延长分配的有效期，返回服务器给出的新有效期
+    pub async fn refresh(&mut self, lifetime: Duration) -> Result<Duration> {
+        let requested = Lifetime::new(lifetime).map_err(|e| self.decode_error(&e.to_string()))?;
+        let response = self.request(REFRESH, |message| message.add_attribute(requested.clone())).await?;
+        self.allocation.lifetime = lifetime_of(&response);
+        Ok(self.allocation.lifetime)
+    }
+
+    // 释放分配（有效期为 0 的 Refresh），服务器不再转发数据
+    pub async fn release(mut self) -> Result<()> {
+        self.refresh(Duration::ZERO).await.map(|_| ())
+    }
+
+    // 为对端 IP 安装或刷新权限，服务器只转发来自有权限 IP 的数据；权限不区分端口
+    // 每个请求最多包含 MAX_PERMISSIONS_PER_REQUEST 个 IP，一批失败时仍发送其余批次，返回第一个错误
+    pub async fn create_permission(&mut self, peers: &[IpAddr]) -> Result<()> {
+        let mut result = Ok(());
+        for batch in peers.chunks(MAX_PERMISSIONS_PER_REQUEST) {
+            let installed = self
+                .request(CREATE_PERMISSION, |message| {
+                    for ip in batch {
+                        message.add_attribute(XorPeerAddress::new(SocketAddr::new(*ip, 0)));
+                    }
+                })
+                .await;
+            if let (Err(e), Ok(())) = (installed, &result) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    // 为对端绑定通道（同时安装该 IP 的权限），之后的数据使用 4 字节头部的 ChannelData 而不是 Send/Data 指示
+    // 已绑定的对端重新绑定同一通道号即为刷新
+    pub async fn channel_bind(&mut self, peer: SocketAddr) -> Result<u16> {
+        let number = match self.channels.get(&peer) {
+            Some(number) => *number,
+            None => {
+                let number = self.next_channel;
+                self.next_channel = if number >= ChannelNumber::MAX { ChannelNumber::MIN } else { number + 1 };
+                number
+            }
+        };
+        let channel = ChannelNumber::new(number).map_err(|e| self.decode_error(&e.to_string()))?;
+        self.request(CHANNEL_BIND, |message| {
+            message.add_attribute(channel);
+            message.add_attribute(XorPeerAddress::new(peer));
+        })
+        .await?;
+        self.channels.insert(peer, number);
+        Ok(number)
+    }
+
+    // 已绑定通道的对端
+    pub fn channel_peers(&self) -> Vec<SocketAddr> {
+        self.channels.keys().copied().collect()
+    }
+
+    // 通过中继地址向对端发送一个数据报，已绑定通道时使用 ChannelData，否则使用 Send 指示
+    pub async fn send_to(&self, data: &[u8], peer: SocketAddr) -> Result<()> {
+        let packet = match self.channels.get(&peer) {
+            Some(number) => encode_channel_data(*number, data),
+            None => {
+                let mut message = Message::<Attribute>::new(MessageClass::Indication, SEND, random_transaction_id());
+                message.add_attribute(XorPeerAddress::new(peer));
+                message.add_attribute(Data::new(data.to_vec()).map_err(|e| self.decode_error(&e.to_string()))?);
+                MessageEncoder::new().encode_into_bytes(message).map_err(|e| self.io_error(io::Error::other(e)))?
+            }
+        };
+        self.socket.send_to(&packet, self.server_address).await.map_err(|e| self.io_error(e))?;
+        Ok(())
+    }
+
+    // 接收一个经中继转发的数据报及其来源（对端在服务器上看到的地址）
+    // 可以安全地取消：数据报在接收后立即解析，不会只处理一半
+    pub async fn recv_from(&mut self) -> Result<(Vec<u8>, SocketAddr)> {
+        if let Some(received) = self.received.pop_front() {
+            return Ok(received);
+        }
+        let mut buffer = vec![0; RECV_BUFFER_SIZE];
+        loop {
+            let (len, from) = self.socket.recv_from(&mut buffer).await.map_err(|e| self.io_error(e))?;
+            if from != self.server_address {
+                continue;
+            }
+            if let Some(received) = self.decode_data(&buffer[..len]) {
+                return Ok(received);
+            }
+        }
+    }
+
+    // 执行一个请求事务：按 RTO 重传，401 时带上长期凭据重发，438 时更新 NONCE 后重发
+    async fn request(&mut self, method: Method, attributes: impl Fn(&mut Message<Attribute>)) -> Result<Message<Attribute>> {
+        for _ in 0..=MAX_AUTH_RETRIES {
+            let mut message = Message::new(MessageClass::Request, method, random_transaction_id());
+            attributes(&mut message);
+            let signed = self.sign(&mut message)?;
+            let response = self.transaction(message, signed).await?;
+            if response.class() == MessageClass::SuccessResponse {
+                return Ok(response);
+            }
+
+            let (code, reason) = match response.get_attribute::<ErrorCode>() {
+                Some(error) => (error.code(), error.reason_phrase().to_string()),
+                None => return Err(self.decode_error("error response without ERROR-CODE")),
+            };
+            let retry = matches!(code, 401 | 438)
+                && self.auth.as_mut().is_some_and(|auth| auth.challenge(code, &response, signed));
+            if !retry {
+                return Err(Error::StunErrorResponse { server: self.server.clone(), code, reason });
+            }
+        }
+        Err(Error::StunErrorResponse {
+            server: self.server.clone(),
+            code: 401,
+            reason: "too many authentication attempts".to_string(),
+        })
+    }
+
+    // 已知 REALM 和 NONCE 时添加长期凭据，返回是否已签名；最后添加 FINGERPRINT
+    fn sign(&self, message: &mut Message<Attribute>) -> Result<bool> {
+        let signed = match &self.auth {
+            Some(auth) => auth.sign(message).map_err(|e| self.decode_error(&e.to_string()))?,
+            None => false,
+        };
+        let fingerprint = Fingerprint::new(message).map_err(|e| self.decode_error(&e.to_string()))?;
+        message.add_attribute(fingerprint);
+        Ok(signed)
+    }
+
+    // 发送请求并等待同一事务的响应，期间收到的中继数据放入队列
+    async fn transaction(&mut self, request: Message<Attribute>, signed: bool) -> Result<Message<Attribute>> {
+        let transaction_id = request.transaction_id();
+        let method = request.method();
+        let bytes = MessageEncoder::new().encode_into_bytes(request).map_err(|e| self.io_error(io::Error::other(e)))?;
+
+        let started = Instant::now();
+        let give_up = self.config.timeout.map(|timeout| started + timeout);
+        let mut rto = self.config.rto;
+        let mut buffer = vec![0; RECV_BUFFER_SIZE];
+        let mut decode_failure = None;
+        for sent in 1..=self.config.max_requests {
+            self.socket.send_to(&bytes, self.server_address).await.map_err(|e| self.io_error(e))?;
+            let wait = if sent >= self.config.max_requests { self.config.rto.saturating_mul(self.config.last_wait_factor) } else { rto };
+            let mut deadline = Instant::now() + wait;
+            if let Some(give_up) = give_up {
+                deadline = deadline.min(give_up);
+            }
+            rto = rto.saturating_mul(2);
+
+            while let Ok(received) = timeout_at(deadline, self.socket.recv_from(&mut buffer)).await {
+                // 部分系统会把 ICMP 错误报告给未连接的套接字，交给超时处理
+                let Ok((len, from)) = received else { continue };
+                if from != self.server_address {
+                    continue;
+                }
+                let packet = &buffer[..len];
+                if let Some(data) = self.decode_data(packet) {
+                    self.received.push_back(data);
+                    continue;
+                }
+                match self.decode_response(packet, transaction_id, method, signed) {
+                    Ok(Some(response)) => return Ok(response),
+                    Ok(None) => {}
+                    Err(reason) => decode_failure = Some(reason),
+                }
+            }
+            if give_up.is_some_and(|give_up| Instant::now() >= give_up) {
+                break;
+            }
+        }
+        let server = self.server.clone();
+        Err(match decode_failure {
+            Some(reason) => Error::StunDecode { server, reason },
+            None => Error::StunTimeout { server },
+        })
+    }
+
+    // 解析本事务的响应；签名请求的响应（401 和 438 除外）必须能用本机凭据验证，否则视为伪造并丢弃
+    fn decode_response(
+        &self,
+        packet: &[u8],
+        transaction_id: TransactionId,
+        method: Method,
+        signed: bool,
+    ) -> std::result::Result<Option<Message<Attribute>>, String> {
+        let message = match MessageDecoder::<Attribute>::new().decode_from_bytes(packet) {
+            Ok(Ok(message)) => message,
+            Ok(Err(broken)) if broken.transaction_id() != transaction_id => return Ok(None),
+            Ok(Err(broken)) => return Err(broken.error().to_string()),
+            Err(e) => return Err(e.to_string()),
+        };
+        let is_response = matches!(message.class(), MessageClass::SuccessResponse | MessageClass::ErrorResponse);
+        if message.transaction_id() != transaction_id || message.method() != method || !is_response {
+            return Ok(None);
+        }
+        let challenge = message.get_attribute::<ErrorCode>().is_some_and(|error| matches!(error.code(), 401 | 438));
+        if let (true, false, Some(auth)) = (signed, challenge, &self.auth) {
+            auth.verify(packet)?;
+        }
+        Ok(Some(message))
+    }
+
+    // 解析服务器转发的数据：ChannelData 或 Data 指示，其它报文返回 None
+    fn decode_data(&self, packet: &[u8]) -> Option<(Vec<u8>, SocketAddr)> {
+        if let Some((number, data)) = decode_channel_data(packet) {
+            let peer = self.channels.iter().find(|(_, n)| **n == number).map(|(peer, _)| *peer)?;
+            return Some((data.to_vec(), peer));
+        }
+        let message = MessageDecoder::<Attribute>::new().decode_from_bytes(packet).ok()?.ok()?;
+        if message.class() != MessageClass::Indication || message.method() != DATA {
+            return None;
+        }
+        let peer = message.get_attribute::<XorPeerAddress>()?.address();
+        let data = message.get_attribute::<Data>()?.data().to_vec();
+        Some((data, peer))
+    }
+
+    fn decode_error(&self, reason: &str) -> Error {
+        Error::StunDecode { server: self.server.clone(), reason: reason.to_string() }
+    }
+
+    fn io_error(&self, source: io::Error) -> Error {
+        Error::StunIo { server: self.server.clone(), source }
+    }
+}
+
+fn random_transaction_id() -> TransactionId {
+    TransactionId::new(rand::random())
+}
+
+// 响应中的 LIFETIME，缺少时按请求的默认值
+fn lifetime_of(response: &Message<Attribute>) -> Duration {
+    response.get_attribute::<Lifetime>().map(Lifetime::lifetime).unwrap_or(DEFAULT_ALLOCATION_LIFETIME)
+}
+
+// ChannelData 消息（RFC 5766 11.4）：2 字节通道号、2 字节长度和数据，UDP 上不需要填充
+pub fn encode_channel_data(number: u16, data: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(4 + data.len());
+    packet.extend_from_slice(&number.to_be_bytes());
+    packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    packet.extend_from_slice(data);
+    packet
+}
+
+// 解析 ChannelData 消息，首字节的最高两位为 01（通道号 0x4000 到 0x7FFF）
+pub fn decode_channel_data(packet: &[u8]) -> Option<(u16, &[u8])> {
+    if packet.len() < 4 || packet[0] & 0xc0 != 0x40 {
+        return None;
+    }
+    let number = u16::from_be_bytes([packet[0], packet[1]]);
+    let len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    packet.get(4..4 + len).map(|data| (number, data))
+}
@@ -0,0 +1,651 @@
+// turn_transport.rs - 经 TURN 中继的 libp2p 传输层，直接连接都失败时的最后手段
+// 监听：listen_on 配置的 TURN 服务器地址时向服务器申请分配，得到的中继地址 /ip4/<R>/udp/<P> 作为监听地址报告
+// 拨号：其它节点直接向中继地址发送 UDP 数据报，不需要自己的 TURN 服务器
+// UDP 不保证送达和顺序，每个连接在数据报上运行一个简单的滑动窗口重传协议，向上层提供可靠的字节流，
+// 之后与 TCP 一样进行 noise 握手和 yamux 多路复用
+// TURN 服务器只转发来自有权限 IP 的数据，节点通过 TurnPermissions 为可能连接本节点的对端安装权限；
+// 对端离开路由表后不再刷新其权限，权限在服务器上自然过期
+use crate::stun::StunConfig;
+use crate::stun_auth::StunCredentials;
+use crate::turn::{TurnClient, CHANNEL_LIFETIME, DEFAULT_ALLOCATION_LIFETIME, PERMISSION_LIFETIME};
+use libp2p::core::transport::{DialOpts, ListenerId, TransportError, TransportEvent};
+use libp2p::futures::future::{self, BoxFuture, FutureExt, Ready};
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId, Transport};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::{interval, timeout, Instant, MissedTickBehavior};
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+// 中继连接的字节流
+pub type TurnStream = Compat<DuplexStream>;
+
+// 帧类型：拨号方发送 SYN，监听方回复 ACCEPT；DATA 和 FIN 带序号，ACK 带下一个期望的序号
+const SYN: u8 = 1;
+const ACCEPT: u8 = 2;
+const DATA: u8 = 3;
+const ACK: u8 = 4;
+const FIN: u8 = 5;
+const RESET: u8 = 6;
+
+// 帧头部：类型（1 字节）、连接 ID（4 字节）、序号（4 字节）
+const HEADER_SIZE: usize = 9;
+// 每帧最多携带的数据，加上 TURN 和 IP 头部后不超过常见的 1280 字节 MTU
+const MAX_PAYLOAD: usize = 1100;
+// 未确认的帧最多数量
+const WINDOW: u32 = 64;
+// 重传超时，每次重传翻倍，最长 MAX_RTO
+const INITIAL_RTO: Duration = Duration::from_millis(300);
+const MAX_RTO: Duration = Duration::from_secs(4);
+// 同一帧重传该次数后仍未确认时断开连接
+const MAX_RETRANSMISSIONS: u32 = 10;
+// 检查重传的间隔
+const RETRANSMIT_TICK: Duration = Duration::from_millis(50);
+// 没有收到任何帧的最长时间，yamux 和 Ping 会定期发送数据
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+// 拨号时等待 ACCEPT 的最长时间，期间每 HANDSHAKE_RETRY 重发一次 SYN
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const HANDSHAKE_RETRY: Duration = Duration::from_millis(500);
+// 应用与重传协议之间的缓冲区大小
+const STREAM_BUFFER: usize = 256 * 1024;
+// 刷新分配、权限和通道绑定的间隔，短于三者的有效期
+const REFRESH_INTERVAL: Duration = Duration::from_secs(120);
+// 监听任务中单个 TURN 事务的最长时间，避免服务器无响应时长时间阻塞转发
+const TURN_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 一个帧
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame {
+    kind: u8,
+    connection: u32,
+    number: u32,
+    payload: Vec<u8>,
+}
+
+impl Frame {
+    fn new(kind: u8, connection: u32, number: u32) -> Self {
+        Frame { kind, connection, number, payload: Vec::new() }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        bytes.push(self.kind);
+        bytes.extend_from_slice(&self.connection.to_be_bytes());
+        bytes.extend_from_slice(&self.number.to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_SIZE || !(SYN..=RESET).contains(&bytes[0]) {
+            return None;
+        }
+        Some(Frame {
+            kind: bytes[0],
+            connection: u32::from_be_bytes(bytes[1..5].try_into().ok()?),
+            number: u32::from_be_bytes(bytes[5..9].try_into().ok()?),
+            payload: bytes[HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+// 需要安装 TURN 权限的对端 IP，可克隆后在节点任务中使用；所有监听任务共享同一集合
+#[derive(Clone)]
+pub struct TurnPermissions {
+    peers: Arc<watch::Sender<PermissionSet>>,
+}
+
+// 调用方直接允许的 IP 一直保留；路由表中节点的 IP 随节点离开路由表一起移除
+#[derive(Debug, Clone, Default)]
+struct PermissionSet {
+    manual: HashSet<IpAddr>,
+    routing: HashMap<PeerId, HashSet<IpAddr>>,
+}
+
+impl PermissionSet {
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.manual.contains(ip) || self.routing.values().any(|ips| ips.contains(ip))
+    }
+
+    fn ips(&self) -> HashSet<IpAddr> {
+        self.manual.iter().chain(self.routing.values().flatten()).copied().collect()
+    }
+}
+
+impl Default for TurnPermissions {
+    fn default() -> Self {
+        let (peers, _) = watch::channel(PermissionSet::default());
+        TurnPermissions { peers: Arc::new(peers) }
+    }
+}
+
+impl TurnPermissions {
+    // 允许 ip 经中继连接本节点，返回是否为新加入的 IP
+    pub fn permit(&self, ip: IpAddr) -> bool {
+        self.peers.send_if_modified(|peers| {
+            let added = !peers.contains(&ip);
+            peers.manual.insert(ip);
+            added
+        })
+    }
+
+    // 路由表中的节点 peer 的公网 IP 变为 ips，替换之前记录的 IP
+    pub fn permit_peer(&self, peer: PeerId, ips: HashSet<IpAddr>) {
+        self.peers.send_if_modified(|peers| {
+            if ips.is_empty() {
+                return peers.routing.remove(&peer).is_some();
+            }
+            peers.routing.insert(peer, ips.clone()) != Some(ips)
+        });
+    }
+
+    // 节点离开了路由表，不再为它的 IP 刷新权限
+    pub fn revoke_peer(&self, peer: &PeerId) {
+        self.peers.send_if_modified(|peers| peers.routing.remove(peer).is_some());
+    }
+
+    pub fn permitted(&self) -> HashSet<IpAddr> {
+        self.peers.borrow().ips()
+    }
+}
+
+// 监听任务向传输层报告的事件
+enum ListenerEvent {
+    NewAddress(Multiaddr),
+    Incoming { stream: TurnStream, send_back_addr: Multiaddr },
+    // 不影响监听的错误，例如安装权限失败
+    Error(io::Error),
+    Closed(Result<(), io::Error>),
+}
+
+struct Listener {
+    events: mpsc::UnboundedReceiver<ListenerEvent>,
+    // 通知监听任务释放分配并退出
+    close: Option<oneshot::Sender<()>>,
+}
+
+// 本节点使用的 TURN 服务器
+struct RelayServer {
+    // host:port 和对应的监听地址
+    server: String,
+    listen_address: Multiaddr,
+    credentials: Option<StunCredentials>,
+}
+
+// TURN 中继传输层：所有节点都能拨号其它节点的中继地址，配置了 TURN 服务器的节点才能在中继地址上监听
+#[derive(Default)]
+pub struct TurnTransport {
+    relay: Option<RelayServer>,
+    permissions: TurnPermissions,
+    listeners: HashMap<ListenerId, Listener>,
+    pending_events: VecDeque<TransportEvent<Ready<Result<TurnStream, io::Error>>, io::Error>>,
+}
+
+impl TurnTransport {
+    // 只能拨号的传输层
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 使用 TURN 服务器（host:port）监听，credentials 为服务器要求的长期凭据
+    pub fn with_server(server: &str, credentials: Option<StunCredentials>) -> io::Result<Self> {
+        let listen_address = turn_listen_address(server)?;
+        let relay = RelayServer { server: server.to_string(), listen_address, credentials };
+        Ok(TurnTransport { relay: Some(relay), ..Self::default() })
+    }
+
+    // 在 Swarm 上调用 listen_on 该地址即向 TURN 服务器申请中继地址
+    pub fn listen_address(&self) -> Option<&Multiaddr> {
+        self.relay.as_ref().map(|relay| &relay.listen_address)
+    }
+
+    pub fn permissions(&self) -> TurnPermissions {
+        self.permissions.clone()
+    }
+}
+
+// TURN 服务器 host:port 对应的监听地址，例如 /ip4/192.0.2.1/udp/3478 或 /dns/turn.example.com/udp/3478
+pub fn turn_listen_address(server: &str) -> io::Result<Multiaddr> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("invalid TURN server {}, expected host:port", server));
+    let (host, port) = server.rsplit_once(':').ok_or_else(invalid)?;
+    let port: u16 = port.parse().map_err(|_| invalid())?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let protocol = match host.parse::<IpAddr>() {
+        Ok(ip) => Protocol::from(ip),
+        Err(_) if !host.is_empty() => Protocol::Dns(host.to_string().into()),
+        Err(_) => return Err(invalid()),
+    };
+    Ok(Multiaddr::empty().with(protocol).with(Protocol::Udp(port)))
+}
+
+// 是否为中继地址（/ip4|ip6/<addr>/udp/<port>，可以带 /p2p/），其它节点通过本传输层拨号
+pub fn is_relayed_address(addr: &Multiaddr) -> bool {
+    relayed_socket_addr(addr).is_some()
+}
+
+fn relayed_socket_addr(addr: &Multiaddr) -> Option<SocketAddr> {
+    let mut protocols = addr.iter();
+    let ip: IpAddr = match protocols.next()? {
+        Protocol::Ip4(ip) => ip.into(),
+        Protocol::Ip6(ip) => ip.into(),
+        _ => return None,
+    };
+    let Protocol::Udp(port) = protocols.next()? else { return None };
+    match protocols.next() {
+        None | Some(Protocol::P2p(_)) if protocols.next().is_none() => Some(SocketAddr::new(ip, port)),
+        _ => None,
+    }
+}
+
+impl Transport for TurnTransport {
+    type Output = TurnStream;
+    type Error = io::Error;
+    type ListenerUpgrade = Ready<Result<TurnStream, io::Error>>;
+    type Dial = BoxFuture<'static, Result<TurnStream, io::Error>>;
+
+    fn listen_on(&mut self, id: ListenerId, addr: Multiaddr) -> Result<(), TransportError<Self::Error>> {
+        let Some(relay) = self.relay.as_ref().filter(|relay| relay.listen_address == addr) else {
+            return Err(TransportError::MultiaddrNotSupported(addr));
+        };
+        let (events, receiver) = mpsc::unbounded_channel();
+        let (close, closed) = oneshot::channel();
+        let listener = RelayListener {
+            server: relay.server.clone(),
+            credentials: relay.credentials.clone(),
+            permissions: self.permissions.peers.subscribe(),
+            events,
+        };
+        tokio::spawn(listener.run(closed));
+        self.listeners.insert(id, Listener { events: receiver, close: Some(close) });
+        Ok(())
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        let Some(mut listener) = self.listeners.remove(&id) else { return false };
+        if let Some(close) = listener.close.take() {
+            let _ = close.send(());
+        }
+        self.pending_events.push_back(TransportEvent::ListenerClosed { listener_id: id, reason: Ok(()) });
+        true
+    }
+
+    fn dial(&mut self, addr: Multiaddr, _opts: DialOpts) -> Result<Self::Dial, TransportError<Self::Error>> {
+        match relayed_socket_addr(&addr) {
+            Some(remote) if !remote.ip().is_unspecified() && remote.port() != 0 => Ok(dial(remote).boxed()),
+            _ => Err(TransportError::MultiaddrNotSupported(addr)),
+        }
+    }
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        if let Some(event) = self.pending_events.pop_front() {
+            return Poll::Ready(event);
+        }
+        let listen_address = self.listen_address().cloned().unwrap_or_else(Multiaddr::empty);
+        let mut closed = None;
+        let mut ready = None;
+        for (id, listener) in self.listeners.iter_mut() {
+            let event = match listener.events.poll_recv(cx) {
+                Poll::Ready(Some(ListenerEvent::NewAddress(listen_addr))) => {
+                    TransportEvent::NewAddress { listener_id: *id, listen_addr }
+                }
+                Poll::Ready(Some(ListenerEvent::Incoming { stream, send_back_addr })) => TransportEvent::Incoming {
+                    listener_id: *id,
+                    upgrade: future::ready(Ok(stream)),
+                    local_addr: listen_address.clone(),
+                    send_back_addr,
+                },
+                Poll::Ready(Some(ListenerEvent::Error(error))) => TransportEvent::ListenerError { listener_id: *id, error },
+                Poll::Ready(Some(ListenerEvent::Closed(reason))) => {
+                    closed = Some(*id);
+                    TransportEvent::ListenerClosed { listener_id: *id, reason }
+                }
+                Poll::Ready(None) => {
+                    closed = Some(*id);
+                    TransportEvent::ListenerClosed { listener_id: *id, reason: Ok(()) }
+                }
+                Poll::Pending => continue,
+            };
+            ready = Some(event);
+            break;
+        }
+        if let Some(id) = closed {
+            self.listeners.remove(&id);
+        }
+        match ready {
+            Some(event) => Poll::Ready(event),
+            None => Poll::Pending,
+        }
+    }
+}
+
+// 直接向对端的中继地址拨号：发送 SYN 直到收到 ACCEPT
+async fn dial(remote: SocketAddr) -> io::Result<TurnStream> {
+    let bind_address: SocketAddr = if remote.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+    let socket = UdpSocket::bind(bind_address).await?;
+    socket.connect(remote).await?;
+
+    let connection: u32 = rand::random();
+    let syn = Frame::new(SYN, connection, 0).encode();
+    let mut buffer = vec![0; HEADER_SIZE + MAX_PAYLOAD];
+    let handshake = async {
+        loop {
+            socket.send(&syn).await?;
+            let deadline = Instant::now() + HANDSHAKE_RETRY;
+            while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv(&mut buffer)).await {
+                // 对端还没有为本机安装权限时 ICMP 错误也可能报告给套接字，继续重试
+                let Ok(len) = received else { continue };
+                match Frame::decode(&buffer[..len]) {
+                    Some(frame) if frame.connection == connection && frame.kind == ACCEPT => return Ok(()),
+                    Some(frame) if frame.connection == connection && frame.kind == RESET => {
+                        return Err(io::Error::new(io::ErrorKind::ConnectionRefused, "relayed connection refused"));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    };
+    match timeout(HANDSHAKE_TIMEOUT, handshake).await {
+        Ok(result) => result?,
+        Err(_) => return Err(io::Error::new(io::ErrorKind::TimedOut, "no answer from relayed address")),
+    }
+
+    let (inbound, inbound_rx) = mpsc::unbounded_channel();
+    let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<(SocketAddr, Vec<u8>)>();
+    let stream = spawn_session(connection, remote, inbound_rx, outbound);
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                received = socket.recv(&mut buffer) => {
+                    let Ok(len) = received else { continue };
+                    let Some(frame) = Frame::decode(&buffer[..len]) else { continue };
+                    if frame.connection == connection && inbound.send(frame).is_err() {
+                        break;
+                    }
+                }
+                sent = outbound_rx.recv() => match sent {
+                    Some((_, bytes)) => {
+                        let _ = socket.send(&bytes).await;
+                    }
+                    None => break,
+                },
+            }
+        }
+    });
+    Ok(stream)
+}
+
+// 监听任务：持有 TURN 分配，把收到的帧分发给各连接，把各连接的帧经服务器发出，并定期刷新分配、权限和通道
+struct RelayListener {
+    server: String,
+    credentials: Option<StunCredentials>,
+    permissions: watch::Receiver<PermissionSet>,
+    events: mpsc::UnboundedSender<ListenerEvent>,
+}
+
+impl RelayListener {
+    async fn run(mut self, mut closed: oneshot::Receiver<()>) {
+        let config = StunConfig { timeout: Some(TURN_TRANSACTION_TIMEOUT), ..StunConfig::default() };
+        let mut client = match TurnClient::allocate(&self.server, self.credentials.clone(), config).await {
+            Ok(client) => client,
+            Err(e) => {
+                let _ = self.events.send(ListenerEvent::Closed(Err(io::Error::other(e))));
+                return;
+            }
+        };
+        let relayed = client.allocation().relayed_address;
+        let _ = self.events.send(ListenerEvent::NewAddress(
+            Multiaddr::empty().with(Protocol::from(relayed.ip())).with(Protocol::Udp(relayed.port())),
+        ));
+
+        // 本次分配中已经安装的权限，集合变化时只为新的 IP 发送请求
+        let mut installed = self.permissions.borrow_and_update().ips();
+        self.install_permissions(&mut client, &installed).await;
+
+        // （对端地址，连接 ID）到连接任务
+        let mut sessions: HashMap<(SocketAddr, u32), mpsc::UnboundedSender<Frame>> = HashMap::new();
+        let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<(SocketAddr, Vec<u8>)>();
+        let mut refresh = interval(REFRESH_INTERVAL.min(PERMISSION_LIFETIME / 2).min(CHANNEL_LIFETIME / 2));
+        refresh.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        refresh.tick().await;
+
+        let reason = loop {
+            tokio::select! {
+                _ = &mut closed => {
+                    let _ = client.release().await;
+                    return;
+                }
+                received = client.recv_from() => {
+                    let (packet, peer) = match received {
+                        Ok(received) => received,
+                        Err(e) => break Err(io::Error::other(e)),
+                    };
+                    let Some(frame) = Frame::decode(&packet) else { continue };
+                    let key = (peer, frame.connection);
+                    if sessions.get(&key).is_some_and(|session| session.is_closed()) {
+                        sessions.remove(&key);
+                    }
+                    match (frame.kind, sessions.get(&key)) {
+                        // 重发的 SYN：对端还没有收到 ACCEPT
+                        (SYN, Some(_)) => {
+                            let _ = client.send_to(&Frame::new(ACCEPT, frame.connection, 0).encode(), peer).await;
+                        }
+                        (SYN, None) => {
+                            // 对端已经在通过中继发送数据，绑定通道后转发使用更短的头部；绑定失败时继续使用 Send 指示
+                            let _ = client.channel_bind(peer).await;
+                            let (inbound, inbound_rx) = mpsc::unbounded_channel();
+                            let stream = spawn_session(frame.connection, peer, inbound_rx, outbound.clone());
+                            sessions.insert(key, inbound);
+                            let send_back_addr = Multiaddr::empty().with(Protocol::from(peer.ip())).with(Protocol::Udp(peer.port()));
+                            if self.events.send(ListenerEvent::Incoming { stream, send_back_addr }).is_err() {
+                                return;
+                            }
+                            let _ = client.send_to(&Frame::new(ACCEPT, frame.connection, 0).encode(), peer).await;
+                        }
+                        (_, Some(session)) => {
+                            let _ = session.send(frame);
+                        }
+                        (RESET, None) => {}
+                        // 不认识的连接（例如本节点重启前的连接），告诉对端重新连接
+                        (_, None) => {
+                            let _ = client.send_to(&Frame::new(RESET, frame.connection, 0).encode(), peer).await;
+                        }
+                    }
+                }
+                Some((peer, bytes)) = outbound_rx.recv() => {
+                    let _ = client.send_to(&bytes, peer).await;
+                }
+                changed = self.permissions.changed() => {
+                    if changed.is_err() {
+                        continue;
+                    }
+                    let permitted = self.permissions.borrow_and_update().ips();
+                    let added: HashSet<IpAddr> = permitted.difference(&installed).copied().collect();
+                    self.install_permissions(&mut client, &added).await;
+                    // 已移除的 IP 不再刷新，到期后服务器不再转发它们的数据
+                    installed = permitted;
+                }
+                _ = refresh.tick() => {
+                    sessions.retain(|_, session| !session.is_closed());
+                    if let Err(e) = client.refresh(DEFAULT_ALLOCATION_LIFETIME).await {
+                        break Err(io::Error::other(e));
+                    }
+                    installed = self.permissions.borrow().ips();
+                    self.install_permissions(&mut client, &installed).await;
+                    let active: HashSet<SocketAddr> = sessions.keys().map(|(peer, _)| *peer).collect();
+                    for peer in client.channel_peers() {
+                        if active.contains(&peer) {
+                            let _ = client.channel_bind(peer).await;
+                        }
+                    }
+                }
+            }
+        };
+        let _ = self.events.send(ListenerEvent::Closed(reason));
+    }
+
+    // 安装或刷新 ips 的权限，失败时报告给节点，下次刷新时重试
+    async fn install_permissions(&self, client: &mut TurnClient, ips: &HashSet<IpAddr>) {
+        let ips: Vec<IpAddr> = ips.iter().copied().collect();
+        if let Err(e) = client.create_permission(&ips).await {
+            let _ = self.events.send(ListenerEvent::Error(io::Error::other(e)));
+        }
+    }
+}
+
+// 启动一个连接的重传任务，返回应用一侧的字节流
+// inbound 为收到的本连接的帧，发出的帧及其目的地址写入 outbound；任务结束时丢弃 inbound，转发方据此清理
+fn spawn_session(
+    connection: u32,
+    remote: SocketAddr,
+    inbound: mpsc::UnboundedReceiver<Frame>,
+    outbound: mpsc::UnboundedSender<(SocketAddr, Vec<u8>)>,
+) -> TurnStream {
+    let (application, pump) = tokio::io::duplex(STREAM_BUFFER);
+    let session = Session {
+        connection,
+        remote,
+        outbound,
+        next_seq: 0,
+        unacked: BTreeMap::new(),
+        fin_sent: false,
+        next_expected: 0,
+        out_of_order: BTreeMap::new(),
+        fin_received: false,
+    };
+    tokio::spawn(session.run(pump, inbound));
+    application.compat()
+}
+
+// 已发出但未确认的帧
+struct Segment {
+    frame: Frame,
+    sent_at: Instant,
+    retransmissions: u32,
+}
+
+impl Segment {
+    fn rto(&self) -> Duration {
+        (INITIAL_RTO * 2u32.saturating_pow(self.retransmissions)).min(MAX_RTO)
+    }
+}
+
+struct Session {
+    connection: u32,
+    remote: SocketAddr,
+    outbound: mpsc::UnboundedSender<(SocketAddr, Vec<u8>)>,
+    // 发送方向
+    next_seq: u32,
+    unacked: BTreeMap<u32, Segment>,
+    fin_sent: bool,
+    // 接收方向：下一个期望的序号和提前到达的帧
+    next_expected: u32,
+    out_of_order: BTreeMap<u32, Frame>,
+    fin_received: bool,
+}
+
+impl Session {
+    async fn run(mut self, pump: DuplexStream, mut inbound: mpsc::UnboundedReceiver<Frame>) {
+        let (mut reader, mut writer) = tokio::io::split(pump);
+        let mut buffer = vec![0; MAX_PAYLOAD];
+        let mut tick = interval(RETRANSMIT_TICK);
+        tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut last_heard = Instant::now();
+
+        loop {
+            // 双方都已发送 FIN 且本机的 FIN 已被确认，连接正常结束
+            if self.fin_sent && self.unacked.is_empty() && self.fin_received {
+                return;
+            }
+            let can_send = !self.fin_sent && (self.unacked.len() as u32) < WINDOW;
+            tokio::select! {
+                read = reader.read(&mut buffer), if can_send => {
+                    let frame = match read {
+                        Ok(len) if len > 0 => Frame { kind: DATA, connection: self.connection, number: self.next_seq, payload: buffer[..len].to_vec() },
+                        // 应用关闭了写方向
+                        _ => {
+                            self.fin_sent = true;
+                            Frame::new(FIN, self.connection, self.next_seq)
+                        }
+                    };
+                    self.next_seq = self.next_seq.wrapping_add(1);
+                    self.send(&frame);
+                    self.unacked.insert(frame.number, Segment { frame, sent_at: Instant::now(), retransmissions: 0 });
+                }
+                frame = inbound.recv() => {
+                    let Some(frame) = frame else { return };
+                    last_heard = Instant::now();
+                    match frame.kind {
+                        DATA | FIN => {
+                            self.receive(frame, &mut writer).await;
+                            self.send(&Frame::new(ACK, self.connection, self.next_expected));
+                        }
+                        ACK => {
+                            // 累积确认：number 之前的帧都已收到
+                            self.unacked = self.unacked.split_off(&frame.number);
+                        }
+                        RESET => return,
+                        _ => {}
+                    }
+                }
+                _ = tick.tick() => {
+                    if last_heard.elapsed() > IDLE_TIMEOUT {
+                        break;
+                    }
+                    let now = Instant::now();
+                    let mut expired = false;
+                    for segment in self.unacked.values_mut() {
+                        if now.duration_since(segment.sent_at) < segment.rto() {
+                            continue;
+                        }
+                        if segment.retransmissions >= MAX_RETRANSMISSIONS {
+                            expired = true;
+                            break;
+                        }
+                        segment.retransmissions += 1;
+                        segment.sent_at = now;
+                        let _ = self.outbound.send((self.remote, segment.frame.encode()));
+                    }
+                    if expired {
+                        break;
+                    }
+                }
+            }
+        }
+        // 对端长时间无响应，通知对端放弃连接；丢弃字节流后应用读到 EOF
+        self.send(&Frame::new(RESET, self.connection, 0));
+    }
+
+    fn send(&self, frame: &Frame) {
+        let _ = self.outbound.send((self.remote, frame.encode()));
+    }
+
+    // 按序交付收到的数据，FIN 交付时关闭应用的读方向；窗口之外和重复的帧丢弃
+    async fn receive(&mut self, frame: Frame, writer: &mut tokio::io::WriteHalf<DuplexStream>) {
+        let offset = frame.number.wrapping_sub(self.next_expected);
+        if offset >= WINDOW {
+            return;
+        }
+        self.out_of_order.entry(frame.number).or_insert(frame);
+        while let Some(frame) = self.out_of_order.remove(&self.next_expected) {
+            self.next_expected = self.next_expected.wrapping_add(1);
+            if frame.kind == FIN {
+                self.fin_received = true;
+                let _ = writer.shutdown().await;
+            } else if !self.fin_received {
+                // 应用已经丢弃字节流时写入失败，继续确认以便对端正常结束
+                let _ = writer.write_all(&frame.payload).await;
+            }
+        }
+    }
+}
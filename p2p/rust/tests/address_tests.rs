@@ -0,0 +1,113 @@
+// 地址分类与拨号顺序测试
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use p2p::address::{is_global, is_global_ipv6_addr, rank_addresses, socket_addr, transport_kind, TransportKind};
+use p2p::relay;
+
+fn addrs(list: &[&str]) -> Vec<Multiaddr> {
+    list.iter().map(|addr| addr.parse().unwrap()).collect()
+}
+
+#[test]
+fn test_global_addresses_are_classified() {
+    for global in ["8.8.8.8", "2001:4860:4860::8888", "2604:a880:1:20::203:d001"] {
+        assert!(is_global(&global.parse().unwrap()), "{} should be global", global);
+    }
+    for local in ["10.0.0.1", "192.168.1.1", "100.64.0.1", "127.0.0.1", "169.254.1.1", "::1", "fe80::1", "fd00::1", "::"] {
+        assert!(!is_global(&local.parse().unwrap()), "{} should not be global", local);
+    }
+    assert!(is_global_ipv6_addr(&"/ip6/2001:db8::1/tcp/4001".parse().unwrap()));
+    assert!(!is_global_ipv6_addr(&"/ip4/8.8.8.8/tcp/4001".parse().unwrap()));
+    assert!(!is_global_ipv6_addr(&"/dns4/example.com/tcp/4001".parse().unwrap()));
+}
+
+#[test]
+fn test_socket_addr_from_multiaddr() {
+    assert_eq!(socket_addr(&"/ip6/::1/tcp/4001/p2p/QmSoLPppuBtQSGwKDZT2M73ULpjvfd3aZ6ha4oFGL1KrGM".parse().unwrap()), Some("[::1]:4001".parse().unwrap()));
+    assert_eq!(socket_addr(&"/ip4/1.2.3.4/udp/3478".parse().unwrap()), Some("1.2.3.4:3478".parse().unwrap()));
+    assert_eq!(socket_addr(&"/dns4/example.com/tcp/4001".parse().unwrap()), None);
+}
+
+#[test]
+fn test_ipv6_is_preferred_when_both_sides_have_it() {
+    let mut addresses = addrs(&[
+        "/ip4/192.168.1.5/tcp/4001",
+        "/ip4/104.131.131.82/tcp/4001",
+        "/ip6/2604:a880:1:20::203:d001/tcp/4001",
+        "/dns4/bootstrap.example.com/tcp/4001",
+        "/ip6/::1/tcp/4001",
+    ]);
+
+    rank_addresses(&mut addresses, true);
+    assert_eq!(
+        addresses,
+        addrs(&[
+            "/ip6/2604:a880:1:20::203:d001/tcp/4001",
+            "/ip4/104.131.131.82/tcp/4001",
+            "/dns4/bootstrap.example.com/tcp/4001",
+            "/ip4/192.168.1.5/tcp/4001",
+            "/ip6/::1/tcp/4001",
+        ])
+    );
+
+    // 本机没有公网 IPv6 时 IPv6 地址排在公网 IPv4 之后
+    rank_addresses(&mut addresses, false);
+    assert_eq!(addresses[0].to_string(), "/ip4/104.131.131.82/tcp/4001");
+    assert_eq!(addresses[2].to_string(), "/ip6/2604:a880:1:20::203:d001/tcp/4001");
+}
+
+#[test]
+fn test_quic_is_preferred_within_the_same_rank() {
+    let mut addresses = addrs(&[
+        "/ip4/104.131.131.82/tcp/4001",
+        "/ip4/192.168.1.5/udp/4001/quic-v1",
+        "/ip4/104.131.131.82/udp/4001/quic-v1",
+        "/ip4/203.0.113.9/udp/3478",
+    ]);
+
+    rank_addresses(&mut addresses, false);
+    assert_eq!(
+        addresses,
+        addrs(&[
+            "/ip4/104.131.131.82/udp/4001/quic-v1",
+            "/ip4/104.131.131.82/tcp/4001",
+            "/ip4/192.168.1.5/udp/4001/quic-v1",
+            "/ip4/203.0.113.9/udp/3478",
+        ])
+    );
+}
+
+#[test]
+fn test_transport_kind_of_address() {
+    let kind = |addr: &str| transport_kind(&addr.parse().unwrap());
+    assert_eq!(kind("/ip4/1.2.3.4/tcp/4001/p2p/QmSoLPppuBtQSGwKDZT2M73ULpjvfd3aZ6ha4oFGL1KrGM"), TransportKind::Tcp);
+    assert_eq!(kind("/ip6/2001:db8::1/udp/4001/quic-v1"), TransportKind::Quic);
+    assert_eq!(kind("/ip4/1.2.3.4/udp/3478"), TransportKind::Relayed);
+    assert_eq!(kind("/dns4/example.com/tcp/4001"), TransportKind::Tcp);
+    assert_eq!(kind("/ip4/1.2.3.4/udp/4001/webrtc-direct"), TransportKind::Other);
+    assert_eq!(
+        kind("/ip4/1.2.3.4/tcp/4001/p2p/QmSoLPppuBtQSGwKDZT2M73ULpjvfd3aZ6ha4oFGL1KrGM/p2p-circuit"),
+        TransportKind::Relayed
+    );
+    assert_eq!(TransportKind::Quic.to_string(), "quic");
+}
+
+#[test]
+fn test_circuit_addresses() {
+    let relay: PeerId = "QmSoLPppuBtQSGwKDZT2M73ULpjvfd3aZ6ha4oFGL1KrGM".parse().unwrap();
+    let relay_addr: Multiaddr = format!("/ip4/1.2.3.4/tcp/4001/p2p/{}", relay).parse().unwrap();
+    let listen = relay::circuit_listen_address(&relay_addr, relay);
+    assert_eq!(listen.to_string(), format!("/ip4/1.2.3.4/tcp/4001/p2p/{}/p2p-circuit", relay));
+    assert!(relay::is_circuit_address(&listen));
+    assert_eq!(relay::circuit_relay_peer(&listen), Some(relay));
+    // 中继地址之后还可以带目标节点
+    let target = PeerId::random();
+    let dial = listen.with(Protocol::P2p(target));
+    assert_eq!(relay::circuit_relay_peer(&dial), Some(relay));
+    assert!(!relay::is_circuit_address(&relay_addr));
+    assert_eq!(relay::circuit_relay_peer(&relay_addr), None);
+    // 排序时中继地址排在直接地址之后
+    let mut ranked = vec![dial.clone(), relay_addr.clone()];
+    rank_addresses(&mut ranked, false);
+    assert_eq!(ranked, vec![relay_addr, dial]);
+}
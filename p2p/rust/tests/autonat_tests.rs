@@ -0,0 +1,36 @@
+// AutoNAT 可达性结论测试
+use libp2p::kad::Mode;
+use p2p::autonat::{Reachability, ReachabilityStatus, MIN_CONFIDENCE};
+
+fn status(reachability: Reachability, confidence: usize) -> ReachabilityStatus {
+    ReachabilityStatus { reachability, confidence, address: None }
+}
+
+#[test]
+fn test_kademlia_mode_follows_confirmed_reachability() {
+    // 状态刚翻转时置信度为 0，得到后续确认前不切换
+    assert_eq!(status(Reachability::Public, 0).kademlia_mode(), None);
+    assert_eq!(status(Reachability::Public, MIN_CONFIDENCE).kademlia_mode(), Some(Mode::Server));
+    assert_eq!(status(Reachability::Private, MIN_CONFIDENCE + 1).kademlia_mode(), Some(Mode::Client));
+    assert_eq!(status(Reachability::Unknown, 3).kademlia_mode(), None);
+    assert_eq!(ReachabilityStatus::default().kademlia_mode(), None);
+
+    assert!(status(Reachability::Public, MIN_CONFIDENCE).is_public());
+    assert!(!status(Reachability::Public, 0).is_public());
+    assert!(!status(Reachability::Private, 3).is_public());
+}
+
+#[test]
+fn test_reachability_status_output() {
+    let public = ReachabilityStatus {
+        reachability: Reachability::Public,
+        confidence: 2,
+        address: Some("/ip4/203.0.113.7/tcp/4001".to_string()),
+    };
+    assert_eq!(public.to_string(), "public (confidence 2) at /ip4/203.0.113.7/tcp/4001");
+    assert_eq!(ReachabilityStatus::default().to_string(), "unknown (confidence 0)");
+
+    let json = serde_json::to_value(&public).unwrap();
+    assert_eq!(json["reachability"], "public");
+    assert_eq!(json["confidence"], 2);
+}
@@ -0,0 +1,196 @@
+// Bootstrap 节点列表加载与合并测试
+use libp2p::{Multiaddr, PeerId};
+use chrono::Utc;
+use p2p::bootstrap::{self, BootstrapList, BootstrapNode, NodeStatus, BOOTSTRAPS_SCHEMA_VERSION};
+use p2p::error::Error;
+use std::fs;
+use std::time::Duration;
+
+fn node(addr: &str, peer_id: &PeerId) -> BootstrapNode {
+    let addr: Multiaddr = addr.parse().unwrap();
+    BootstrapNode::new(&addr, peer_id)
+}
+
+#[test]
+fn test_merge_prefers_saved_statistics_and_dedupes() {
+    let seed_peer = PeerId::random();
+    let learned_peer = PeerId::random();
+
+    let seeds = vec![
+        node(&format!("/ip4/1.2.3.4/tcp/4001/p2p/{}", seed_peer), &seed_peer),
+        node(&format!("/ip4/1.2.3.4/tcp/4001/p2p/{}", seed_peer), &seed_peer),
+    ];
+
+    // 保存的记录可能不带 /p2p/ 后缀，仍应与种子节点视为同一条
+    let mut saved_seed = node("/ip4/1.2.3.4/tcp/4001", &seed_peer);
+    saved_seed.success_count = 3;
+    let saved = vec![
+        saved_seed,
+        node("/ip4/5.6.7.8/tcp/4001", &learned_peer),
+        node("/ip4/5.6.7.8/tcp/4001", &learned_peer),
+        BootstrapNode { peer_id: "not-a-peer-id".to_string(), ..node("/ip4/9.9.9.9/tcp/1", &learned_peer) },
+    ];
+
+    let merged = bootstrap::merge_bootstrap_nodes(seeds, saved);
+    assert_eq!(merged.len(), 2);
+    assert_eq!(merged[0].peer_id, seed_peer.to_string());
+    assert_eq!(merged[0].success_count, 3);
+    assert_eq!(merged[1].peer_id, learned_peer.to_string());
+    // 带和不带 /p2p/ 后缀的同一地址只保留一个
+    assert_eq!(merged[0].addresses, vec!["/ip4/1.2.3.4/tcp/4001".to_string()]);
+}
+
+#[test]
+fn test_load_migrates_unversioned_file() {
+    let dir = std::env::temp_dir().join(format!("p2p_bootstrap_{}", rand::random::<u32>()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("BOOTSTRAPS.json");
+
+    // 版本 0：没有 version 字段，同一节点的两个地址是两条记录
+    let peer_id = PeerId::random();
+    let legacy = format!(
+        r#"{{
+  "nodes": [
+    {{"address": "/ip4/1.2.3.4/tcp/4001", "peer_id": "{peer}", "status": "active",
+      "last_seen": "2025-08-19T15:12:55.203680800+00:00", "response_time": 35,
+      "success_count": 4, "failure_count": 1}},
+    {{"address": "/ip6/::1/tcp/4001", "peer_id": "{peer}", "status": "unknown",
+      "last_seen": null, "response_time": null, "success_count": 0, "failure_count": 0}}
+  ],
+  "last_updated": "2025-08-19T15:12:55.203680800+00:00"
+}}"#,
+        peer = peer_id
+    );
+    fs::write(&path, legacy).unwrap();
+
+    let loaded = bootstrap::load_bootstrap_nodes_from_path(&path).unwrap();
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].status, NodeStatus::Active);
+    assert_eq!(loaded[0].success_count, 4);
+    assert_eq!(loaded[0].addresses, vec!["/ip4/1.2.3.4/tcp/4001", "/ip6/::1/tcp/4001"]);
+    assert_eq!(loaded[0].last_seen.unwrap().to_rfc3339(), "2025-08-19T15:12:55.203680800+00:00");
+
+    // 比当前版本新的文件不能被静默读取
+    fs::write(&path, r#"{"version": 99, "nodes": [], "last_updated": "2025-08-19T15:12:55Z"}"#).unwrap();
+    let err = bootstrap::load_bootstrap_nodes_from_path(&path).unwrap_err();
+    assert!(matches!(err, Error::UnsupportedVersion { version: 99, .. }), "unexpected error {:?}", err);
+    fs::write(&path, "not json").unwrap();
+    assert!(matches!(bootstrap::load_bootstrap_nodes_from_path(&path), Err(Error::Json { .. })));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_load_bootstrap_nodes_from_path() {
+    let dir = std::env::temp_dir().join(format!("p2p_bootstrap_{}", rand::random::<u32>()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("BOOTSTRAPS.json");
+
+    // 文件不存在时返回空列表
+    assert!(bootstrap::load_bootstrap_nodes_from_path(&path).unwrap().is_empty());
+
+    let peer_id = PeerId::random();
+    let list = BootstrapList {
+        version: BOOTSTRAPS_SCHEMA_VERSION,
+        nodes: vec![node("/ip4/1.2.3.4/tcp/4001", &peer_id)],
+        last_updated: Utc::now(),
+    };
+    fs::write(&path, serde_json::to_string(&list).unwrap()).unwrap();
+    let loaded = bootstrap::load_bootstrap_nodes_from_path(&path).unwrap();
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].peer_id, peer_id.to_string());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_ranking_prefers_reliable_fast_recent_nodes() {
+    let good_peer = PeerId::random();
+    let bad_peer = PeerId::random();
+    let fresh_peer = PeerId::random();
+
+    let mut nodes = vec![
+        node("/ip4/1.1.1.1/tcp/4001", &bad_peer),
+        node("/ip4/2.2.2.2/tcp/4001", &fresh_peer),
+        node("/ip4/3.3.3.3/tcp/4001", &good_peer),
+    ];
+    let (bad, good) = (bad_peer.to_string(), good_peer.to_string());
+    for _ in 0..3 {
+        bootstrap::update_bootstrap_node_status(&mut nodes, &bad, NodeStatus::Inactive);
+        bootstrap::update_bootstrap_node_status(&mut nodes, &good, NodeStatus::Active);
+    }
+    bootstrap::update_bootstrap_node_response_time(&mut nodes, &good, Duration::from_millis(40));
+    assert_eq!(nodes[2].response_time, Some(40));
+
+    bootstrap::rank_bootstrap_nodes(&mut nodes);
+    let order: Vec<_> = nodes.iter().map(|n| n.peer_id.clone()).collect();
+    assert_eq!(order, vec![good.clone(), fresh_peer.to_string(), bad.clone()]);
+    assert!(nodes[0].score > nodes[1].score && nodes[1].score > nodes[2].score);
+
+    // 连续失败达到阈值后被移除，成功一次会清零连续失败计数
+    let dead = bootstrap::prune_dead_bootstrap_nodes(&mut nodes, 3);
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].peer_id, bad);
+    bootstrap::update_bootstrap_node_status(&mut nodes, &good, NodeStatus::Inactive);
+    bootstrap::update_bootstrap_node_status(&mut nodes, &good, NodeStatus::Active);
+    assert_eq!(nodes[0].consecutive_failures, 0);
+}
+
+#[test]
+fn test_save_replaces_file_atomically_in_configured_path() {
+    let dir = std::env::temp_dir().join(format!("p2p_bootstrap_{}", rand::random::<u32>()));
+    // 输出目录不存在时自动创建
+    let path = dir.join("state").join("BOOTSTRAPS.json");
+
+    let peer_id = PeerId::random();
+    let nodes = vec![node("/ip4/1.2.3.4/tcp/4001", &peer_id)];
+    bootstrap::save_bootstrap_nodes_to_path(&nodes, &path).unwrap();
+    bootstrap::save_bootstrap_nodes_to_path(&nodes[..0], &path).unwrap();
+    assert!(bootstrap::load_bootstrap_nodes_from_path(&path).unwrap().is_empty());
+
+    // 只留下目标文件，没有残留的临时文件和锁文件
+    let mut names: Vec<String> = fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["BOOTSTRAPS.json"]);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_identify_versions_are_recorded() {
+    let peer_id = PeerId::random();
+    let mut nodes = vec![node("/ip4/1.2.3.4/tcp/4001", &peer_id)];
+    let protocols = vec!["/ipfs/kad/1.0.0".to_string(), "/ipfs/ping/1.0.0".to_string()];
+    bootstrap::update_bootstrap_node_versions(&mut nodes, &peer_id.to_string(), "kubo/0.30.0", "ipfs/0.1.0", &protocols);
+    assert_eq!(nodes[0].agent_version.as_deref(), Some("kubo/0.30.0"));
+    assert_eq!(nodes[0].protocol_version.as_deref(), Some("ipfs/0.1.0"));
+    assert_eq!(nodes[0].protocols, protocols);
+
+    // 旧的路由表快照没有版本字段
+    let entry: bootstrap::RoutingTableEntry =
+        serde_json::from_str(&format!(r#"{{"peer_id":"{}","addresses":[]}}"#, peer_id)).unwrap();
+    assert_eq!(entry.agent_version, None);
+    assert!(!serde_json::to_string(&entry).unwrap().contains("agent_version"));
+}
+
+#[test]
+fn test_ping_success_does_not_count_as_connection_success() {
+    let peer_id = PeerId::random();
+    let mut nodes = vec![node("/ip4/1.2.3.4/tcp/4001", &peer_id)];
+    let id = peer_id.to_string();
+
+    bootstrap::update_bootstrap_node_status(&mut nodes, &id, NodeStatus::Active);
+    bootstrap::update_bootstrap_node_status(&mut nodes, &id, NodeStatus::Inactive);
+    // 周期性 Ping 成功只刷新活动时间和连续失败计数
+    for _ in 0..5 {
+        bootstrap::touch_bootstrap_node(&mut nodes, &id);
+    }
+    assert_eq!(nodes[0].success_count, 1);
+    assert_eq!(nodes[0].failure_count, 1);
+    assert_eq!(nodes[0].consecutive_failures, 0);
+    assert_eq!(nodes[0].status, NodeStatus::Active);
+    assert!(nodes[0].last_seen.is_some());
+}
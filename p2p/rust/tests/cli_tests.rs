@@ -0,0 +1,66 @@
+// 命令行解析测试
+use clap::Parser;
+use p2p::cli::{BootstrapCommand, Cli, Command, Role};
+use p2p::commands::nat_test;
+use p2p::config::{Config, StunSource};
+use p2p::output::OutputFormat;
+use std::path::PathBuf;
+
+#[test]
+fn test_subcommands_and_named_flags() {
+    let cli = Cli::try_parse_from(["p2p", "nat-test", "initiator", "--room", "room1", "--format", "json"]).unwrap();
+    assert_eq!(cli.format, OutputFormat::Json);
+    match cli.command {
+        Command::NatTest { role, room } => {
+            assert_eq!(role, Role::Initiator);
+            assert_eq!(room, "room1");
+        }
+        other => panic!("unexpected command {:?}", other),
+    }
+
+    let cli = Cli::try_parse_from(["p2p", "bootstrap", "prune", "--max-failures", "3", "--dry-run"]).unwrap();
+    assert!(matches!(cli.command, Command::Bootstrap { command: BootstrapCommand::Prune { max_failures: 3, dry_run: true } }));
+
+    assert!(Cli::try_parse_from(["p2p", "nat-test", "observer"]).is_err());
+    assert!(Cli::try_parse_from(["p2p", "dht", "put", "only_key"]).is_err());
+}
+
+#[test]
+fn test_config_flags_override_defaults() {
+    // 同一参数出现多次时以最后一次为准
+    let cli = Cli::try_parse_from([
+        "p2p", "nat-test", "responder", "--max-runtime-minutes", "5", "--kademlia-mode", "client",
+        "--max-runtime-minutes", "1",
+    ])
+    .unwrap();
+    let config = cli.config.load(nat_test::default_config()).unwrap();
+    assert_eq!(config.max_runtime_minutes, 1);
+    assert_eq!(config.max_connection_attempts, nat_test::default_config().max_connection_attempts);
+
+    // 值的类型和范围由 clap 检查
+    assert!(Cli::try_parse_from(["p2p", "stun", "--stun-servers", "no-port"]).is_err());
+    assert!(Cli::try_parse_from(["p2p", "run", "--max-runtime-minutes", "0"]).is_err());
+    assert!(Cli::try_parse_from(["p2p", "run", "--kademlia-mode", "dht"]).is_err());
+    assert!(Cli::try_parse_from(["p2p", "run", "--listen-addrs", "not-an-addr"]).is_err());
+    assert!(Cli::try_parse_from(["p2p", "run", "--relay-server", "yes"]).is_err());
+}
+
+#[test]
+fn test_typed_config_flags_build_config() {
+    let cli = Cli::try_parse_from([
+        "p2p", "run", "--listen-addrs", "/ip4/127.0.0.1/tcp/4001, /ip4/127.0.0.1/udp/4001/quic-v1", "--stun-servers=",
+        "--stun-source", "peers", "--relay-reservations", "2", "--data-dir", "/var/lib/p2p",
+    ])
+    .unwrap();
+    let mut config = Config::default();
+    cli.config.apply(&mut config);
+    assert_eq!(config.listen_addrs, vec!["/ip4/127.0.0.1/tcp/4001", "/ip4/127.0.0.1/udp/4001/quic-v1"]);
+    // 空值表示空列表
+    assert!(config.stun_servers.is_empty());
+    assert_eq!(config.stun_source, StunSource::Peers);
+    assert_eq!(config.relay_reservations, 2);
+    assert_eq!(config.data_dir, PathBuf::from("/var/lib/p2p"));
+    // 命令行中没有出现的配置项保持不变
+    assert_eq!(config.ping_interval_secs, Config::default().ping_interval_secs);
+    assert!(config.validate().is_ok());
+}
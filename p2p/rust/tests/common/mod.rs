@@ -0,0 +1,57 @@
+// 集成测试共用的 STUN 配置和本地 UDP 服务器
+#![allow(dead_code)]
+
+use bytecodec::DecodeExt;
+use p2p::stun::StunConfig;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use stun_codec::rfc5389::attributes::Fingerprint;
+use stun_codec::rfc5389::Attribute;
+use stun_codec::{Message, MessageDecoder};
+use tokio::net::UdpSocket;
+
+// 短重传间隔，测试不需要等待 RFC 默认的 39.5 秒
+pub fn fast_config() -> StunConfig {
+    StunConfig { rto: Duration::from_millis(50), max_requests: 3, last_wait_factor: 4, timeout: None }
+}
+
+// 在 sockets[index] 上应答 STUN 请求：respond 收到解码后的请求、原始报文和来源地址，
+// 返回发送响应的套接字序号和响应报文，返回 None 时不回复
+// 请求按 RFC 5389 的属性解码，其它属性保留原始值，需要时由 respond 从原始报文中解码
+pub fn serve_stun<F>(sockets: Arc<Vec<UdpSocket>>, index: usize, mut respond: F)
+where
+    F: FnMut(&Message<Attribute>, &[u8], SocketAddr) -> Option<(usize, Vec<u8>)> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut buffer = [0; 1500];
+        while let Ok((len, from)) = sockets[index].recv_from(&mut buffer).await {
+            let packet = &buffer[..len];
+            let request = MessageDecoder::<Attribute>::new().decode_from_bytes(packet).unwrap().unwrap();
+            // 客户端请求应带有 FINGERPRINT
+            assert!(request.get_attribute::<Fingerprint>().is_some());
+            if let Some((source, reply)) = respond(&request, packet, from) {
+                sockets[source].send_to(&reply, from).await.unwrap();
+            }
+        }
+    });
+}
+
+// 启动一个单套接字的本地服务器，响应从收到请求的套接字发出，返回服务器地址
+pub async fn spawn_stun_server<F>(mut respond: F) -> String
+where
+    F: FnMut(&Message<Attribute>, &[u8], SocketAddr) -> Option<Vec<u8>> + Send + 'static,
+{
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap().to_string();
+    serve_stun(Arc::new(vec![socket]), 0, move |request, packet, from| {
+        respond(request, packet, from).map(|reply| (0, reply))
+    });
+    address
+}
+
+// 绑定后立即关闭的本地端口，发往它的请求没有应答
+pub async fn closed_port() -> String {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.local_addr().unwrap().to_string()
+}
@@ -0,0 +1,136 @@
+// 配置加载与校验测试
+use p2p::config::{Config, ConfigError, KademliaMode, StunSource};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+fn temp_config(name: &str, content: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("p2p_config_{}_{}", name, rand::random::<u32>()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("p2p.toml");
+    fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn test_file_env_and_cli_override_in_order() {
+    let path = temp_config(
+        "precedence",
+        "max_runtime_minutes = 5\nping_interval_secs = 3\nkademlia_mode = \"client\"\nlisten_addrs = [\"/ip4/127.0.0.1/tcp/4001\"]\n",
+    );
+    let cli = args(&["initiator", "--config", path.to_str().unwrap(), "--ping-interval-secs=7", "room1"]);
+    let env = |name: &str| match name {
+        "P2P_MAX_RUNTIME_MINUTES" => Some("8".to_string()),
+        "P2P_PING_INTERVAL_SECS" => Some("6".to_string()),
+        _ => None,
+    };
+
+    let (config, positional) = Config::load_from(Config::default(), &cli, env).unwrap();
+    assert_eq!(positional, args(&["initiator", "room1"]));
+    assert_eq!(config.max_runtime_minutes, 8); // 环境变量覆盖文件
+    assert_eq!(config.ping_interval_secs, 7); // 命令行覆盖环境变量
+    assert_eq!(config.kademlia_mode, KademliaMode::Client); // 文件覆盖默认值
+    assert_eq!(config.listen_addrs, args(&["/ip4/127.0.0.1/tcp/4001"]));
+    // 文件中没有出现的配置项保留默认值
+    assert_eq!(config.max_connection_attempts, Config::default().max_connection_attempts);
+
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+}
+
+#[test]
+fn test_invalid_values_are_reported() {
+    let no_env = |_: &str| None;
+
+    let err = Config::load_from(Config::default(), &args(&["--max-runtime-minutes", "0"]), no_env).unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "max_runtime_minutes"));
+    let err = Config::load_from(Config::default(), &args(&["--max-runtime-minutes", &u64::MAX.to_string()]), no_env).unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "max_runtime_minutes"));
+    assert_eq!(Config { max_runtime_minutes: u64::MAX, ..Config::default() }.max_runtime(), Duration::from_secs(u64::MAX));
+
+    let err = Config::load_from(Config::default(), &args(&["--listen-addrs", "not-an-addr"]), no_env).unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "listen_addrs"));
+
+    let err = Config::load_from(Config::default(), &args(&["--stun-servers", "stun.example.com"]), no_env).unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "stun_servers"));
+
+    let err = Config::load_from(Config::default(), &args(&["--stun-source", "dht"]), no_env).unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "stun_source"));
+
+    // 只询问节点时可以不配置 STUN 服务器
+    let (config, _) = Config::load_from(Config::default(), &args(&["--stun-source=peers", "--stun-servers="]), no_env).unwrap();
+    assert_eq!(config.stun_source, StunSource::Peers);
+
+    let err = Config::load_from(Config::default(), &args(&["--turn-server", "turn.example.com"]), no_env).unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "turn_server"));
+
+    // TURN 用户名和密码必须同时设置
+    let err = Config::load_from(Config::default(), &args(&["--turn-server", "turn.example.com:3478", "--turn-username", "alice"]), no_env).unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "turn_password"));
+
+    // auto 模式按 AutoNAT 的结论切换，未编译 AutoNAT 时不可用
+    let result = Config::load_from(Config::default(), &args(&["--kademlia-mode", "auto"]), no_env);
+    assert_eq!(result.is_ok(), p2p::autonat::is_supported());
+
+    let err = Config::load_from(Config::default(), &args(&["--no-such-key", "1"]), no_env).unwrap_err();
+    assert!(matches!(err, ConfigError::UnknownKey { .. }));
+
+    let err = Config::load_from(Config::default(), &args(&["--ping-interval-secs"]), no_env).unwrap_err();
+    assert!(matches!(err, ConfigError::MissingValue(_)));
+
+    let path = temp_config("bad_type", "ping_interval_secs = \"often\"\n");
+    let err = Config::load_from(Config::default(), &args(&["--config", path.to_str().unwrap()]), no_env).unwrap_err();
+    assert!(matches!(err, ConfigError::Parse { .. }));
+    assert!(err.to_string().contains("p2p.toml"));
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+}
+
+#[test]
+fn test_output_paths_resolve_against_data_dir() {
+    let env = |name: &str| match name {
+        "P2P_DATA_DIR" => Some("/var/lib/p2p".to_string()),
+        _ => None,
+    };
+    let cli = args(&["--report-file", "/tmp/report.txt"]);
+    let (config, _) = Config::load_from(Config::default(), &cli, env).unwrap();
+    assert_eq!(config.bootstraps_path(), PathBuf::from("/var/lib/p2p/BOOTSTRAPS.json"));
+    assert_eq!(config.routing_table_path(), PathBuf::from("/var/lib/p2p/ROUTING_TABLE.json"));
+    // 绝对路径不受 data_dir 影响
+    assert_eq!(config.report_path(), PathBuf::from("/tmp/report.txt"));
+
+    let err = Config::load_from(Config::default(), &args(&["--bootstraps-file="]), |_| None).unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "bootstraps_file"));
+}
+
+#[test]
+fn test_relay_server_config() {
+    let peer = libp2p::PeerId::random();
+    let cli = args(&[
+        "--relay-max-circuits=4",
+        "--relay-max-circuit-bytes=1048576",
+        "--relay-max-circuit-duration-secs=600",
+        &format!("--relay-allowlist={}", peer),
+    ]);
+    let (config, _) = Config::load_from(Config::default(), &cli, |_| None).unwrap();
+    let relay = config.relay_server_config();
+    assert_eq!(relay.max_reservations, 128);
+    assert_eq!(relay.max_circuits, 4);
+    assert_eq!(relay.max_circuit_bytes, 1 << 20);
+    assert_eq!(relay.max_circuit_duration, std::time::Duration::from_secs(600));
+    assert!(relay.allows(&peer));
+    assert!(!relay.allows(&libp2p::PeerId::random()));
+    // 允许列表为空时不限制
+    assert!(Config::default().relay_server_config().allows(&peer));
+
+    let err = Config::load_from(Config::default(), &args(&["--relay-allowlist", "not-a-peer"]), |_| None).unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "relay_allowlist"));
+    let err = Config::load_from(Config::default(), &args(&["--relay-max-circuit-duration-secs=0"]), |_| None).unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "relay_max_circuit_duration_secs"));
+
+    // 未编译中继支持时不能开启中继服务端
+    let result = Config::load_from(Config::default(), &args(&["--relay-server=true"]), |_| None);
+    assert_eq!(result.is_ok(), p2p::relay::is_supported());
+}
@@ -0,0 +1,60 @@
+// 错误类型测试：调用方通过变体区分失败原因
+use libp2p::futures::StreamExt;
+use libp2p::{Multiaddr, PeerId};
+use p2p::error::Error;
+use p2p::node::{NodeBuilder, NodeEvent};
+use p2p::stun::{binding_request, StunConfig};
+use std::net::TcpListener;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn test_stun_garbage_response_is_decode_error() {
+    // 本地 UDP 服务器对任何请求回复无效数据
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = server.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        let mut buffer = [0; 1024];
+        if let Ok((_, from)) = server.recv_from(&mut buffer).await {
+            let _ = server.send_to(b"not a stun message", from).await;
+        }
+    });
+
+    // 无效响应被丢弃，重传结束后报告解码失败而不是超时
+    let config = StunConfig { rto: Duration::from_millis(20), max_requests: 2, last_wait_factor: 2, timeout: None };
+    let err = binding_request(&server_addr, &config).await.unwrap_err();
+    assert!(matches!(&err, Error::StunDecode { server, .. } if *server == server_addr), "unexpected error {:?}", err);
+    assert!(err.is_stun_binding_failure());
+    assert!(!err.is_timeout());
+
+    let err = Error::StunFailed(vec![err]);
+    assert!(err.is_stun_binding_failure());
+}
+
+#[tokio::test]
+async fn test_dial_closed_port_reports_connection_refused() {
+    // 绑定后立即释放端口，保证没有进程在监听
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let peer_id = PeerId::random();
+    let addr: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}/p2p/{}", port, peer_id).parse().unwrap();
+
+    let (handle, mut events) = NodeBuilder::new().build().unwrap().spawn();
+    handle.dial(addr).await.unwrap();
+
+    let error = timeout(Duration::from_secs(10), async {
+        loop {
+            match events.next().await {
+                Some(NodeEvent::OutgoingConnectionError { error, .. }) => return error,
+                Some(_) => continue,
+                None => panic!("node stopped"),
+            }
+        }
+    })
+    .await
+    .expect("dial did not fail");
+
+    assert!(matches!(error, Error::Dial { peer_id: Some(id), .. } if id == peer_id));
+    assert!(error.is_connection_refused(), "unexpected error {:?}", error);
+    handle.shutdown().await.unwrap();
+}
@@ -0,0 +1,40 @@
+// identify 信息处理测试
+use libp2p::Multiaddr;
+use p2p::identify::{self, PeerIdentity};
+
+fn identity(listen_addrs: &[&str], protocols: &[&str]) -> PeerIdentity {
+    PeerIdentity {
+        agent_version: identify::agent_version(),
+        protocol_version: identify::PROTOCOL_VERSION.to_string(),
+        protocols: protocols.iter().map(|protocol| protocol.to_string()).collect(),
+        listen_addrs: listen_addrs.iter().map(|addr| addr.parse().unwrap()).collect(),
+        observed_addr: "/ip4/203.0.113.7/tcp/51234".parse().unwrap(),
+    }
+}
+
+fn addrs(list: &[&str]) -> Vec<Multiaddr> {
+    list.iter().map(|addr| addr.parse().unwrap()).collect()
+}
+
+#[test]
+fn test_routable_addrs_skip_loopback_and_unspecified() {
+    let peer = identity(
+        &["/ip4/127.0.0.1/tcp/4001", "/ip4/192.168.1.5/tcp/4001", "/ip4/0.0.0.0/tcp/4001", "/ip6/::1/tcp/4001", "/ip4/203.0.113.9/tcp/4001", "/dns4/example.com/tcp/4001"],
+        &[],
+    );
+    assert_eq!(
+        peer.routable_addrs(),
+        addrs(&["/ip4/192.168.1.5/tcp/4001", "/ip4/203.0.113.9/tcp/4001", "/dns4/example.com/tcp/4001"])
+    );
+
+    // 只在本机监听的节点保留回环地址
+    let local = identity(&["/ip4/127.0.0.1/tcp/4001", "/ip6/::1/tcp/4001"], &[]);
+    assert_eq!(local.routable_addrs(), addrs(&["/ip4/127.0.0.1/tcp/4001", "/ip6/::1/tcp/4001"]));
+}
+
+#[test]
+fn test_only_kademlia_peers_join_routing_table() {
+    assert!(identity(&[], &["/ipfs/id/1.0.0", "/ipfs/kad/1.0.0"]).supports_kademlia());
+    assert!(!identity(&[], &["/ipfs/id/1.0.0", "/ipfs/ping/1.0.0"]).supports_kademlia());
+    assert!(identify::agent_version().starts_with("p2p/"));
+}
@@ -0,0 +1,92 @@
+// 身份密钥存储集成测试
+use p2p::error::Error;
+use p2p::keystore::{self, KeyType};
+use std::fs;
+use std::path::PathBuf;
+
+// 每个测试使用独立的临时目录
+fn temp_keystore(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("p2p_keystore_{}_{}", name, rand::random::<u32>()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join("KEYSTORE.json")
+}
+
+#[test]
+fn test_load_or_generate_keeps_peer_id() {
+    let path = temp_keystore("persist");
+
+    let first = keystore::load_or_generate(&path, KeyType::Ed25519, None).unwrap();
+    let second = keystore::load_or_generate(&path, KeyType::Ed25519, None).unwrap();
+    assert_eq!(first.public().to_peer_id(), second.public().to_peer_id());
+
+    let info = keystore::show(&path).unwrap();
+    assert_eq!(info.peer_id, first.public().to_peer_id());
+    assert_eq!(info.key_type, KeyType::Ed25519);
+    assert!(!info.encrypted);
+
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+}
+
+#[test]
+fn test_encrypted_keystore_requires_passphrase() {
+    let path = temp_keystore("encrypted");
+
+    let keypair = keystore::generate(&path, KeyType::Secp256k1, Some("secret")).unwrap();
+    assert!(keystore::show(&path).unwrap().encrypted);
+    // 密文中不应出现明文密钥
+    let content = fs::read_to_string(&path).unwrap();
+    assert!(!content.contains(&hex::encode(keypair.to_protobuf_encoding().unwrap())));
+
+    assert!(matches!(keystore::load(&path, None), Err(Error::PassphraseRequired { .. })));
+    assert!(matches!(keystore::load(&path, Some("wrong")), Err(Error::WrongPassphrase { .. })));
+    let loaded = keystore::load(&path, Some("secret")).unwrap();
+    assert_eq!(loaded.public().to_peer_id(), keypair.public().to_peer_id());
+
+    // 已存在的密钥文件不能被 generate 覆盖
+    assert!(matches!(keystore::generate(&path, KeyType::Ed25519, None), Err(Error::KeystoreExists { .. })));
+
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+}
+
+#[test]
+fn test_rotate_replaces_key_and_keeps_backup() {
+    let path = temp_keystore("rotate");
+
+    let old = keystore::generate(&path, KeyType::Ed25519, None).unwrap();
+    let (new, backup) = keystore::rotate(&path, KeyType::Ecdsa, None).unwrap();
+    assert_ne!(old.public().to_peer_id(), new.public().to_peer_id());
+    assert_eq!(keystore::show(&path).unwrap().key_type, KeyType::Ecdsa);
+
+    let restored = keystore::load(&backup, None).unwrap();
+    assert_eq!(restored.public().to_peer_id(), old.public().to_peer_id());
+
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+}
+
+#[test]
+fn test_rsa_keystore_roundtrip() {
+    let path = temp_keystore("rsa");
+
+    let keypair = keystore::generate(&path, KeyType::Rsa, Some("secret")).unwrap();
+    let loaded = keystore::load(&path, Some("secret")).unwrap();
+    assert_eq!(loaded.public().to_peer_id(), keypair.public().to_peer_id());
+
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+}
+
+#[test]
+fn test_concurrent_first_run_shares_one_identity() {
+    let path = temp_keystore("concurrent");
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let path = path.clone();
+            std::thread::spawn(move || keystore::load_or_generate(&path, KeyType::Ed25519, None).unwrap())
+        })
+        .collect();
+    let peer_ids: Vec<_> = handles.into_iter().map(|h| h.join().unwrap().public().to_peer_id()).collect();
+    assert!(peer_ids.iter().all(|id| *id == peer_ids[0]));
+    assert_eq!(keystore::show(&path).unwrap().peer_id, peer_ids[0]);
+
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+}
@@ -0,0 +1,167 @@
+// NAT 行为发现测试：本地模拟一个 RFC 5780 服务器（两个 IP、两个端口）和它前面的 NAT
+use bytecodec::EncodeExt;
+use p2p::error::Error;
+use p2p::nat::{discover_nat_type, FilteringBehavior, MappingBehavior};
+use p2p::stun::Attribute;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use stun_codec::rfc5389::attributes::{Fingerprint, XorMappedAddress};
+use stun_codec::rfc5780::attributes::{ChangeRequest, OtherAddress};
+use stun_codec::rfc5389;
+use stun_codec::Attribute as _;
+use stun_codec::{Message, MessageClass, MessageEncoder};
+use tokio::net::UdpSocket;
+
+mod common;
+use common::{fast_config, serve_stun, spawn_stun_server};
+
+const PRIMARY_IP: [u8; 4] = [127, 0, 0, 1];
+const ALTERNATE_IP: [u8; 4] = [127, 0, 0, 2];
+
+// 模拟的 NAT：映射地址由请求到达的服务器套接字决定，过滤规则决定服务器从其它地址发出的响应能否到达
+#[derive(Clone, Copy)]
+struct SimulatedNat {
+    // 为空时不模拟 NAT，直接返回请求的来源地址
+    mapping: Option<MappingBehavior>,
+    filtering: FilteringBehavior,
+}
+
+impl SimulatedNat {
+    // index 为服务器套接字的序号：0 主 IP 主端口，1 主 IP 备用端口，2 备用 IP 主端口，3 备用 IP 备用端口
+    fn mapped_address(&self, from: SocketAddr, index: u16) -> SocketAddr {
+        let public_ip: IpAddr = [203, 0, 113, 1].into();
+        let port = match self.mapping {
+            None | Some(MappingBehavior::NoNat) => return from,
+            Some(MappingBehavior::EndpointIndependent) => 40000,
+            Some(MappingBehavior::AddressDependent) => 40000 + index / 2,
+            Some(MappingBehavior::AddressAndPortDependent) => 40000 + index,
+        };
+        SocketAddr::new(public_ip, port)
+    }
+
+    fn allows(&self, destination: SocketAddr, source: SocketAddr) -> bool {
+        match self.filtering {
+            FilteringBehavior::EndpointIndependent => true,
+            FilteringBehavior::AddressDependent => destination.ip() == source.ip(),
+            FilteringBehavior::AddressAndPortDependent => destination == source,
+        }
+    }
+}
+
+// stun_codec 0.4 的 CHANGE-REQUEST 解码器位序有误，按 RFC 5780 7.2 从原始属性中读取（0x4 换 IP，0x2 换端口）
+fn change_request(request: &Message<rfc5389::Attribute>) -> (bool, bool) {
+    let flags = request
+        .unknown_attributes()
+        .find(|attr| attr.get_type().as_u16() == ChangeRequest::CODEPOINT)
+        .map_or(0, |attr| attr.value()[3]);
+    (flags & 0x4 != 0, flags & 0x2 != 0)
+}
+
+// 在主 IP 和备用 IP 的两个端口上各监听一个套接字，返回主地址
+async fn spawn_rfc5780_server(nat: SimulatedNat) -> SocketAddr {
+    let (primary, alternate) = loop {
+        let first = UdpSocket::bind(SocketAddr::from((PRIMARY_IP, 0))).await.unwrap();
+        let second = UdpSocket::bind(SocketAddr::from((PRIMARY_IP, 0))).await.unwrap();
+        let (p1, p2) = (first.local_addr().unwrap().port(), second.local_addr().unwrap().port());
+        let third = UdpSocket::bind(SocketAddr::from((ALTERNATE_IP, p1))).await;
+        let fourth = UdpSocket::bind(SocketAddr::from((ALTERNATE_IP, p2))).await;
+        if let (Ok(third), Ok(fourth)) = (third, fourth) {
+            break ((first, second), (third, fourth));
+        }
+    };
+    let sockets = Arc::new(vec![primary.0, primary.1, alternate.0, alternate.1]);
+    let addresses: Vec<SocketAddr> = sockets.iter().map(|socket| socket.local_addr().unwrap()).collect();
+    let other_address = addresses[3];
+
+    for index in 0..sockets.len() {
+        let addresses = addresses.clone();
+        serve_stun(sockets.clone(), index, move |request, _, from| {
+            let local = addresses[index];
+            let (change_ip, change_port) = change_request(request);
+            let source_ip = if change_ip { other_address.ip() } else { local.ip() };
+            let source_port = match (change_port, local.port() == other_address.port()) {
+                (false, _) => local.port(),
+                (true, true) => addresses[0].port(),
+                (true, false) => other_address.port(),
+            };
+            let source = SocketAddr::new(source_ip, source_port);
+            if !nat.allows(local, source) {
+                return None;
+            }
+
+            let mut reply = Message::<Attribute>::new(MessageClass::SuccessResponse, request.method(), request.transaction_id());
+            reply.add_attribute(XorMappedAddress::new(nat.mapped_address(from, index as u16)));
+            reply.add_attribute(OtherAddress::new(other_address));
+            let fingerprint = Fingerprint::new(&reply).unwrap();
+            reply.add_attribute(fingerprint);
+            let bytes = MessageEncoder::new().encode_into_bytes(reply).unwrap();
+            Some((addresses.iter().position(|addr| *addr == source).unwrap(), bytes))
+        });
+    }
+    addresses[0]
+}
+
+async fn discover(mapping: Option<MappingBehavior>, filtering: FilteringBehavior) -> p2p::nat::NatType {
+    let server = spawn_rfc5780_server(SimulatedNat { mapping, filtering }).await.to_string();
+    discover_nat_type(&[&server], &fast_config()).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_no_nat_is_detected() {
+    let nat_type = discover(None, FilteringBehavior::EndpointIndependent).await;
+    assert_eq!(nat_type.mapping, MappingBehavior::NoNat);
+    assert_eq!(nat_type.filtering, FilteringBehavior::EndpointIndependent);
+    assert_eq!(nat_type.classic_name(), "open internet");
+}
+
+#[tokio::test]
+async fn test_mapping_behaviors_are_classified() {
+    for mapping in [
+        MappingBehavior::EndpointIndependent,
+        MappingBehavior::AddressDependent,
+        MappingBehavior::AddressAndPortDependent,
+    ] {
+        let nat_type = discover(Some(mapping), FilteringBehavior::EndpointIndependent).await;
+        assert_eq!(nat_type.mapping, mapping);
+        assert_eq!(nat_type.public_address, "203.0.113.1:40000".parse().unwrap());
+    }
+}
+
+#[tokio::test]
+async fn test_filtering_behaviors_are_classified() {
+    let mapping = Some(MappingBehavior::EndpointIndependent);
+    for (filtering, name) in [
+        (FilteringBehavior::EndpointIndependent, "full cone"),
+        (FilteringBehavior::AddressDependent, "restricted cone"),
+        (FilteringBehavior::AddressAndPortDependent, "port restricted cone"),
+    ] {
+        let nat_type = discover(mapping, filtering).await;
+        assert_eq!(nat_type.filtering, filtering);
+        assert_eq!(nat_type.classic_name(), name);
+        assert!(nat_type.supports_hole_punching());
+    }
+
+    let symmetric = discover(Some(MappingBehavior::AddressAndPortDependent), FilteringBehavior::AddressAndPortDependent).await;
+    assert_eq!(symmetric.classic_name(), "symmetric");
+    assert!(!symmetric.supports_hole_punching());
+}
+
+#[tokio::test]
+async fn test_server_without_other_address_is_unsupported() {
+    let server = spawn_stun_server(|request, _, from| {
+        let mut reply = Message::<Attribute>::new(MessageClass::SuccessResponse, request.method(), request.transaction_id());
+        reply.add_attribute(XorMappedAddress::new(from));
+        let fingerprint = Fingerprint::new(&reply).unwrap();
+        reply.add_attribute(fingerprint);
+        Some(MessageEncoder::new().encode_into_bytes(reply).unwrap())
+    })
+    .await;
+
+    let err = discover_nat_type(&[&server], &fast_config()).await.unwrap_err();
+    match err {
+        Error::StunFailed(errors) => {
+            assert!(matches!(errors.as_slice(), [Error::NatDiscoveryUnsupported { server: s }] if *s == server))
+        }
+        other => panic!("unexpected error {:?}", other),
+    }
+}
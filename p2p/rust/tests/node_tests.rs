@@ -0,0 +1,206 @@
+// 节点库集成测试
+use libp2p::futures::StreamExt;
+use libp2p::kad::RecordKey;
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use p2p::error::Error;
+use p2p::node::{NodeBuilder, NodeEvent, NodeEvents};
+use std::time::Duration;
+use tokio::time::timeout;
+
+// 等待节点报告第一个监听地址
+async fn first_listen_addr(events: &mut NodeEvents) -> Multiaddr {
+    loop {
+        if let Some(NodeEvent::NewListenAddr { address }) = events.next().await {
+            return address;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_two_nodes_connect_and_ping() {
+    let listener = NodeBuilder::new()
+        .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+        .ping_interval(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let listener_peer_id = listener.local_peer_id();
+    let (listener_handle, mut listener_events) = listener.spawn();
+    let listen_addr = first_listen_addr(&mut listener_events).await;
+
+    let dialer = NodeBuilder::new()
+        .ping_interval(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let (dialer_handle, mut dialer_events) = dialer.spawn();
+    dialer_handle.dial(listen_addr).await.unwrap();
+
+    // 连接建立后应收到对端的 Ping 响应
+    let rtt = timeout(Duration::from_secs(10), async {
+        loop {
+            match dialer_events.next().await {
+                Some(NodeEvent::Ping { peer, result: Ok(rtt) }) if peer == listener_peer_id => return rtt,
+                Some(_) => continue,
+                None => panic!("node stopped"),
+            }
+        }
+    })
+    .await
+    .expect("no ping response from listener");
+    assert!(rtt < Duration::from_secs(10));
+
+    dialer_handle.shutdown().await.unwrap();
+    listener_handle.shutdown().await.unwrap();
+    // 节点停止后命令应返回错误
+    assert!(dialer_handle.bootstrap().await.is_err());
+}
+
+#[tokio::test]
+async fn test_lookup_finds_locally_published_record() {
+    let node = NodeBuilder::new().build().unwrap();
+    let (handle, _events) = node.spawn();
+
+    let key = RecordKey::new(&"test_room");
+    // 没有其他节点时发布会失败，但记录仍保存在本地
+    let _ = handle.publish(key.clone(), b"hello".to_vec()).await;
+    let record = handle.lookup(key.clone()).await.unwrap();
+    assert_eq!(record.value, b"hello".to_vec());
+    // 只查找其它节点发布的记录时跳过本地记录
+    assert!(matches!(handle.lookup_remote(key).await, Err(Error::RecordNotFound)));
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_shutdown_closes_connections() {
+    let listener = NodeBuilder::new()
+        .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+        .build()
+        .unwrap();
+    let (listener_handle, mut listener_events) = listener.spawn();
+    let listen_addr = first_listen_addr(&mut listener_events).await;
+
+    let dialer = NodeBuilder::new().build().unwrap();
+    let dialer_peer_id = dialer.local_peer_id();
+    let (dialer_handle, mut dialer_events) = dialer.spawn();
+    dialer_handle.dial(listen_addr).await.unwrap();
+    timeout(Duration::from_secs(10), async {
+        while !matches!(dialer_events.next().await, Some(NodeEvent::ConnectionEstablished { .. })) {}
+    })
+    .await
+    .expect("connection not established");
+
+    // 关闭时应主动断开连接，对端随即收到连接关闭事件
+    let snapshot = dialer_handle.shutdown().await.unwrap();
+    assert!(snapshot.bootstrap_nodes.is_empty());
+    timeout(Duration::from_secs(5), async {
+        loop {
+            match listener_events.next().await {
+                Some(NodeEvent::ConnectionClosed { peer_id, .. }) if peer_id == dialer_peer_id => break,
+                Some(_) => continue,
+                None => panic!("node stopped"),
+            }
+        }
+    })
+    .await
+    .expect("listener did not observe the connection closing");
+
+    listener_handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_external_address_is_registered() {
+    let node = NodeBuilder::new().listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap()).build().unwrap();
+    let (handle, mut events) = node.spawn();
+    let listen_addr = first_listen_addr(&mut events).await;
+    assert_eq!(handle.listen_addresses().await.unwrap(), vec![listen_addr]);
+
+    // 没有其它节点时提供者记录只保存在本地，登记仍然成功
+    let external: Multiaddr = "/ip4/203.0.113.1/tcp/4001".parse().unwrap();
+    handle.add_external_address(external).await.unwrap();
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_peer_stun_via_dht() {
+    // 开启 STUN 应答的节点登记外部地址后在 DHT 中宣告
+    let responder = NodeBuilder::new()
+        .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+        .stun_responder(0)
+        .build()
+        .unwrap();
+    assert!(!responder.stun_responder_addresses().is_empty());
+    let responder_peer_id = responder.local_peer_id();
+    let (responder_handle, mut responder_events) = responder.spawn();
+    let listen_addr = first_listen_addr(&mut responder_events).await;
+    responder_handle.add_external_address(listen_addr.clone()).await.unwrap();
+
+    let client = NodeBuilder::new()
+        .bootstrap(listen_addr.with(Protocol::P2p(responder_peer_id)))
+        .build()
+        .unwrap();
+    let (client_handle, _client_events) = client.spawn();
+
+    let probe = timeout(Duration::from_secs(10), client_handle.peer_stun(4)).await.unwrap().unwrap();
+    assert_eq!(probe.results.len(), 1);
+    assert_eq!(probe.results[0].server, responder_peer_id.to_string());
+    assert_eq!(probe.consensus().unwrap().ip().to_string(), "127.0.0.1");
+
+    client_handle.shutdown().await.unwrap();
+    responder_handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_peer_without_responder_is_unsupported() {
+    let listener = NodeBuilder::new().listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap()).build().unwrap();
+    let listener_peer_id = listener.local_peer_id();
+    let (listener_handle, mut listener_events) = listener.spawn();
+    let listen_addr = first_listen_addr(&mut listener_events).await;
+
+    let client = NodeBuilder::new().bootstrap(listen_addr.with(Protocol::P2p(listener_peer_id))).build().unwrap();
+    let (client_handle, _client_events) = client.spawn();
+    let err = timeout(Duration::from_secs(10), client_handle.observe_address(listener_peer_id)).await.unwrap().unwrap_err();
+    assert!(matches!(err, Error::PeerStunUnsupported { peer_id } if peer_id == listener_peer_id), "unexpected error {:?}", err);
+
+    client_handle.shutdown().await.unwrap();
+    listener_handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_unsupported_listen_address_does_not_fail_build() {
+    // 任何特性组合下都没有编译 WebRTC 传输，该地址总是无法监听
+    let node = NodeBuilder::new()
+        .listen_on("/ip6/::1/tcp/0".parse().unwrap())
+        .listen_on("/ip4/127.0.0.1/udp/0/webrtc-direct".parse().unwrap())
+        .build()
+        .unwrap();
+    assert_eq!(node.listen_errors().len(), 1);
+    assert_eq!(node.listen_errors()[0].0.to_string(), "/ip4/127.0.0.1/udp/0/webrtc-direct");
+    let listener_peer_id = node.local_peer_id();
+    let (listener_handle, mut listener_events) = node.spawn();
+    let listen_addr = first_listen_addr(&mut listener_events).await;
+    assert!(listen_addr.to_string().starts_with("/ip6/::1/tcp/"));
+
+    // 无法连接的地址不影响通过 IPv6 地址建立连接
+    let dialer = NodeBuilder::new().build().unwrap();
+    let (dialer_handle, mut dialer_events) = dialer.spawn();
+    let addresses = vec!["/ip4/127.0.0.1/tcp/1".parse().unwrap(), listen_addr];
+    dialer_handle.dial_peer(listener_peer_id, addresses).await.unwrap();
+    timeout(Duration::from_secs(10), async {
+        loop {
+            match dialer_events.next().await {
+                Some(NodeEvent::ConnectionEstablished { peer_id, .. }) if peer_id == listener_peer_id => return,
+                Some(_) => continue,
+                None => panic!("node stopped"),
+            }
+        }
+    })
+    .await
+    .expect("no connection over IPv6");
+
+    // 所有监听地址都失败时构建失败
+    assert!(NodeBuilder::new().listen_on("/ip4/127.0.0.1/udp/0/webrtc-direct".parse().unwrap()).build().is_err());
+
+    dialer_handle.shutdown().await.unwrap();
+    listener_handle.shutdown().await.unwrap();
+}
@@ -1,8 +1,10 @@
 // 集成测试文件
-use p2p::performance_benchmark::{PerformanceTestResult, TestMetrics, save_performance_test_results};
-use serde_json;
+use libp2p::core::{ConnectedPoint, Endpoint, transport::PortUse};
+use libp2p::{ping, PeerId};
+use p2p::address::TransportKind;
+use p2p::performance_benchmark::{PerformanceTestResult, TestMetrics, TransportBenchmark, save_performance_test_results};
 use std::fs;
-use tokio;
+use std::time::Duration;
 
 #[tokio::test]
 async fn test_performance_result_serialization() {
@@ -72,4 +74,34 @@ async fn test_save_performance_test_results() {
     
     // 清理临时文件
     let _ = fs::remove_file(temp_file);
-}
\ No newline at end of file
+}
+
+fn dialer(address: &str) -> ConnectedPoint {
+    ConnectedPoint::Dialer { address: address.parse().unwrap(), role_override: Endpoint::Dialer, port_use: PortUse::Reuse }
+}
+
+#[test]
+fn test_transport_benchmark_reports_each_transport() {
+    let (tcp_peer, quic_peer) = (PeerId::random(), PeerId::random());
+    let mut benchmark = TransportBenchmark::new();
+    benchmark.connection_established(tcp_peer, &dialer("/ip4/1.2.3.4/tcp/4001"));
+    benchmark.connection_established(quic_peer, &dialer("/ip4/1.2.3.4/udp/4001/quic-v1"));
+    benchmark.ping(tcp_peer, &Ok(Duration::from_millis(40)));
+    benchmark.ping(quic_peer, &Ok(Duration::from_millis(20)));
+    benchmark.ping(quic_peer, &Ok(Duration::from_millis(30)));
+    benchmark.ping(quic_peer, &Err(ping::Failure::Timeout));
+    benchmark.connection_closed(tcp_peer);
+    // 没有连接记录的节点不计入统计
+    benchmark.ping(PeerId::random(), &Ok(Duration::from_millis(1)));
+
+    let results = benchmark.results();
+    assert_eq!(results.len(), 2);
+    let (tcp, quic) = (&results[0], &results[1]);
+    assert_eq!((tcp.transport, tcp.connections, tcp.connections_closed, tcp.pings), (TransportKind::Tcp, 1, 1, 1));
+    assert_eq!((quic.transport, quic.connections, quic.pings, quic.ping_failures), (TransportKind::Quic, 1, 2, 1));
+    assert!((tcp.avg_ping_ms - 40.0).abs() < 1e-6);
+    assert!((quic.avg_ping_ms - 25.0).abs() < 1e-6);
+
+    let json = serde_json::to_string(&results).unwrap();
+    assert!(json.contains("\"transport\":\"quic\""), "{}", json);
+}
@@ -0,0 +1,72 @@
+// QUIC 传输测试，需要以 --features quic 编译
+#![cfg(feature = "quic")]
+use libp2p::futures::StreamExt;
+use libp2p::Multiaddr;
+use p2p::address::{connection_transport, is_quic_address, TransportKind};
+use p2p::node::{NodeBuilder, NodeEvent, NodeEvents};
+use std::time::Duration;
+use tokio::time::timeout;
+
+// 等待节点报告 TCP 和 QUIC 两个监听地址
+async fn tcp_and_quic_listen_addrs(events: &mut NodeEvents) -> (Multiaddr, Multiaddr) {
+    let (mut tcp, mut quic) = (None, None);
+    while tcp.is_none() || quic.is_none() {
+        if let Some(NodeEvent::NewListenAddr { address }) = events.next().await {
+            if is_quic_address(&address) {
+                quic = Some(address);
+            } else {
+                tcp = Some(address);
+            }
+        }
+    }
+    (tcp.unwrap(), quic.unwrap())
+}
+
+#[tokio::test]
+async fn test_quic_is_preferred_when_peer_offers_both() {
+    let listener = NodeBuilder::new()
+        .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+        .listen_on("/ip4/127.0.0.1/udp/0/quic-v1".parse().unwrap())
+        .ping_interval(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let listener_peer_id = listener.local_peer_id();
+    let (listener_handle, mut listener_events) = listener.spawn();
+    let (tcp, quic) = timeout(Duration::from_secs(10), tcp_and_quic_listen_addrs(&mut listener_events)).await.unwrap();
+
+    let dialer = NodeBuilder::new().ping_interval(Duration::from_millis(200)).build().unwrap();
+    let (dialer_handle, mut dialer_events) = dialer.spawn();
+    // TCP 地址排在前面，拨号时仍应先用 QUIC
+    dialer_handle.dial_peer(listener_peer_id, vec![tcp, quic]).await.unwrap();
+
+    let transport = timeout(Duration::from_secs(10), async {
+        loop {
+            match dialer_events.next().await {
+                Some(NodeEvent::ConnectionEstablished { peer_id, endpoint }) if peer_id == listener_peer_id => {
+                    return connection_transport(&endpoint);
+                }
+                Some(_) => continue,
+                None => panic!("node stopped"),
+            }
+        }
+    })
+    .await
+    .expect("no connection to listener");
+    assert_eq!(transport, TransportKind::Quic);
+
+    // QUIC 连接上的 Ping 正常
+    timeout(Duration::from_secs(10), async {
+        loop {
+            if let Some(NodeEvent::Ping { peer, result: Ok(_) }) = dialer_events.next().await
+                && peer == listener_peer_id
+            {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("no ping over QUIC");
+
+    dialer_handle.shutdown().await.unwrap();
+    listener_handle.shutdown().await.unwrap();
+}
@@ -0,0 +1,49 @@
+// 测试报告与最终连接方式测试
+use libp2p::PeerId;
+use p2p::address::TransportKind;
+use p2p::autonat::ReachabilityStatus;
+use p2p::report::{connection_label, final_connection, generate_test_report, ConnectionAttempt};
+
+fn attempt(result: &str, transport: Option<TransportKind>) -> ConnectionAttempt {
+    ConnectionAttempt {
+        peer_id: PeerId::random(),
+        timestamp: String::new(),
+        result: result.to_string(),
+        error_message: None,
+        transport,
+    }
+}
+
+#[test]
+fn test_connection_label() {
+    assert_eq!(connection_label(Some(TransportKind::Relayed)), "relayed");
+    assert_eq!(connection_label(Some(TransportKind::Tcp)), "direct (tcp)");
+    assert_eq!(connection_label(Some(TransportKind::Quic)), "direct (quic)");
+    assert_eq!(connection_label(None), "none");
+}
+
+#[test]
+fn test_final_connection_follows_hole_punching() {
+    // 打洞成功后直接连接取代中继连接，打洞失败时仍为中继连接
+    let relayed = attempt("relayed", Some(TransportKind::Relayed));
+    let punched = [relayed.clone(), attempt("success", Some(TransportKind::Tcp))];
+    assert_eq!(final_connection(&punched), Some(TransportKind::Tcp));
+    let failed = [relayed, attempt("hole_punch_failed", None)];
+    assert_eq!(final_connection(&failed), Some(TransportKind::Relayed));
+    assert_eq!(final_connection(&[attempt("timeout", None)]), None);
+}
+
+#[test]
+fn test_report_counts_hole_punch_failures_separately() {
+    let results = [attempt("relayed", Some(TransportKind::Relayed)), attempt("hole_punch_failed", None)];
+    let dir = std::env::temp_dir().join(format!("p2p_report_{}", rand::random::<u32>()));
+    let path = dir.join("report.txt");
+    generate_test_report(true, &results, 1, 1, 10, None, &ReachabilityStatus::default(), &path);
+
+    // 打洞失败后经中继通过的测试不报告错误
+    let report = std::fs::read_to_string(&path).unwrap();
+    assert!(report.contains("Test Result: PASSED"));
+    assert!(report.contains("- Other Errors: 0"));
+    assert!(report.contains("- Hole Punch Failures: 1"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
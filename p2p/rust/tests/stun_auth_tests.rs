@@ -0,0 +1,234 @@
+// STUN 长期凭据测试：本地服务器按 RFC 5389/8489 发出 401、438 挑战并验证请求的 MESSAGE-INTEGRITY(-SHA256)
+use bytecodec::{DecodeExt, EncodeExt};
+use hmac::{Hmac, Mac};
+use p2p::error::Error;
+use p2p::stun::{binding_requests, Attribute, StunCredentialMap};
+use p2p::stun_auth::{MessageIntegritySha256, PasswordAlgorithm, PasswordAlgorithms, StunCredentials};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use stun_codec::rfc5389::attributes::{ErrorCode, Fingerprint, MessageIntegrity, Nonce, Realm, Username, XorMappedAddress};
+use stun_codec::{Message, MessageClass, MessageDecoder, MessageEncoder};
+use tokio::net::UdpSocket;
+
+mod common;
+use common::{fast_config, spawn_stun_server};
+
+const REALM: &str = "p2p.test";
+const USERNAME: &str = "alice";
+const PASSWORD: &str = "secret";
+// RFC 8489 的 NONCE 安全特性标记
+const SHA256_NONCE: &str = "obMatJos2AAABnonce";
+
+fn credentials(server: &str, password: &str) -> StunCredentialMap {
+    let credentials = StunCredentials { username: USERNAME.to_string(), password: password.to_string() };
+    StunCredentialMap::from([(server.to_string(), credentials)])
+}
+
+// 服务器的挑战方式
+#[derive(Clone, Copy)]
+enum Scheme {
+    // RFC 5389：MESSAGE-INTEGRITY，密钥为 MD5
+    Classic,
+    // RFC 8489：MESSAGE-INTEGRITY-SHA256，服务器优先 SHA-256 密码算法
+    Sha256,
+}
+
+#[derive(Default)]
+struct ServerState {
+    requests: usize,
+    unsigned: usize,
+    verified: usize,
+    stale_nonce: usize,
+}
+
+struct AuthServer {
+    address: String,
+    state: Arc<Mutex<ServerState>>,
+}
+
+// 启动一个要求长期凭据的服务器；stale_nonces 为签名请求中先以 438 拒绝的次数，sign_responses 为 false 时响应不签名
+async fn spawn_auth_server(scheme: Scheme, stale_nonces: usize, sign_responses: bool) -> AuthServer {
+    let state = Arc::new(Mutex::new(ServerState::default()));
+    let shared = state.clone();
+    let mut nonce = nonce_for(scheme, 0);
+    let address = spawn_stun_server(move |_, packet, from| {
+        // 认证属性不在 RFC 5389 中，按客户端的属性集重新解码
+        let request = MessageDecoder::<Attribute>::new().decode_from_bytes(packet).unwrap().unwrap();
+        let mut state = shared.lock().unwrap();
+        state.requests += 1;
+        let reply = if request.get_attribute::<Username>().is_none() {
+            state.unsigned += 1;
+            challenge(&request, 401, scheme, &nonce)
+        } else if !request_is_valid(&request, packet, scheme, &nonce) {
+            challenge(&request, 401, scheme, &nonce)
+        } else if state.stale_nonce < stale_nonces {
+            state.stale_nonce += 1;
+            nonce = nonce_for(scheme, state.stale_nonce);
+            challenge(&request, 438, scheme, &nonce)
+        } else {
+            state.verified += 1;
+            success(&request, from, scheme, sign_responses)
+        };
+        Some(reply)
+    })
+    .await;
+    AuthServer { address, state }
+}
+
+fn nonce_for(scheme: Scheme, generation: usize) -> String {
+    match scheme {
+        Scheme::Classic => format!("nonce-{}", generation),
+        Scheme::Sha256 => format!("{}{}", SHA256_NONCE, generation),
+    }
+}
+
+// SHA-256 在前，表示服务器更希望使用 SHA-256
+fn password_algorithms() -> PasswordAlgorithms {
+    PasswordAlgorithms::new(vec![0, 2, 0, 0, 0, 1, 0, 0])
+}
+
+fn challenge(request: &Message<Attribute>, code: u16, scheme: Scheme, nonce: &str) -> Vec<u8> {
+    let mut reply = Message::<Attribute>::new(MessageClass::ErrorResponse, request.method(), request.transaction_id());
+    let reason = if code == 401 { "Unauthorized" } else { "Stale Nonce" };
+    reply.add_attribute(ErrorCode::new(code, reason.to_string()).unwrap());
+    reply.add_attribute(Realm::new(REALM.to_string()).unwrap());
+    reply.add_attribute(Nonce::new(nonce.to_string()).unwrap());
+    if let Scheme::Sha256 = scheme {
+        reply.add_attribute(password_algorithms());
+    }
+    MessageEncoder::new().encode_into_bytes(reply).unwrap()
+}
+
+fn success(request: &Message<Attribute>, from: SocketAddr, scheme: Scheme, sign: bool) -> Vec<u8> {
+    let mut reply = Message::<Attribute>::new(MessageClass::SuccessResponse, request.method(), request.transaction_id());
+    reply.add_attribute(XorMappedAddress::new(from));
+    if sign {
+        match scheme {
+            Scheme::Classic => {
+                let username = Username::new(USERNAME.to_string()).unwrap();
+                let realm = Realm::new(REALM.to_string()).unwrap();
+                reply.add_attribute(MessageIntegrity::new_long_term_credential(&reply, &username, &realm, PASSWORD).unwrap());
+            }
+            Scheme::Sha256 => {
+                let mut bytes = MessageEncoder::new().encode_into_bytes(reply.clone()).unwrap();
+                set_length(&mut bytes, 36);
+                reply.add_attribute(MessageIntegritySha256::new(hmac_sha256(&bytes)));
+            }
+        }
+    }
+    let fingerprint = Fingerprint::new(&reply).unwrap();
+    reply.add_attribute(fingerprint);
+    MessageEncoder::new().encode_into_bytes(reply).unwrap()
+}
+
+// 检查签名请求的 NONCE、算法和完整性
+fn request_is_valid(request: &Message<Attribute>, packet: &[u8], scheme: Scheme, nonce: &str) -> bool {
+    if request.get_attribute::<Nonce>().map(Nonce::value) != Some(nonce) {
+        return false;
+    }
+    match scheme {
+        Scheme::Classic => {
+            let username = request.get_attribute::<Username>().unwrap();
+            let realm = request.get_attribute::<Realm>().unwrap();
+            request.get_attribute::<MessageIntegrity>().is_some_and(|integrity| {
+                integrity.check_long_term_credential(username, realm, PASSWORD).is_ok()
+            })
+        }
+        Scheme::Sha256 => {
+            // 请求应带回服务器的算法列表并选择 SHA-256
+            assert_eq!(request.get_attribute::<PasswordAlgorithms>(), Some(&password_algorithms()));
+            assert_eq!(request.get_attribute::<PasswordAlgorithm>().map(PasswordAlgorithm::value), Some(&[0, 2, 0, 0][..]));
+            assert!(request.get_attribute::<MessageIntegrity>().is_none());
+            let Some(offset) = attribute_offset(packet, MessageIntegritySha256::CODEPOINT) else { return false };
+            let mut preceding = packet[..offset].to_vec();
+            set_length(&mut preceding, 36);
+            request.get_attribute::<MessageIntegritySha256>().unwrap().value() == hmac_sha256(&preceding)
+        }
+    }
+}
+
+fn hmac_sha256(bytes: &[u8]) -> Vec<u8> {
+    let key = Sha256::digest(format!("{}:{}:{}", USERNAME, REALM, PASSWORD).as_bytes());
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key).unwrap();
+    mac.update(bytes);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn set_length(bytes: &mut [u8], integrity_len: usize) {
+    let length = (bytes.len() - 20 + integrity_len) as u16;
+    bytes[2..4].copy_from_slice(&length.to_be_bytes());
+}
+
+fn attribute_offset(packet: &[u8], codepoint: u16) -> Option<usize> {
+    let mut offset = 20;
+    while offset + 4 <= packet.len() {
+        if u16::from_be_bytes([packet[offset], packet[offset + 1]]) == codepoint {
+            return Some(offset);
+        }
+        let length = u16::from_be_bytes([packet[offset + 2], packet[offset + 3]]) as usize;
+        offset += 4 + length.next_multiple_of(4);
+    }
+    None
+}
+
+async fn authenticated_binding(server: &str, credentials: &StunCredentialMap) -> p2p::error::Result<SocketAddr> {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let targets = [(server.to_string(), server.parse().unwrap())];
+    let result = binding_requests(&socket, &targets, credentials, &fast_config()).await.remove(0)?;
+    assert_eq!(result.mapped_address, socket.local_addr().unwrap());
+    Ok(result.mapped_address)
+}
+
+#[tokio::test]
+async fn test_binding_with_message_integrity() {
+    let server = spawn_auth_server(Scheme::Classic, 0, true).await;
+    authenticated_binding(&server.address, &credentials(&server.address, PASSWORD)).await.unwrap();
+    let state = server.state.lock().unwrap();
+    assert_eq!((state.requests, state.unsigned, state.verified), (2, 1, 1));
+}
+
+#[tokio::test]
+async fn test_binding_with_message_integrity_sha256() {
+    let server = spawn_auth_server(Scheme::Sha256, 0, true).await;
+    authenticated_binding(&server.address, &credentials(&server.address, PASSWORD)).await.unwrap();
+    assert_eq!(server.state.lock().unwrap().verified, 1);
+}
+
+#[tokio::test]
+async fn test_stale_nonce_is_renewed() {
+    let server = spawn_auth_server(Scheme::Classic, 1, true).await;
+    authenticated_binding(&server.address, &credentials(&server.address, PASSWORD)).await.unwrap();
+    {
+        let state = server.state.lock().unwrap();
+        assert_eq!((state.requests, state.stale_nonce, state.verified), (3, 1, 1));
+    }
+
+    // NONCE 一直过期时重试次数有限
+    let server = spawn_auth_server(Scheme::Sha256, usize::MAX, true).await;
+    let err = authenticated_binding(&server.address, &credentials(&server.address, PASSWORD)).await.unwrap_err();
+    assert!(matches!(err, Error::StunErrorResponse { code: 438, .. }), "unexpected error {:?}", err);
+    assert_eq!(server.state.lock().unwrap().requests, 3);
+}
+
+#[tokio::test]
+async fn test_wrong_password_is_not_retried() {
+    let server = spawn_auth_server(Scheme::Classic, 0, true).await;
+    let err = authenticated_binding(&server.address, &credentials(&server.address, "wrong")).await.unwrap_err();
+    assert!(matches!(err, Error::StunErrorResponse { code: 401, .. }), "unexpected error {:?}", err);
+    // 不带凭据和带凭据各一次，同一 NONCE 下再次 401 后放弃
+    assert_eq!(server.state.lock().unwrap().requests, 2);
+
+    // 没有为该服务器配置凭据时 401 直接作为错误返回
+    let err = authenticated_binding(&server.address, &StunCredentialMap::new()).await.unwrap_err();
+    assert!(matches!(err, Error::StunErrorResponse { code: 401, .. }), "unexpected error {:?}", err);
+}
+
+#[tokio::test]
+async fn test_unsigned_response_to_signed_request_is_rejected() {
+    for scheme in [Scheme::Classic, Scheme::Sha256] {
+        let server = spawn_auth_server(scheme, 0, false).await;
+        let err = authenticated_binding(&server.address, &credentials(&server.address, PASSWORD)).await.unwrap_err();
+        assert!(matches!(err, Error::StunDecode { .. }), "unexpected error {:?}", err);
+    }
+}
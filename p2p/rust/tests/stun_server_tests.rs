@@ -0,0 +1,88 @@
+// 节点内置 STUN 应答测试：用本项目的客户端请求应答套接字
+use p2p::error::Error;
+use p2p::stun::{binding_request, change_request, perform_stun_request, StunCredentialMap};
+use p2p::stun_server::{bind_udp_responder, respond, serve_udp};
+use bytecodec::{DecodeExt, EncodeExt};
+use std::net::SocketAddr;
+use stun_codec::rfc5389::attributes::{Fingerprint, XorMappedAddress};
+use stun_codec::rfc5389::methods::BINDING;
+use stun_codec::rfc5389::Attribute;
+use stun_codec::rfc5780::attributes::ChangeRequest;
+use stun_codec::{Message, MessageClass, MessageDecoder, MessageEncoder, TransactionId};
+use tokio::net::UdpSocket;
+
+mod common;
+use common::fast_config;
+
+async fn spawn_responder() -> SocketAddr {
+    let socket = bind_udp_responder("127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(serve_udp(socket));
+    addr
+}
+
+#[tokio::test]
+async fn test_responder_reports_observed_address() {
+    let server = spawn_responder().await.to_string();
+    let result = binding_request(&server, &fast_config()).await.unwrap();
+    assert_eq!(result.mapped_address.ip().to_string(), "127.0.0.1");
+    assert_eq!(result.server_address.to_string(), server);
+    // 应答不支持 RFC 5780
+    assert_eq!(result.other_address, None);
+}
+
+#[tokio::test]
+async fn test_unknown_required_attribute_is_rejected() {
+    let server = spawn_responder().await;
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let change = ChangeRequest::new(false, false);
+    let err = change_request(&socket, "responder", server, change, server, &fast_config()).await.unwrap_err();
+    assert!(matches!(err, Error::StunErrorResponse { code: 420, .. }), "unexpected error {:?}", err);
+}
+
+#[test]
+fn test_only_requests_are_answered() {
+    let observed = "192.0.2.1:4001".parse().unwrap();
+    assert_eq!(respond(b"not a stun message", observed), None);
+
+    let mut request = Message::<Attribute>::new(MessageClass::Request, BINDING, TransactionId::new([1; 12]));
+    let fingerprint = Fingerprint::new(&request).unwrap();
+    request.add_attribute(fingerprint);
+    let request = MessageEncoder::new().encode_into_bytes(request).unwrap();
+    let response = respond(&request, observed).expect("binding request is answered");
+
+    let decoded = MessageDecoder::<Attribute>::new().decode_from_bytes(&response).unwrap().unwrap();
+    assert_eq!(decoded.class(), MessageClass::SuccessResponse);
+    assert_eq!(decoded.transaction_id(), TransactionId::new([1; 12]));
+    assert_eq!(decoded.get_attribute::<XorMappedAddress>().unwrap().address(), observed);
+    // 响应不是请求，不应再回复
+    assert_eq!(respond(&response, observed), None);
+}
+
+#[tokio::test]
+async fn test_responder_answers_over_ipv6() {
+    let socket = bind_udp_responder("[::1]:0".parse().unwrap()).unwrap();
+    let server = socket.local_addr().unwrap().to_string();
+    tokio::spawn(serve_udp(socket));
+
+    // XOR-MAPPED-ADDRESS 的 IPv6 地址与事务 ID 异或
+    let result = binding_request(&server, &fast_config()).await.unwrap();
+    assert_eq!(result.mapped_address.ip().to_string(), "::1");
+    assert_eq!(result.server_address.to_string(), server);
+}
+
+#[tokio::test]
+async fn test_dual_stack_probe_reports_each_family() {
+    let ipv4 = spawn_responder().await.to_string();
+    let socket = bind_udp_responder("[::1]:0".parse().unwrap()).unwrap();
+    let ipv6 = socket.local_addr().unwrap().to_string();
+    tokio::spawn(serve_udp(socket));
+
+    let probe = perform_stun_request(&[&ipv4, &ipv6], &StunCredentialMap::new()).await.unwrap();
+    assert_eq!(probe.results.len(), 2);
+    assert_eq!(probe.consensus_for(false).unwrap().ip().to_string(), "127.0.0.1");
+    assert_eq!(probe.consensus_for(true).unwrap().ip().to_string(), "::1");
+    // 不同地址族的映射不同不代表对称型 NAT
+    assert!(!probe.disagrees());
+    assert_eq!(probe.consensus(), probe.consensus_for(false));
+}
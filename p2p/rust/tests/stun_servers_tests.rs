@@ -0,0 +1,237 @@
+// STUN 服务器健康记录测试
+use chrono::Utc;
+use p2p::error::Error;
+use p2p::stun::{StunProbe, StunResult};
+use p2p::stun_auth::StunCredentials;
+use p2p::stun_servers::{
+    load_stun_servers_from_path, merge_shared_stun_servers, merge_stun_servers, prune_failed_candidates,
+    record_stun_outcomes, save_stun_servers_to_path, select_stun_servers, shared_stun_servers, stun_credentials,
+    update_stun_servers_at_path, SharedStunServers, StunServerRecord, DEFAULT_MAX_STUN_FAILURES,
+    MAX_SHARED_CANDIDATE_FAILURES, MAX_SHARED_STUN_CANDIDATES, MAX_STUN_SERVERS_PER_ROUND,
+};
+use std::fs;
+use std::time::Duration;
+
+fn result(server: &str, server_address: &str, rtt_ms: u64) -> StunResult {
+    StunResult {
+        mapped_address: "203.0.113.1:4001".parse().unwrap(),
+        server: server.to_string(),
+        server_address: server_address.parse().unwrap(),
+        rtt: Duration::from_millis(rtt_ms),
+        other_address: None,
+    }
+}
+
+fn servers(list: &[&str]) -> Vec<String> {
+    list.iter().map(|server| server.to_string()).collect()
+}
+
+#[test]
+fn test_merge_keeps_configured_order_and_saved_statistics() {
+    let mut known = StunServerRecord::new("b.example:3478");
+    known.success_count = 7;
+    let saved = vec![known, StunServerRecord::new("shared.example:3478")];
+
+    let merged = merge_stun_servers(&servers(&["a.example:3478", "b.example:3478", "a.example:3478"]), saved);
+    let names: Vec<&str> = merged.iter().map(|record| record.server.as_str()).collect();
+    assert_eq!(names, ["a.example:3478", "b.example:3478", "shared.example:3478"]);
+    assert_eq!(merged[1].success_count, 7);
+}
+
+#[test]
+fn test_outcomes_count_each_server_once() {
+    let mut records = merge_stun_servers(&servers(&["dual:3478", "v4only:3478", "dead:3478"]), Vec::new());
+
+    // dual 在 IPv4 和 IPv6 上都应答，v4only 没有 IPv6 地址，dead 没有应答
+    let ipv4 = Ok(StunProbe {
+        results: vec![result("dual:3478", "192.0.2.1:3478", 40), result("v4only:3478", "192.0.2.2:3478", 80)],
+        failures: vec![Error::StunTimeout { server: "dead:3478".to_string() }],
+    });
+    let ipv6 = Ok(StunProbe {
+        results: vec![result("dual:3478", "[2001:db8::1]:3478", 50)],
+        failures: vec![
+            Error::StunIo { server: "v4only:3478".to_string(), source: std::io::Error::other("no IPv6 address") },
+            Error::StunTimeout { server: "dead:3478".to_string() },
+        ],
+    });
+    let tcp = Err(Error::StunFailed(vec![Error::StunTimeout { server: "dead:3478".to_string() }]));
+    record_stun_outcomes(&mut records, [&ipv4, &ipv6, &tcp]);
+
+    let dual = &records[0];
+    assert_eq!((dual.success_count, dual.failure_count), (1, 0));
+    assert_eq!(dual.resolved, servers(&["192.0.2.1:3478", "[2001:db8::1]:3478"]));
+    let v4only = &records[1];
+    assert_eq!((v4only.success_count, v4only.failure_count, v4only.consecutive_failures), (1, 0, 0));
+    assert_eq!(v4only.last_error, None);
+    let dead = &records[2];
+    assert_eq!((dead.success_count, dead.failure_count, dead.consecutive_failures), (0, 1, 1));
+    assert_eq!(dead.last_error.as_deref(), Some("dead:3478: STUN request timed out"));
+    assert!(dead.last_failure.is_some());
+}
+
+#[test]
+fn test_selection_prefers_healthy_and_skips_dead_servers() {
+    let mut records = merge_stun_servers(&servers(&["slow:3478", "fast:3478", "dead:3478"]), Vec::new());
+    let probe = Ok(StunProbe {
+        results: vec![result("fast:3478", "192.0.2.1:3478", 20), result("slow:3478", "192.0.2.2:3478", 400)],
+        failures: vec![Error::StunTimeout { server: "dead:3478".to_string() }],
+    });
+    for _ in 0..DEFAULT_MAX_STUN_FAILURES {
+        record_stun_outcomes(&mut records, [&probe]);
+    }
+    assert_eq!(select_stun_servers(&mut records, DEFAULT_MAX_STUN_FAILURES), servers(&["fast:3478", "slow:3478"]));
+
+    // 很久以前失败的服务器重新尝试
+    let dead = records.iter_mut().find(|record| record.server == "dead:3478").unwrap();
+    dead.last_failure = Some(Utc::now() - chrono::Duration::days(1));
+    assert_eq!(select_stun_servers(&mut records, DEFAULT_MAX_STUN_FAILURES).len(), 3);
+
+    // 全部失败时仍然查询所有服务器，例如本机刚恢复网络
+    let mut all_dead = merge_stun_servers(&servers(&["a:3478", "b:3478"]), Vec::new());
+    let failed = Err(Error::StunFailed(vec![
+        Error::StunTimeout { server: "a:3478".to_string() },
+        Error::StunTimeout { server: "b:3478".to_string() },
+    ]));
+    for _ in 0..DEFAULT_MAX_STUN_FAILURES {
+        record_stun_outcomes(&mut all_dead, [&failed]);
+    }
+    assert_eq!(select_stun_servers(&mut all_dead, DEFAULT_MAX_STUN_FAILURES).len(), 2);
+}
+
+#[test]
+fn test_shared_servers_are_candidates_only() {
+    let mut records = merge_stun_servers(&servers(&["good:3478", "bad:3478", "new:3478"]), Vec::new());
+    let probe = Ok(StunProbe {
+        results: vec![result("good:3478", "192.0.2.1:3478", 20)],
+        failures: vec![Error::StunTimeout { server: "bad:3478".to_string() }],
+    });
+    record_stun_outcomes(&mut records, [&probe]);
+
+    // 只分享最近查询成功的服务器
+    let shared = shared_stun_servers(&records);
+    assert_eq!(shared.servers, servers(&["good:3478"]));
+
+    let received = SharedStunServers {
+        servers: servers(&["good:3478", "stun.example.cn:3478", "not-a-server", ":3478"]),
+        last_updated: Utc::now(),
+    };
+    assert_eq!(merge_shared_stun_servers(&mut records, &received), 1);
+    let added = records.iter().find(|record| record.server == "stun.example.cn:3478").unwrap();
+    assert_eq!(added.success_count, 0);
+    // 已知服务器的统计不受分享方影响
+    assert_eq!(records.iter().find(|record| record.server == "good:3478").unwrap().success_count, 1);
+}
+
+#[test]
+fn test_save_and_load_round_trip() {
+    let dir = std::env::temp_dir().join(format!("p2p_stun_servers_{}", rand::random::<u32>()));
+    let path = dir.join("STUN_SERVERS.json");
+    assert!(load_stun_servers_from_path(&path).unwrap().is_empty());
+
+    let mut records = merge_stun_servers(&servers(&["slow:3478", "fast:3478"]), Vec::new());
+    let probe = Ok(StunProbe {
+        results: vec![result("fast:3478", "192.0.2.1:3478", 20), result("slow:3478", "192.0.2.2:3478", 400)],
+        failures: Vec::new(),
+    });
+    record_stun_outcomes(&mut records, [&probe]);
+    save_stun_servers_to_path(&records, &path).unwrap();
+
+    // 按评分排序保存
+    let loaded = load_stun_servers_from_path(&path).unwrap();
+    assert_eq!(loaded[0].server, "fast:3478");
+    assert_eq!(loaded[0].rtt_ms, Some(20));
+    assert_eq!(loaded.len(), 2);
+
+    fs::write(&path, r#"{"version": 99, "servers": [], "last_updated": "2024-01-01T00:00:00Z"}"#).unwrap();
+    assert!(load_stun_servers_from_path(&path).unwrap_err().to_string().contains("unsupported version 99"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_credentials_are_kept_per_server() {
+    let dir = std::env::temp_dir().join(format!("p2p_stun_credentials_{}", rand::random::<u32>()));
+    let path = dir.join("STUN_SERVERS.json");
+    // 在 STUN_SERVERS.json 中为要求认证的服务器设置凭据
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        &path,
+        r#"{"version": 1, "last_updated": "2024-01-01T00:00:00Z", "servers": [
+            {"server": "corp:3478", "success_count": 0, "failure_count": 0, "consecutive_failures": 0,
+             "rtt_ms": null, "last_success": null, "last_failure": null, "last_error": null, "score": 0.0,
+             "credentials": {"username": "alice", "password": "secret"}}
+        ]}"#,
+    )
+    .unwrap();
+
+    let records = merge_stun_servers(&servers(&["public:3478", "corp:3478"]), load_stun_servers_from_path(&path).unwrap());
+    let credentials = stun_credentials(&records);
+    assert_eq!(credentials.len(), 1);
+    assert_eq!(credentials["corp:3478"], StunCredentials { username: "alice".to_string(), password: "secret".to_string() });
+
+    // 保存后凭据仍在，没有凭据的服务器不写入该字段
+    save_stun_servers_to_path(&records, &path).unwrap();
+    let saved = fs::read_to_string(&path).unwrap();
+    assert_eq!(saved.matches("\"credentials\"").count(), 1);
+    assert_eq!(stun_credentials(&load_stun_servers_from_path(&path).unwrap()), credentials);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_shared_candidates_are_capped_and_pruned() {
+    let mut records = merge_stun_servers(&servers(&["configured:3478"]), Vec::new());
+
+    // 候选总数有上限，不论对方分享了多少次
+    for batch in 0..MAX_SHARED_STUN_CANDIDATES {
+        let received = SharedStunServers {
+            servers: (0..4).map(|i| format!("peer{}-{}.example:3478", batch, i)).collect(),
+            last_updated: Utc::now(),
+        };
+        merge_shared_stun_servers(&mut records, &received);
+    }
+    assert_eq!(records.iter().filter(|record| record.shared).count(), MAX_SHARED_STUN_CANDIDATES);
+    assert_eq!(records.len(), MAX_SHARED_STUN_CANDIDATES + 1);
+
+    // 每轮只查询评分最高的几个服务器
+    assert_eq!(select_stun_servers(&mut records, DEFAULT_MAX_STUN_FAILURES).len(), MAX_STUN_SERVERS_PER_ROUND);
+
+    // 从未应答的候选失败多次后删除，配置的服务器即使失败也保留
+    let failures: Vec<Error> =
+        records.iter().map(|record| Error::StunTimeout { server: record.server.clone() }).collect();
+    let failed = Err(Error::StunFailed(failures));
+    for _ in 0..MAX_SHARED_CANDIDATE_FAILURES {
+        assert_eq!(prune_failed_candidates(&mut records), 0);
+        record_stun_outcomes(&mut records, [&failed]);
+    }
+    assert_eq!(prune_failed_candidates(&mut records), MAX_SHARED_STUN_CANDIDATES);
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].server, "configured:3478");
+}
+
+#[test]
+fn test_concurrent_updates_are_not_lost() {
+    let dir = std::env::temp_dir().join(format!("p2p_stun_update_{}", rand::random::<u32>()));
+    let path = dir.join("STUN_SERVERS.json");
+    let configured = servers(&["configured:3478"]);
+
+    // 分享服务器和记录查询结果同时进行，各自的修改都保留
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            let (path, configured) = (path.clone(), configured.clone());
+            std::thread::spawn(move || {
+                let shared = SharedStunServers { servers: vec![format!("shared{}:3478", i)], last_updated: Utc::now() };
+                update_stun_servers_at_path(&path, &configured, |records| merge_shared_stun_servers(records, &shared)).unwrap();
+                let probe = Ok(StunProbe { results: vec![result("configured:3478", "192.0.2.1:3478", 20)], failures: Vec::new() });
+                update_stun_servers_at_path(&path, &configured, |records| record_stun_outcomes(records, [&probe])).unwrap();
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let loaded = load_stun_servers_from_path(&path).unwrap();
+    assert_eq!(loaded.len(), 9);
+    assert_eq!(loaded.iter().find(|record| record.server == "configured:3478").unwrap().success_count, 8);
+    assert_eq!(loaded.iter().filter(|record| record.shared).count(), 8);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,306 @@
+// STUN 客户端测试：本地 UDP 服务器模拟各种响应
+use bytecodec::{DecodeExt, EncodeExt};
+use p2p::error::Error;
+use p2p::stun::{
+    binding_request, binding_requests, perform_stun_request, probe_from, tcp_binding_request, StunConfig,
+    StunCredentialMap, StunTransport,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use stun_codec::rfc5389::attributes::{ErrorCode, Fingerprint, XorMappedAddress};
+use stun_codec::rfc5389::Attribute;
+use stun_codec::{Message, MessageClass, MessageDecoder, MessageEncoder, TransactionId};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket, UdpSocket};
+
+mod common;
+use common::{closed_port, fast_config, serve_stun, spawn_stun_server};
+
+fn no_credentials() -> StunCredentialMap {
+    StunCredentialMap::new()
+}
+
+fn encode(message: Message<Attribute>) -> Vec<u8> {
+    MessageEncoder::new().encode_into_bytes(message).unwrap()
+}
+
+fn response(request: &Message<Attribute>, class: MessageClass, transaction_id: TransactionId) -> Message<Attribute> {
+    Message::new(class, request.method(), transaction_id)
+}
+
+// 把请求来源作为映射地址返回，port_offset 模拟对称型 NAT 为不同目标分配不同端口
+fn reflect(request: &Message<Attribute>, from: SocketAddr, port_offset: u16) -> Vec<u8> {
+    let mut reply = response(request, MessageClass::SuccessResponse, request.transaction_id());
+    let mapped = SocketAddr::new(from.ip(), from.port().wrapping_add(port_offset));
+    reply.add_attribute(XorMappedAddress::new(mapped));
+    let fingerprint = Fingerprint::new(&reply).unwrap();
+    reply.add_attribute(fingerprint);
+    encode(reply)
+}
+
+#[tokio::test]
+async fn test_retransmits_and_ignores_unrelated_responses() {
+    let mut count = 0;
+    let server = spawn_stun_server(move |request, _, from| {
+        count += 1;
+        match count {
+            // 第一个请求丢失
+            1 => None,
+            // 事务 ID 不同的响应应被忽略
+            2 => {
+                let mut reply = response(request, MessageClass::SuccessResponse, TransactionId::new([7; 12]));
+                reply.add_attribute(XorMappedAddress::new("1.2.3.4:5".parse().unwrap()));
+                Some(encode(reply))
+            }
+            _ => {
+                let mut reply = response(request, MessageClass::SuccessResponse, request.transaction_id());
+                reply.add_attribute(XorMappedAddress::new(from));
+                let fingerprint = Fingerprint::new(&reply).unwrap();
+                reply.add_attribute(fingerprint);
+                Some(encode(reply))
+            }
+        }
+    })
+    .await;
+
+    let result = binding_request(&server, &fast_config()).await.unwrap();
+    assert_eq!(result.server, server);
+    assert_eq!(result.server_address.to_string(), server);
+    assert_eq!(result.mapped_address.ip().to_string(), "127.0.0.1");
+    assert!(result.rtt < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_transaction_ids_are_random() {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let server = spawn_stun_server(move |request, _, _| {
+        let _ = sender.send(request.transaction_id());
+        None
+    })
+    .await;
+
+    let config = StunConfig { max_requests: 1, last_wait_factor: 1, ..fast_config() };
+    assert!(matches!(binding_request(&server, &config).await, Err(Error::StunTimeout { .. })));
+    assert!(matches!(binding_request(&server, &config).await, Err(Error::StunTimeout { .. })));
+    let first = receiver.recv().await.unwrap();
+    let second = receiver.recv().await.unwrap();
+    assert_ne!(first, second);
+    assert_ne!(first, TransactionId::new([0; 12]));
+}
+
+#[tokio::test]
+async fn test_error_code_is_reported() {
+    let server = spawn_stun_server(|request, _, _| {
+        let mut reply = response(request, MessageClass::ErrorResponse, request.transaction_id());
+        reply.add_attribute(ErrorCode::new(420, "Unknown Attribute".to_string()).unwrap());
+        Some(encode(reply))
+    })
+    .await;
+
+    let err = binding_request(&server, &fast_config()).await.unwrap_err();
+    assert!(matches!(err, Error::StunErrorResponse { code: 420, ref reason, .. } if reason == "Unknown Attribute"));
+}
+
+#[tokio::test]
+async fn test_bad_fingerprint_is_discarded() {
+    let server = spawn_stun_server(|request, _, from| {
+        let mut reply = response(request, MessageClass::SuccessResponse, request.transaction_id());
+        reply.add_attribute(XorMappedAddress::new(from));
+        let fingerprint = Fingerprint::new(&reply).unwrap();
+        reply.add_attribute(fingerprint);
+        let mut bytes = encode(reply);
+        // 改坏最后 4 字节的 CRC
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        Some(bytes)
+    })
+    .await;
+
+    let err = binding_request(&server, &fast_config()).await.unwrap_err();
+    assert!(matches!(err, Error::StunDecode { .. }), "unexpected error {:?}", err);
+}
+
+#[tokio::test]
+async fn test_malformed_responses_are_skipped() {
+    let mut count = 0;
+    let server = spawn_stun_server(move |request, _, from| {
+        count += 1;
+        match count {
+            // 不是 STUN 报文
+            1 => Some(b"definitely not stun".to_vec()),
+            // 截断的响应
+            2 => Some(reflect(request, from, 0)[..24].to_vec()),
+            _ => Some(reflect(request, from, 0)),
+        }
+    })
+    .await;
+
+    // 无法解码的响应不结束事务，重传后的有效响应仍被接受
+    let config = StunConfig { max_requests: 4, ..fast_config() };
+    let result = binding_request(&server, &config).await.unwrap();
+    assert_eq!(result.mapped_address.ip().to_string(), "127.0.0.1");
+
+    // 始终收到无法解码的响应时，超时后报告解码失败而不是超时
+    let server = spawn_stun_server(|_, _, _| Some(vec![0xff; 20])).await;
+    let err = binding_request(&server, &fast_config()).await.unwrap_err();
+    assert!(matches!(err, Error::StunDecode { server: ref failed, .. } if *failed == server), "unexpected error {:?}", err);
+}
+
+#[tokio::test]
+async fn test_response_from_other_source_is_ignored() {
+    // 服务器从另一个端口应答，事务 ID 正确也不接受
+    let sockets = vec![UdpSocket::bind("127.0.0.1:0").await.unwrap(), UdpSocket::bind("127.0.0.1:0").await.unwrap()];
+    let server = sockets[0].local_addr().unwrap().to_string();
+    serve_stun(Arc::new(sockets), 0, |request, _, from| Some((1, reflect(request, from, 0))));
+
+    let err = binding_request(&server, &fast_config()).await.unwrap_err();
+    assert!(matches!(err, Error::StunTimeout { .. }), "unexpected error {:?}", err);
+}
+
+#[tokio::test]
+async fn test_success_without_mapped_address() {
+    let server = spawn_stun_server(|request, _, _| {
+        let mut reply = response(request, MessageClass::SuccessResponse, request.transaction_id());
+        let fingerprint = Fingerprint::new(&reply).unwrap();
+        reply.add_attribute(fingerprint);
+        Some(encode(reply))
+    })
+    .await;
+
+    let err = binding_request(&server, &fast_config()).await.unwrap_err();
+    assert!(matches!(err, Error::NoMappedAddress { .. }), "unexpected error {:?}", err);
+}
+
+#[tokio::test]
+async fn test_all_servers_failing_reports_each_failure() {
+    let rejecting = spawn_stun_server(|request, _, _| {
+        let mut reply = response(request, MessageClass::ErrorResponse, request.transaction_id());
+        reply.add_attribute(ErrorCode::new(500, "Server Error".to_string()).unwrap());
+        Some(encode(reply))
+    })
+    .await;
+
+    let closed = closed_port().await;
+
+    let err = perform_stun_request(&[&rejecting, &closed], &no_credentials()).await.unwrap_err();
+    let Error::StunFailed(failures) = err else { panic!("unexpected error {:?}", err) };
+    assert!(failures.iter().any(|e| matches!(e, Error::StunErrorResponse { code: 500, .. })));
+    // 没有应答的服务器同样出现在失败列表中
+    assert!(
+        failures.iter().any(|e| matches!(e, Error::StunTimeout { server } | Error::StunIo { server, .. } if *server == closed)),
+        "unexpected failures {:?}",
+        failures
+    );
+
+    // 服务器列表为空时没有结果
+    assert!(matches!(perform_stun_request(&[], &no_credentials()).await, Err(Error::StunFailed(_))));
+}
+
+#[tokio::test]
+async fn test_servers_agree_on_shared_port() {
+    let first = spawn_stun_server(|request, _, from| Some(reflect(request, from, 0))).await;
+    let second = spawn_stun_server(|request, _, from| Some(reflect(request, from, 0))).await;
+
+    let probe = perform_stun_request(&[&first, &second], &no_credentials()).await.unwrap();
+    assert_eq!(probe.results.len(), 2);
+    assert!(probe.failures.is_empty());
+    // 两个请求从同一个本地端口发出
+    assert_eq!(probe.results[0].mapped_address, probe.results[1].mapped_address);
+    assert_eq!(probe.consensus(), Some(probe.results[0].mapped_address));
+    assert!(!probe.disagrees());
+}
+
+#[tokio::test]
+async fn test_disagreement_is_reported() {
+    let first = spawn_stun_server(|request, _, from| Some(reflect(request, from, 0))).await;
+    let second = spawn_stun_server(|request, _, from| Some(reflect(request, from, 0))).await;
+    let third = spawn_stun_server(|request, _, from| Some(reflect(request, from, 1))).await;
+
+    let probe = perform_stun_request(&[&third, &first, &second], &no_credentials()).await.unwrap();
+    assert!(probe.disagrees());
+    assert_eq!(probe.mapped_addresses().len(), 2);
+    // 多数服务器报告的地址胜出
+    let majority = probe.results.iter().find(|result| result.server == first).unwrap().mapped_address;
+    assert_eq!(probe.consensus(), Some(majority));
+}
+
+#[tokio::test]
+async fn test_failed_server_does_not_block_others() {
+    let silent = spawn_stun_server(|_, _, _| None).await;
+    let working = spawn_stun_server(|request, _, from| Some(reflect(request, from, 0))).await;
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let servers = [
+        (silent.clone(), silent.parse().unwrap()),
+        (working.clone(), working.parse().unwrap()),
+    ];
+    let outcomes = binding_requests(&socket, &servers, &no_credentials(), &fast_config()).await;
+    assert!(matches!(outcomes[0], Err(Error::StunTimeout { ref server }) if *server == silent));
+    let result = outcomes[1].as_ref().unwrap();
+    assert_eq!(result.mapped_address, socket.local_addr().unwrap());
+}
+
+// TCP 服务器：每条连接上先发送一个无关的响应，再回复请求来源地址
+async fn spawn_tcp_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        while let Ok((mut stream, from)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut header = [0; 20];
+                stream.read_exact(&mut header).await.unwrap();
+                let mut bytes = header.to_vec();
+                bytes.resize(20 + u16::from_be_bytes([header[2], header[3]]) as usize, 0);
+                stream.read_exact(&mut bytes[20..]).await.unwrap();
+                let request = MessageDecoder::<Attribute>::new().decode_from_bytes(&bytes).unwrap().unwrap();
+
+                let mut unrelated = response(&request, MessageClass::SuccessResponse, TransactionId::new([7; 12]));
+                unrelated.add_attribute(XorMappedAddress::new("1.2.3.4:5".parse().unwrap()));
+                stream.write_all(&encode(unrelated)).await.unwrap();
+                stream.write_all(&reflect(&request, from, 0)).await.unwrap();
+            });
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn test_tcp_binding_request_reads_framed_messages() {
+    let server = spawn_tcp_server().await;
+    let local = "127.0.0.1:0".parse().unwrap();
+    let result = tcp_binding_request(&server, server.parse().unwrap(), local, None, &fast_config()).await.unwrap();
+    assert_eq!(result.mapped_address.ip().to_string(), "127.0.0.1");
+    assert_ne!(result.mapped_address.port(), 5);
+}
+
+#[tokio::test]
+async fn test_probe_from_listening_port() {
+    // 模拟 Swarm 的监听套接字，监听端口设置了地址和端口复用
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.set_reuseaddr(true).unwrap();
+    #[cfg(unix)]
+    socket.set_reuseport(true).unwrap();
+    socket.bind("0.0.0.0:0".parse().unwrap()).unwrap();
+    let listener = socket.listen(16).unwrap();
+    let local = listener.local_addr().unwrap();
+
+    let tcp_server = spawn_tcp_server().await;
+    let probe = probe_from(&[&tcp_server], local, StunTransport::Tcp, &no_credentials()).await.unwrap();
+    assert_eq!(probe.consensus().unwrap().port(), local.port());
+
+    let udp_server = spawn_stun_server(|request, _, from| Some(reflect(request, from, 0))).await;
+    let probe = probe_from(&[&udp_server], local, StunTransport::Udp, &no_credentials()).await.unwrap();
+    assert_eq!(probe.consensus().unwrap().port(), local.port());
+}
+
+#[test]
+fn test_transaction_timeout() {
+    // RFC 5389 的 Ti：39.5 秒
+    assert_eq!(StunConfig::default().transaction_timeout(), Duration::from_millis(39_500));
+    // 重传次数很大时饱和而不溢出
+    let config = StunConfig { max_requests: 100, ..StunConfig::default() };
+    assert!(config.transaction_timeout() > StunConfig::default().transaction_timeout());
+    let config = StunConfig { timeout: Some(Duration::from_secs(3)), ..config };
+    assert_eq!(config.transaction_timeout(), Duration::from_secs(3));
+}
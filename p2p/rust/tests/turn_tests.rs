@@ -0,0 +1,381 @@
+// TURN 客户端和中继传输层测试：本地的最小 TURN 服务器支持长期凭据认证、分配、权限、通道绑定和数据转发
+use bytecodec::{DecodeExt, EncodeExt};
+use libp2p::futures::StreamExt;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use p2p::error::Error;
+use p2p::node::{NodeBuilder, NodeEvent, NodeEvents};
+use p2p::stun_auth::StunCredentials;
+use p2p::turn::{decode_channel_data, encode_channel_data, Attribute, TurnClient, MAX_PERMISSIONS_PER_REQUEST};
+use p2p::turn_transport::{is_relayed_address, TurnPermissions};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use stun_codec::rfc5389::attributes::{ErrorCode, MessageIntegrity, Nonce, Realm, Username, XorMappedAddress};
+use stun_codec::rfc5766::attributes::{ChannelNumber, Data, Lifetime, XorPeerAddress, XorRelayAddress};
+use stun_codec::rfc5766::methods::{ALLOCATE, CHANNEL_BIND, CREATE_PERMISSION, DATA, REFRESH, SEND};
+use stun_codec::{Message, MessageClass, MessageDecoder, MessageEncoder};
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+mod common;
+use common::fast_config;
+
+const REALM: &str = "p2p.test";
+const USERNAME: &str = "alice";
+const PASSWORD: &str = "secret";
+
+fn credentials(password: &str) -> Option<StunCredentials> {
+    Some(StunCredentials { username: USERNAME.to_string(), password: password.to_string() })
+}
+
+// 服务器状态，测试用来检查客户端的行为
+#[derive(Default)]
+struct TurnState {
+    allocated: bool,
+    permissions: HashSet<IpAddr>,
+    // 单个 CreatePermission 请求中最多的对端数量
+    largest_permission_request: usize,
+    channels: HashMap<u16, SocketAddr>,
+    nonce: u32,
+    // 带凭据的请求数达到该值后更换 NONCE，模拟 NONCE 过期
+    rotate_nonce_after: Option<usize>,
+    authenticated_requests: usize,
+    unauthorized: usize,
+    stale_nonce: usize,
+    channel_data: usize,
+}
+
+struct TurnServer {
+    address: String,
+    relay: SocketAddr,
+    state: Arc<Mutex<TurnState>>,
+}
+
+// 启动只支持一个分配的 TURN 服务器，中继套接字预先绑定
+async fn spawn_turn_server(rotate_nonce_after: Option<usize>) -> TurnServer {
+    let control = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let relay = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let server = TurnServer {
+        address: control.local_addr().unwrap().to_string(),
+        relay: relay.local_addr().unwrap(),
+        state: Arc::new(Mutex::new(TurnState { rotate_nonce_after, ..TurnState::default() })),
+    };
+    let state = server.state.clone();
+    let relay_address = server.relay;
+    tokio::spawn(async move {
+        let mut client = None;
+        let mut control_buffer = [0; 2048];
+        let mut relay_buffer = [0; 2048];
+        loop {
+            tokio::select! {
+                received = control.recv_from(&mut control_buffer) => {
+                    let Ok((len, from)) = received else { return };
+                    client = Some(from);
+                    let replies = handle_control(&state, &control_buffer[..len], from, relay_address);
+                    for (packet, destination) in replies {
+                        match destination {
+                            Destination::Client => { let _ = control.send_to(&packet, from).await; }
+                            Destination::Peer(peer) => { let _ = relay.send_to(&packet, peer).await; }
+                        }
+                    }
+                }
+                received = relay.recv_from(&mut relay_buffer) => {
+                    let Ok((len, peer)) = received else { return };
+                    let Some(client) = client else { continue };
+                    let packet = {
+                        let state = state.lock().unwrap();
+                        if !state.allocated || !state.permissions.contains(&peer.ip()) {
+                            continue;
+                        }
+                        match state.channels.iter().find(|(_, bound)| **bound == peer) {
+                            Some((number, _)) => encode_channel_data(*number, &relay_buffer[..len]),
+                            None => {
+                                let mut indication = Message::<Attribute>::new(MessageClass::Indication, DATA, random_id());
+                                indication.add_attribute(XorPeerAddress::new(peer));
+                                indication.add_attribute(Data::new(relay_buffer[..len].to_vec()).unwrap());
+                                encode(indication)
+                            }
+                        }
+                    };
+                    let _ = control.send_to(&packet, client).await;
+                }
+            }
+        }
+    });
+    server
+}
+
+enum Destination {
+    Client,
+    Peer(SocketAddr),
+}
+
+fn random_id() -> stun_codec::TransactionId {
+    stun_codec::TransactionId::new(rand::random())
+}
+
+fn encode(message: Message<Attribute>) -> Vec<u8> {
+    MessageEncoder::new().encode_into_bytes(message).unwrap()
+}
+
+fn error_response(request: &Message<Attribute>, code: u16, nonce: Option<String>) -> Vec<u8> {
+    let mut reply = Message::<Attribute>::new(MessageClass::ErrorResponse, request.method(), request.transaction_id());
+    reply.add_attribute(ErrorCode::new(code, "error".to_string()).unwrap());
+    if let Some(nonce) = nonce {
+        reply.add_attribute(Realm::new(REALM.to_string()).unwrap());
+        reply.add_attribute(Nonce::new(nonce).unwrap());
+    }
+    encode(reply)
+}
+
+fn handle_control(state: &Mutex<TurnState>, packet: &[u8], from: SocketAddr, relay: SocketAddr) -> Vec<(Vec<u8>, Destination)> {
+    let mut state = state.lock().unwrap();
+    if let Some((number, data)) = decode_channel_data(packet) {
+        state.channel_data += 1;
+        return match state.channels.get(&number) {
+            Some(peer) if state.permissions.contains(&peer.ip()) => vec![(data.to_vec(), Destination::Peer(*peer))],
+            _ => Vec::new(),
+        };
+    }
+    let message = MessageDecoder::<Attribute>::new().decode_from_bytes(packet).unwrap().unwrap();
+    if message.class() == MessageClass::Indication {
+        assert_eq!(message.method(), SEND);
+        let peer = message.get_attribute::<XorPeerAddress>().unwrap().address();
+        let data = message.get_attribute::<Data>().unwrap().data().to_vec();
+        if state.permissions.contains(&peer.ip()) {
+            return vec![(data, Destination::Peer(peer))];
+        }
+        return Vec::new();
+    }
+
+    // 长期凭据认证（RFC 5389 10.2.2）
+    let nonce = format!("nonce-{}", state.nonce);
+    let Some(integrity) = message.get_attribute::<MessageIntegrity>() else {
+        state.unauthorized += 1;
+        return vec![(error_response(&message, 401, Some(nonce)), Destination::Client)];
+    };
+    let username = message.get_attribute::<Username>().unwrap();
+    let realm = message.get_attribute::<Realm>().unwrap();
+    if integrity.check_long_term_credential(username, realm, PASSWORD).is_err() {
+        state.unauthorized += 1;
+        return vec![(error_response(&message, 401, Some(nonce)), Destination::Client)];
+    }
+    if message.get_attribute::<Nonce>().unwrap().value() != nonce {
+        state.stale_nonce += 1;
+        return vec![(error_response(&message, 438, Some(nonce)), Destination::Client)];
+    }
+    state.authenticated_requests += 1;
+    if state.rotate_nonce_after == Some(state.authenticated_requests) {
+        state.nonce += 1;
+    }
+
+    let mut reply = Message::<Attribute>::new(MessageClass::SuccessResponse, message.method(), message.transaction_id());
+    match message.method() {
+        ALLOCATE => {
+            state.allocated = true;
+            reply.add_attribute(XorRelayAddress::new(relay));
+            reply.add_attribute(XorMappedAddress::new(from));
+            reply.add_attribute(Lifetime::new(Duration::from_secs(600)).unwrap());
+        }
+        REFRESH => {
+            let lifetime = message.get_attribute::<Lifetime>().unwrap().lifetime();
+            state.allocated = !lifetime.is_zero();
+            reply.add_attribute(Lifetime::new(lifetime).unwrap());
+        }
+        CREATE_PERMISSION => {
+            let mut peers = 0;
+            for attribute in message.attributes() {
+                if let Attribute::XorPeerAddress(peer) = attribute {
+                    state.permissions.insert(peer.address().ip());
+                    peers += 1;
+                }
+            }
+            state.largest_permission_request = state.largest_permission_request.max(peers);
+        }
+        CHANNEL_BIND => {
+            let number = message.get_attribute::<ChannelNumber>().unwrap().value();
+            let peer = message.get_attribute::<XorPeerAddress>().unwrap().address();
+            state.channels.insert(number, peer);
+            state.permissions.insert(peer.ip());
+        }
+        method => panic!("unexpected method {:?}", method),
+    }
+    // 响应同样带 MESSAGE-INTEGRITY，客户端用自己的凭据验证
+    let integrity = MessageIntegrity::new_long_term_credential(
+        &reply,
+        &Username::new(USERNAME.to_string()).unwrap(),
+        &Realm::new(REALM.to_string()).unwrap(),
+        PASSWORD,
+    )
+    .unwrap();
+    reply.add_attribute(integrity);
+    vec![(encode(reply), Destination::Client)]
+}
+
+#[tokio::test]
+async fn test_allocate_with_long_term_credentials() {
+    let server = spawn_turn_server(None).await;
+    let client = TurnClient::allocate(&server.address, credentials(PASSWORD), fast_config()).await.unwrap();
+
+    let allocation = client.allocation();
+    assert_eq!(allocation.relayed_address, server.relay);
+    assert_eq!(allocation.mapped_address.map(|mapped| mapped.port()), Some(client.local_addr().unwrap().port()));
+    assert_eq!(allocation.lifetime, Duration::from_secs(600));
+    // 第一次请求没有凭据，服务器要求认证后带凭据重发
+    let state = server.state.lock().unwrap();
+    assert_eq!((state.unauthorized, state.authenticated_requests), (1, 1));
+    assert!(state.allocated);
+}
+
+#[tokio::test]
+async fn test_wrong_credentials_are_rejected() {
+    let server = spawn_turn_server(None).await;
+    let err = TurnClient::allocate(&server.address, credentials("wrong"), fast_config()).await.err().unwrap();
+    assert!(matches!(err, Error::StunErrorResponse { code: 401, .. }), "unexpected error {:?}", err);
+    let err = TurnClient::allocate(&server.address, None, fast_config()).await.err().unwrap();
+    assert!(matches!(err, Error::StunErrorResponse { code: 401, .. }), "unexpected error {:?}", err);
+}
+
+#[tokio::test]
+async fn test_permissions_are_sent_in_batches() {
+    let server = spawn_turn_server(None).await;
+    let mut client = TurnClient::allocate(&server.address, credentials(PASSWORD), fast_config()).await.unwrap();
+    let peers: Vec<IpAddr> = (0..100u8).map(|i| IpAddr::from([198, 51, 100, i])).collect();
+    client.create_permission(&peers).await.unwrap();
+
+    let state = server.state.lock().unwrap();
+    assert_eq!(state.permissions.len(), peers.len());
+    assert_eq!(state.largest_permission_request, MAX_PERMISSIONS_PER_REQUEST);
+}
+
+#[test]
+fn test_permissions_follow_the_routing_table() {
+    let permissions = TurnPermissions::default();
+    let (peer, other) = (PeerId::random(), PeerId::random());
+    let ip = |last: u8| IpAddr::from([203, 0, 113, last]);
+
+    assert!(permissions.permit(ip(1)));
+    assert!(!permissions.permit(ip(1)));
+    permissions.permit_peer(peer, HashSet::from([ip(2), ip(3)]));
+    permissions.permit_peer(other, HashSet::from([ip(3)]));
+    assert_eq!(permissions.permitted(), HashSet::from([ip(1), ip(2), ip(3)]));
+
+    // 节点的地址变化时替换它的 IP，离开路由表后移除，另一个节点仍在使用的 IP 保留
+    permissions.permit_peer(peer, HashSet::from([ip(4)]));
+    assert_eq!(permissions.permitted(), HashSet::from([ip(1), ip(3), ip(4)]));
+    permissions.revoke_peer(&peer);
+    permissions.revoke_peer(&other);
+    assert_eq!(permissions.permitted(), HashSet::from([ip(1)]));
+}
+
+#[tokio::test]
+async fn test_stale_nonce_is_renewed() {
+    // 分配成功后 NONCE 过期，下一个请求收到 438 后用新的 NONCE 重发
+    let server = spawn_turn_server(Some(1)).await;
+    let mut client = TurnClient::allocate(&server.address, credentials(PASSWORD), fast_config()).await.unwrap();
+    client.create_permission(&["127.0.0.1".parse().unwrap()]).await.unwrap();
+
+    let state = server.state.lock().unwrap();
+    assert_eq!(state.stale_nonce, 1);
+    assert!(state.permissions.contains(&"127.0.0.1".parse::<IpAddr>().unwrap()));
+}
+
+#[tokio::test]
+async fn test_relays_data_through_permissions_and_channels() {
+    let server = spawn_turn_server(None).await;
+    let mut client = TurnClient::allocate(&server.address, credentials(PASSWORD), fast_config()).await.unwrap();
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let peer_address = peer.local_addr().unwrap();
+    let mut buffer = [0; 1500];
+
+    // 没有权限时服务器丢弃对端的数据
+    peer.send_to(b"dropped", server.relay).await.unwrap();
+    assert!(timeout(Duration::from_millis(300), client.recv_from()).await.is_err());
+
+    // Send 和 Data 指示
+    client.create_permission(&[peer_address.ip()]).await.unwrap();
+    client.send_to(b"hello", peer_address).await.unwrap();
+    let (len, from) = timeout(Duration::from_secs(2), peer.recv_from(&mut buffer)).await.unwrap().unwrap();
+    assert_eq!((&buffer[..len], from), (&b"hello"[..], server.relay));
+    peer.send_to(b"world", server.relay).await.unwrap();
+    let received = timeout(Duration::from_secs(2), client.recv_from()).await.unwrap().unwrap();
+    assert_eq!(received, (b"world".to_vec(), peer_address));
+
+    // 绑定通道后使用 ChannelData
+    assert_eq!(client.channel_bind(peer_address).await.unwrap(), ChannelNumber::MIN);
+    assert_eq!(client.channel_bind(peer_address).await.unwrap(), ChannelNumber::MIN);
+    client.send_to(b"over channel", peer_address).await.unwrap();
+    let (len, _) = timeout(Duration::from_secs(2), peer.recv_from(&mut buffer)).await.unwrap().unwrap();
+    assert_eq!(&buffer[..len], b"over channel");
+    peer.send_to(b"channel reply", server.relay).await.unwrap();
+    let received = timeout(Duration::from_secs(2), client.recv_from()).await.unwrap().unwrap();
+    assert_eq!(received, (b"channel reply".to_vec(), peer_address));
+    assert_eq!(server.state.lock().unwrap().channel_data, 1);
+
+    // 释放后服务器不再转发
+    client.release().await.unwrap();
+    assert!(!server.state.lock().unwrap().allocated);
+}
+
+async fn relayed_listen_addr(events: &mut NodeEvents) -> Multiaddr {
+    loop {
+        match events.next().await {
+            Some(NodeEvent::NewListenAddr { address }) if is_relayed_address(&address) => return address,
+            Some(NodeEvent::RelayFailed { error }) => panic!("relay failed: {}", error),
+            Some(_) => continue,
+            None => panic!("node stopped"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_nodes_connect_through_relay_when_direct_dial_fails() {
+    let server = spawn_turn_server(None).await;
+    let listener = NodeBuilder::new().turn_relay(&server.address, credentials(PASSWORD)).build().unwrap();
+    let listener_peer_id = listener.local_peer_id();
+    let (listener_handle, mut listener_events) = listener.spawn();
+    let relayed = timeout(Duration::from_secs(10), relayed_listen_addr(&mut listener_events)).await.unwrap();
+    assert_eq!(relayed, Multiaddr::from(server.relay.ip()).with(Protocol::Udp(server.relay.port())));
+    assert!(listener_handle.permit_relay_peer("127.0.0.1".parse().unwrap()).await.unwrap());
+
+    // 直接地址无法连接，失败后改用中继地址
+    let dialer = NodeBuilder::new().ping_interval(Duration::from_millis(200)).build().unwrap();
+    let (dialer_handle, mut dialer_events) = dialer.spawn();
+    let addresses = vec![relayed.clone(), "/ip4/127.0.0.1/tcp/1".parse().unwrap()];
+    dialer_handle.dial_peer(listener_peer_id, addresses).await.unwrap();
+    timeout(Duration::from_secs(20), async {
+        let mut direct_failed = false;
+        let mut connected = false;
+        loop {
+            match dialer_events.next().await {
+                Some(NodeEvent::OutgoingConnectionError { peer_id: Some(peer_id), .. }) if peer_id == listener_peer_id => {
+                    direct_failed = true;
+                }
+                Some(NodeEvent::ConnectionEstablished { peer_id, endpoint }) if peer_id == listener_peer_id => {
+                    assert!(direct_failed, "relayed address dialed before the direct address failed");
+                    assert_eq!(endpoint.get_remote_address(), &relayed.clone().with(Protocol::P2p(listener_peer_id)));
+                    connected = true;
+                }
+                // 中继连接上的 yamux 流可以正常使用
+                Some(NodeEvent::Ping { peer, result: Ok(_) }) if peer == listener_peer_id && connected => return,
+                Some(_) => continue,
+                None => panic!("node stopped"),
+            }
+        }
+    })
+    .await
+    .expect("no ping over the relayed connection");
+
+    dialer_handle.shutdown().await.unwrap();
+    listener_handle.shutdown().await.unwrap();
+    // 停止监听时释放分配
+    timeout(Duration::from_secs(5), async {
+        while server.state.lock().unwrap().allocated {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("allocation was not released");
+}